@@ -0,0 +1,116 @@
+use crate::dsp;
+
+const MEL_FILTERS: usize = 26;
+const MFCC_COEFFICIENTS: usize = 13;
+// frames quieter than this are left out, so silence doesn't drag the averages down
+const SILENCE: f32 = 1e-3;
+
+/// 13 MFCC means, spectral centroid in kHz and tempo in BPM
+pub const FEATURE_LEN: usize = MFCC_COEFFICIENTS + 2;
+pub const CENTROID: usize = MFCC_COEFFICIENTS;
+
+fn hz_to_mel(hz: f32) -> f32 {
+    2595.0 * (1.0 + hz / 700.0).log10()
+}
+
+fn mel_to_hz(mel: f32) -> f32 {
+    700.0 * (10.0_f32.powf(mel / 2595.0) - 1.0)
+}
+
+/// Triangular filters evenly spaced on the mel scale, as (bin, weight) pairs per filter
+fn mel_filterbank(frame_len: usize, sample_rate: u32) -> Vec<Vec<(usize, f32)>> {
+    let max_mel = hz_to_mel(sample_rate as f32 / 2.0);
+    let edges: Vec<f32> = (0..MEL_FILTERS + 2)
+        .map(|i| mel_to_hz(max_mel * i as f32 / (MEL_FILTERS + 1) as f32))
+        .collect();
+
+    edges
+        .windows(3)
+        .map(|edge| {
+            (0..frame_len / 2)
+                .filter_map(|bin| {
+                    let freq = dsp::bin_frequency(bin, frame_len, sample_rate);
+                    let weight = if freq <= edge[0] || freq >= edge[2] {
+                        0.0
+                    } else if freq <= edge[1] {
+                        (freq - edge[0]) / (edge[1] - edge[0])
+                    } else {
+                        (edge[2] - freq) / (edge[2] - edge[1])
+                    };
+                    (weight > 0.0).then_some((bin, weight))
+                })
+                .collect()
+        })
+        .collect()
+}
+
+fn mfcc(spectrum: &[f32], filterbank: &[Vec<(usize, f32)>]) -> [f32; MFCC_COEFFICIENTS] {
+    let log_energies: Vec<f32> = filterbank
+        .iter()
+        .map(|filter| {
+            let energy: f32 = filter
+                .iter()
+                .map(|(bin, weight)| weight * spectrum[*bin] * spectrum[*bin])
+                .sum();
+            (energy + 1e-10).ln()
+        })
+        .collect();
+
+    // type II discrete cosine transform of the log energies
+    std::array::from_fn(|k| {
+        log_energies
+            .iter()
+            .enumerate()
+            .map(|(n, energy)| {
+                energy
+                    * (std::f32::consts::PI * k as f32 * (n as f32 + 0.5) / MEL_FILTERS as f32)
+                        .cos()
+            })
+            .sum()
+    })
+}
+
+fn spectral_centroid(spectrum: &[f32], frame_len: usize, sample_rate: u32) -> f32 {
+    let total: f32 = spectrum.iter().sum();
+    let weighted: f32 = spectrum
+        .iter()
+        .enumerate()
+        .map(|(bin, magnitude)| dsp::bin_frequency(bin, frame_len, sample_rate) * magnitude)
+        .sum();
+
+    weighted / total.max(f32::EPSILON)
+}
+
+/// Summarises a track's timbre and tempo as a fixed length vector
+pub fn extract(
+    spectra: &[Vec<f32>],
+    frame_len: usize,
+    sample_rate: u32,
+    bpm: Option<f32>,
+) -> Option<Vec<f32>> {
+    let filterbank = mel_filterbank(frame_len, sample_rate);
+    let audible: Vec<&Vec<f32>> = spectra
+        .iter()
+        .filter(|spectrum| spectrum.iter().cloned().fold(0.0, f32::max) > SILENCE)
+        .collect();
+
+    if audible.is_empty() {
+        return None;
+    }
+
+    let mut features = vec![0.0; FEATURE_LEN];
+    for spectrum in &audible {
+        for (i, coefficient) in mfcc(spectrum, &filterbank).iter().enumerate() {
+            features[i] += coefficient;
+        }
+        features[CENTROID] += spectral_centroid(spectrum, frame_len, sample_rate) / 1000.0;
+    }
+    features
+        .iter_mut()
+        .for_each(|feature| *feature /= audible.len() as f32);
+
+    // without a clear beat, assume the most common tempo rather than an outlier
+    features[MFCC_COEFFICIENTS + 1] = bpm.unwrap_or(120.0);
+
+    Some(features)
+}
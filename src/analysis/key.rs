@@ -0,0 +1,198 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::dsp;
+
+// Krumhansl-Kessler key profiles, starting from the tonic
+static MAJOR_PROFILE: [f32; 12] = [
+    6.35, 2.23, 3.48, 2.33, 4.38, 4.09, 2.52, 5.19, 2.39, 3.66, 2.29, 2.88,
+];
+static MINOR_PROFILE: [f32; 12] = [
+    6.33, 2.68, 3.52, 5.38, 2.60, 3.53, 2.54, 4.75, 3.98, 2.69, 3.34, 3.17,
+];
+static PITCH_CLASSES: [&str; 12] = [
+    "C", "C♯", "D", "E♭", "E", "F", "F♯", "G", "A♭", "A", "B♭", "B",
+];
+
+const MIN_FREQ: f32 = 55.0;
+const MAX_FREQ: f32 = 5000.0;
+
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Key {
+    /// Pitch class of the tonic, 0 being C
+    pub tonic: usize,
+    pub minor: bool,
+}
+
+impl fmt::Display for Key {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {}",
+            PITCH_CLASSES[self.tonic],
+            if self.minor { "minor" } else { "major" }
+        )
+    }
+}
+
+/// Energy of each of the 12 pitch classes over all spectra, normalised to sum to 1
+pub fn chroma(spectra: &[Vec<f32>], frame_len: usize, sample_rate: u32) -> [f32; 12] {
+    let mut chroma = [0.0; 12];
+
+    for spectrum in spectra {
+        for (bin, magnitude) in spectrum.iter().enumerate() {
+            let freq = dsp::bin_frequency(bin, frame_len, sample_rate);
+            if !(MIN_FREQ..MAX_FREQ).contains(&freq) {
+                continue;
+            }
+            // MIDI note 69 is A4 at 440Hz, and MIDI note 0 is a C
+            let note = (12.0 * (freq / 440.0).log2()).round() as i32 + 69;
+            chroma[note.rem_euclid(12) as usize] += magnitude * magnitude;
+        }
+    }
+
+    let total: f32 = chroma.iter().sum();
+    if total > 0.0 {
+        chroma.iter_mut().for_each(|energy| *energy /= total);
+    }
+    chroma
+}
+
+fn correlation(a: &[f32; 12], b: &[f32; 12]) -> f32 {
+    let mean_a = a.iter().sum::<f32>() / 12.0;
+    let mean_b = b.iter().sum::<f32>() / 12.0;
+
+    let (mut covariance, mut variance_a, mut variance_b) = (0.0, 0.0, 0.0);
+    for i in 0..12 {
+        covariance += (a[i] - mean_a) * (b[i] - mean_b);
+        variance_a += (a[i] - mean_a).powi(2);
+        variance_b += (b[i] - mean_b).powi(2);
+    }
+
+    covariance / (variance_a * variance_b).sqrt().max(f32::EPSILON)
+}
+
+/// Picks the key whose profile best correlates with the chromagram of the spectra
+pub fn estimate(spectra: &[Vec<f32>], frame_len: usize, sample_rate: u32) -> Option<Key> {
+    let chroma = chroma(spectra, frame_len, sample_rate);
+    if chroma.iter().all(|energy| *energy == 0.0) {
+        return None;
+    }
+
+    let candidates = (0..12).flat_map(|tonic| [false, true].map(|minor| Key { tonic, minor }));
+
+    candidates.max_by(|a, b| score(&chroma, a).total_cmp(&score(&chroma, b)))
+}
+
+fn score(chroma: &[f32; 12], key: &Key) -> f32 {
+    let profile = if key.minor {
+        &MINOR_PROFILE
+    } else {
+        &MAJOR_PROFILE
+    };
+
+    // rotate the profile so its tonic lines up with the candidate's
+    let rotated: [f32; 12] = std::array::from_fn(|pitch| profile[(pitch + 12 - key.tonic) % 12]);
+    correlation(chroma, &rotated)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::PI;
+
+    use super::*;
+    use crate::{
+        analysis::{FRAME_LEN, HOP},
+        dsp,
+    };
+
+    const SAMPLE_RATE: u32 = 22_050;
+
+    /// A second of the notes, given as MIDI numbers, played together
+    fn chord(notes: &[i32]) -> Vec<f32> {
+        (0..SAMPLE_RATE as usize)
+            .map(|i| {
+                let t = i as f32 / SAMPLE_RATE as f32;
+                notes
+                    .iter()
+                    .map(|note| {
+                        let freq = 440.0 * 2.0_f32.powf((note - 69) as f32 / 12.0);
+                        (2.0 * PI * freq * t).sin() / notes.len() as f32
+                    })
+                    .sum()
+            })
+            .collect()
+    }
+
+    fn estimate_key(chords: &[&[i32]]) -> Option<Key> {
+        let samples: Vec<f32> = chords.iter().flat_map(|notes| chord(notes)).collect();
+        estimate(&dsp::stft(&samples, FRAME_LEN, HOP), FRAME_LEN, SAMPLE_RATE)
+    }
+
+    #[test]
+    fn chroma_of_a_chord_is_its_notes() {
+        let samples = chord(&[60, 64, 67]);
+        let chroma = chroma(&dsp::stft(&samples, FRAME_LEN, HOP), FRAME_LEN, SAMPLE_RATE);
+
+        let mut loudest: Vec<usize> = (0..12).collect();
+        loudest.sort_by(|a, b| chroma[*b].total_cmp(&chroma[*a]));
+        loudest.truncate(3);
+        loudest.sort();
+        assert_eq!(loudest, vec![0, 4, 7]);
+        assert!((chroma.iter().sum::<f32>() - 1.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn cadences_give_their_key() {
+        // I IV V I in C major
+        let c_major: [&[i32]; 4] = [&[60, 64, 67], &[65, 69, 72], &[67, 71, 74], &[60, 64, 67]];
+        assert!(
+            estimate_key(&c_major)
+                == Some(Key {
+                    tonic: 0,
+                    minor: false
+                })
+        );
+
+        // i iv V i in A minor
+        let a_minor: [&[i32]; 4] = [&[57, 60, 64], &[62, 65, 69], &[64, 68, 71], &[57, 60, 64]];
+        assert!(
+            estimate_key(&a_minor)
+                == Some(Key {
+                    tonic: 9,
+                    minor: true
+                })
+        );
+    }
+
+    #[test]
+    fn no_key_in_silence() {
+        assert!(estimate(
+            &dsp::stft(&[0.0; 8192], FRAME_LEN, HOP),
+            FRAME_LEN,
+            SAMPLE_RATE
+        )
+        .is_none());
+    }
+
+    #[test]
+    fn keys_are_named_by_tonic_and_mode() {
+        assert_eq!(
+            Key {
+                tonic: 3,
+                minor: false
+            }
+            .to_string(),
+            "E♭ major"
+        );
+        assert_eq!(
+            Key {
+                tonic: 9,
+                minor: true
+            }
+            .to_string(),
+            "A minor"
+        );
+    }
+}
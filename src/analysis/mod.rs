@@ -0,0 +1,87 @@
+mod features;
+mod key;
+mod tempo;
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use rspotify::{model::FullTrack, prelude::Id};
+use serde::{Deserialize, Serialize};
+
+use crate::{audio, dsp, storage};
+
+pub use features::{CENTROID, FEATURE_LEN};
+pub use key::Key;
+
+const FRAME_LEN: usize = 2048;
+const HOP: usize = 512;
+static CACHE_FILE: &str = "analysis.json";
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct TrackAnalysis {
+    pub bpm: Option<f32>,
+    pub key: Option<Key>,
+    /// See `features::extract`, empty if the preview was silent
+    pub features: Vec<f32>,
+}
+
+/// Analyses by track ID, shared with the thread that analyses playlist tracks in the background
+pub type AnalysisCache = Arc<Mutex<HashMap<String, TrackAnalysis>>>;
+
+pub fn load_cache() -> AnalysisCache {
+    Arc::new(Mutex::new(storage::load_or_set_aside(CACHE_FILE)))
+}
+
+pub fn save_cache(cache: &AnalysisCache) {
+    // the lock is held while writing, so the background analysis and the main thread take turns
+    if let Ok(cache) = cache.lock() {
+        if let Err(e) = storage::save(CACHE_FILE, &*cache) {
+            log::error!("Failed to save analysis cache: {}", e);
+        }
+    }
+}
+
+pub fn cached(track: &FullTrack, cache: &AnalysisCache) -> Option<TrackAnalysis> {
+    let track_id = track.id.as_ref()?.id();
+    cache.lock().ok()?.get(track_id).cloned()
+}
+
+/// Estimates tempo, key and similarity features from a track's preview, reusing earlier results
+/// for the same track ID
+pub fn analyse(
+    track: &FullTrack,
+    preview: Option<&[u8]>,
+    cache: &AnalysisCache,
+) -> Option<TrackAnalysis> {
+    let track_id = track.id.as_ref()?.id().to_string();
+
+    // Check if the track was already analysed
+    if let Some(analysis) = cached(track, cache) {
+        return Some(analysis);
+    }
+
+    let (samples, sample_rate) = audio::decode_mono(preview?)?;
+    let spectra = dsp::stft(&samples, FRAME_LEN, HOP);
+
+    let bpm = tempo::estimate(&spectra, sample_rate as f32 / HOP as f32);
+    let analysis = TrackAnalysis {
+        bpm,
+        key: key::estimate(&spectra, FRAME_LEN, sample_rate),
+        features: features::extract(&spectra, FRAME_LEN, sample_rate, bpm).unwrap_or_default(),
+    };
+    log::info!(
+        "Analysed track {}: {:?} BPM, key {:?}",
+        track_id,
+        analysis.bpm,
+        analysis.key.map(|key| key.to_string())
+    );
+
+    // Update cache
+    if let Ok(mut cache) = cache.lock() {
+        cache.insert(track_id, analysis.clone());
+    }
+
+    Some(analysis)
+}
@@ -0,0 +1,132 @@
+const MIN_BPM: f32 = 60.0;
+const MAX_BPM: f32 = 200.0;
+// most popular music sits around this tempo, used to choose between double and half time
+const PRIOR_BPM: f32 = 120.0;
+
+/// Spectral flux of log-compressed spectra, which peaks whenever a note or beat starts
+fn onset_strength(spectra: &[Vec<f32>]) -> Vec<f32> {
+    let compressed: Vec<Vec<f32>> = spectra
+        .iter()
+        .map(|spectrum| spectrum.iter().map(|m| (1.0 + 100.0 * m).ln()).collect())
+        .collect();
+
+    compressed
+        .windows(2)
+        .map(|pair| {
+            pair[1]
+                .iter()
+                .zip(pair[0].iter())
+                .map(|(now, before)| (now - before).max(0.0))
+                .sum()
+        })
+        .collect()
+}
+
+fn autocorrelation(signal: &[f32], lag: usize) -> f32 {
+    signal
+        .iter()
+        .zip(signal[lag..].iter())
+        .map(|(a, b)| a * b)
+        .sum::<f32>()
+        / (signal.len() - lag) as f32
+}
+
+/// Estimates beats per minute from spectra taken `frame_rate` times per second
+pub fn estimate(spectra: &[Vec<f32>], frame_rate: f32) -> Option<f32> {
+    let onsets = onset_strength(spectra);
+    let min_lag = (60.0 * frame_rate / MAX_BPM).floor() as usize;
+    let max_lag = (60.0 * frame_rate / MIN_BPM).ceil() as usize;
+
+    if min_lag < 2 || onsets.len() < 2 * max_lag {
+        return None;
+    }
+
+    // centre the envelope so autocorrelation measures periodicity rather than loudness
+    let mean = onsets.iter().sum::<f32>() / onsets.len() as f32;
+    let envelope: Vec<f32> = onsets.iter().map(|onset| onset - mean).collect();
+
+    let weighted = |lag: usize| {
+        let bpm = 60.0 * frame_rate / lag as f32;
+        let prior = (-0.5 * (bpm / PRIOR_BPM).log2().powi(2)).exp();
+        autocorrelation(&envelope, lag) * prior
+    };
+
+    let best_lag = (min_lag..=max_lag).max_by(|a, b| weighted(*a).total_cmp(&weighted(*b)))?;
+    if autocorrelation(&envelope, best_lag) <= 0.0 {
+        return None;
+    }
+
+    // fit a parabola through the neighbouring lags for a finer estimate than whole frames
+    let (before, at, after) = (
+        autocorrelation(&envelope, best_lag - 1),
+        autocorrelation(&envelope, best_lag),
+        autocorrelation(&envelope, best_lag + 1),
+    );
+    let curvature = before - 2.0 * at + after;
+    let offset = if curvature < 0.0 {
+        (0.5 * (before - after) / curvature).clamp(-0.5, 0.5)
+    } else {
+        0.0
+    };
+
+    Some(60.0 * frame_rate / (best_lag as f32 + offset))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        analysis::{FRAME_LEN, HOP},
+        dsp,
+    };
+
+    const SAMPLE_RATE: u32 = 22_050;
+
+    /// Ten seconds of short bursts of a decaying tone at the tempo
+    fn click_train(bpm: f32) -> Vec<f32> {
+        let period = (60.0 * SAMPLE_RATE as f32 / bpm) as usize;
+        let click_len = SAMPLE_RATE as usize / 50;
+        (0..10 * SAMPLE_RATE as usize)
+            .map(|i| {
+                let since_click = i % period;
+                if since_click < click_len {
+                    let t = since_click as f32 / SAMPLE_RATE as f32;
+                    (2.0 * std::f32::consts::PI * 1000.0 * t).sin() * (-t * 200.0).exp()
+                } else {
+                    0.0
+                }
+            })
+            .collect()
+    }
+
+    fn estimate_bpm(samples: &[f32]) -> Option<f32> {
+        let spectra = dsp::stft(samples, FRAME_LEN, HOP);
+        estimate(&spectra, SAMPLE_RATE as f32 / HOP as f32)
+    }
+
+    #[test]
+    fn click_trains_give_their_tempo() {
+        for bpm in [90.0, 120.0, 140.0] {
+            let estimated = estimate_bpm(&click_train(bpm)).unwrap();
+            assert!(
+                (estimated - bpm).abs() < 2.0,
+                "{} BPM estimated as {}",
+                bpm,
+                estimated
+            );
+        }
+    }
+
+    #[test]
+    fn no_tempo_without_beats() {
+        assert_eq!(estimate_bpm(&vec![0.0; 10 * SAMPLE_RATE as usize]), None);
+    }
+
+    #[test]
+    fn no_tempo_from_too_little_audio() {
+        assert_eq!(
+            estimate_bpm(&click_train(120.0)[..SAMPLE_RATE as usize]),
+            None
+        );
+    }
+}
@@ -1,11 +1,107 @@
-use rodio::{Decoder, OutputStream, Sink};
+use rodio::{Decoder, OutputStream, Sink, Source};
 use rspotify::model::FullTrack;
-use std::io::{Cursor, Read};
+use std::{
+    collections::VecDeque,
+    io::{Cursor, Read},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+// enough samples for a spectrum frame and a screen-wide waveform
+const SAMPLE_WINDOW_LEN: usize = 4096;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Visualiser {
+    Off,
+    Waveform,
+    Spectrum,
+}
+
+impl Visualiser {
+    pub fn next(self) -> Visualiser {
+        match self {
+            Visualiser::Off => Visualiser::Waveform,
+            Visualiser::Waveform => Visualiser::Spectrum,
+            Visualiser::Spectrum => Visualiser::Off,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Visualiser::Off => "off",
+            Visualiser::Waveform => "waveform",
+            Visualiser::Spectrum => "spectrum",
+        }
+    }
+}
+
+/// The most recent mono samples fed to the sink, shared with whoever wants to visualise them
+#[derive(Default)]
+pub struct SampleWindow {
+    pub samples: VecDeque<f32>,
+    pub sample_rate: u32,
+}
+
+pub type Samples = Arc<Mutex<SampleWindow>>;
+
+/// Source adapter that copies the first channel of every frame it yields into a `SampleWindow`
+struct Tap<S> {
+    source: S,
+    window: Samples,
+    channel: u16,
+}
+
+impl<S> Iterator for Tap<S>
+where
+    S: Source<Item = i16>,
+{
+    type Item = i16;
+
+    fn next(&mut self) -> Option<i16> {
+        let sample = self.source.next()?;
+
+        if self.channel == 0 {
+            if let Ok(mut window) = self.window.lock() {
+                if window.samples.len() == SAMPLE_WINDOW_LEN {
+                    window.samples.pop_front();
+                }
+                window.samples.push_back(sample as f32 / i16::MAX as f32);
+                window.sample_rate = self.source.sample_rate();
+            }
+        }
+        self.channel = (self.channel + 1) % self.source.channels().max(1);
+
+        Some(sample)
+    }
+}
+
+impl<S> Source for Tap<S>
+where
+    S: Source<Item = i16>,
+{
+    fn current_frame_len(&self) -> Option<usize> {
+        self.source.current_frame_len()
+    }
+
+    fn channels(&self) -> u16 {
+        self.source.channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.source.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.source.total_duration()
+    }
+}
 
 pub struct AudioPlayer {
     _stream: OutputStream,
     sink: Sink,
     volume: f32,
+    samples: Samples,
+    visualiser: Visualiser,
 }
 
 impl AudioPlayer {
@@ -18,20 +114,25 @@ impl AudioPlayer {
             _stream: stream,
             volume: sink.volume(),
             sink,
+            samples: Samples::default(),
+            visualiser: Visualiser::Off,
         })
     }
 
     pub fn stop(&self) {
         self.sink.stop();
+        if let Ok(mut window) = self.samples.lock() {
+            window.samples.clear();
+        }
     }
 
     pub fn volume_up(&mut self) {
-        self.volume = self.volume + 0.1;
+        self.volume += 0.1;
         self.sink.set_volume(self.sink.volume() + 0.1);
     }
 
     pub fn volume_down(&mut self) {
-        self.volume = self.volume - 0.1;
+        self.volume -= 0.1;
         self.sink.set_volume(self.sink.volume() - 0.1);
     }
 
@@ -39,21 +140,59 @@ impl AudioPlayer {
         self.sink.volume()
     }
 
-    pub fn play_track_preview(&mut self, track: &FullTrack) -> Option<&Sink> {
-        // make request for audio file, saving it in buffer
-        let mut buffer: Vec<u8> = Vec::new();
-        _ = ureq::get(&track.preview_url.clone()?)
-            .call()
-            .ok()?
-            .into_reader()
-            .read_to_end(&mut buffer);
+    pub fn cycle_visualiser(&mut self) {
+        self.visualiser = self.visualiser.next();
+    }
+
+    pub fn visualiser(&self) -> Visualiser {
+        self.visualiser
+    }
+
+    pub fn samples(&self) -> Samples {
+        self.samples.clone()
+    }
 
+    pub fn play_preview(&mut self, preview: Vec<u8>) -> Option<&Sink> {
         // Decoder requires its source to implement both Read and Seek, add them to the bytes via Cursor
-        let source = Decoder::new_looped(Cursor::new(buffer)).ok()?;
+        let source = Decoder::new_looped(Cursor::new(preview)).ok()?;
 
         // we play the sound using a sink instead of play_raw to be able to later stop it
-        self.sink.append(source);
+        self.sink.append(Tap {
+            source,
+            window: self.samples.clone(),
+            channel: 0,
+        });
 
         Some(&self.sink)
     }
 }
+
+pub fn download_preview(track: &FullTrack) -> Option<Vec<u8>> {
+    // make request for audio file, saving it in buffer
+    let mut buffer: Vec<u8> = Vec::new();
+    ureq::get(&track.preview_url.clone()?)
+        .call()
+        .ok()?
+        .into_reader()
+        .read_to_end(&mut buffer)
+        .ok()?;
+
+    Some(buffer)
+}
+
+/// Decodes a whole preview into mono samples in [-1, 1], along with their sample rate
+pub fn decode_mono(preview: &[u8]) -> Option<(Vec<f32>, u32)> {
+    let decoder = Decoder::new(Cursor::new(preview.to_vec())).ok()?;
+    let channels = decoder.channels().max(1) as usize;
+    let sample_rate = decoder.sample_rate();
+
+    let interleaved: Vec<i16> = decoder.collect();
+    let mono = interleaved
+        .chunks(channels)
+        .map(|frame| {
+            frame.iter().map(|s| *s as f32).sum::<f32>() / (channels as f32 * i16::MAX as f32)
+        })
+        .collect();
+
+    Some((mono, sample_rate))
+}
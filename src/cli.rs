@@ -0,0 +1,113 @@
+use std::path::PathBuf;
+
+use clap::{Parser, Subcommand};
+
+use crate::{export::Format, queue::Order};
+
+/// Sort the tracks of a Spotify playlist into your other playlists
+#[derive(Parser)]
+#[command(version, about)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    /// Name or ID of the playlist to sort, instead of choosing it when starting
+    #[arg(long, global = true)]
+    pub source: Option<String>,
+
+    /// Only report what the rules in .sortify/rules.json would do, without changing anything
+    #[arg(long)]
+    pub dry_run: bool,
+
+    /// Sort all the tracks at once in $EDITOR, instead of one at a time
+    #[arg(long)]
+    pub edit: bool,
+
+    /// Write a summary of the session to this file, as JSON if it ends in .json and as Markdown
+    /// otherwise
+    #[arg(long, value_name = "FILE")]
+    pub summary: Option<PathBuf>,
+
+    /// Order to go through the source playlist in
+    #[arg(long, value_enum)]
+    pub order: Option<Order>,
+
+    /// Only sort tracks added to the source playlist more than this many days ago
+    #[arg(long, value_name = "DAYS")]
+    pub older_than: Option<i64>,
+
+    /// Only sort tracks added to the source playlist by this user ID
+    #[arg(long, value_name = "USER")]
+    pub added_by: Option<String>,
+
+    /// Leave out tracks already in this playlist, by name or ID; can be given more than once
+    #[arg(long, value_name = "PLAYLIST")]
+    pub not_in: Vec<String>,
+
+    /// Leave out tracks already in any of your other playlists
+    #[arg(long, conflicts_with = "not_in")]
+    pub not_in_any: bool,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Apply decisions prepared in a CSV or JSON file, with a row per track holding its URI or
+    /// ID, the names or IDs of playlists to add it to, and optionally an action
+    Apply { file: PathBuf },
+
+    /// Save all your playlists and liked songs to a file, to restore them from later
+    Snapshot {
+        /// File to write to, in .sortify/snapshots by default
+        output: Option<PathBuf>,
+    },
+
+    /// Bring back the tracks and track order of your playlists and liked songs from a snapshot
+    Restore { file: PathBuf },
+
+    /// Show what changed between two snapshots, or between a snapshot and your library now
+    Diff {
+        a: PathBuf,
+
+        #[arg(required_unless_present = "live")]
+        b: Option<PathBuf>,
+
+        /// Compare with your library as it is now
+        #[arg(long, conflicts_with = "b")]
+        live: bool,
+
+        /// Print the differences as JSON
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Find the tracks of an M3U or CSV file on Spotify, and add them to the source playlist to
+    /// sort them
+    Import {
+        file: PathBuf,
+
+        /// Create a playlist with this name for the tracks instead
+        #[arg(long, conflicts_with = "source")]
+        create: Option<String>,
+    },
+
+    /// Search for tracks, albums and artists, and add what you choose to your buffer playlist,
+    /// set with "buffer" in .sortify/config.json or --source
+    Search {
+        /// Read one "artist - title" per line from stdin and add the tracks found, without asking
+        #[arg(long)]
+        bulk: bool,
+    },
+
+    /// Write playlists to files, one per playlist
+    Export {
+        /// Names or IDs of the playlists to export, all of them if none are given
+        playlists: Vec<String>,
+
+        #[arg(long, value_enum, default_value_t = Format::M3u8)]
+        format: Format,
+
+        /// Directory to write the files to
+        #[arg(long, default_value = ".")]
+        output: PathBuf,
+    },
+}
@@ -0,0 +1,69 @@
+use std::collections::HashMap;
+
+use serde::Deserialize;
+
+use crate::{hooks::HookConfig, queue::QueueConfig, storage, ui};
+
+static CONFIG_FILE: &str = "config.json";
+
+/// User settings, read once at startup from the data directory
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub hooks: Vec<HookConfig>,
+    /// How many of the snapshots taken before each session to keep, none are taken if 0
+    pub auto_snapshots: usize,
+    /// Name or ID of the playlist search results are added to, to sort them later
+    pub buffer: Option<String>,
+    /// How playlists are shown on the track screen, keyed by ID so renaming them doesn't matter
+    pub playlists: HashMap<String, PlaylistConfig>,
+    /// Groups in the order they're shown, for those that need an order or start collapsed
+    pub groups: Vec<GroupConfig>,
+    /// How many days a skipped track stays away for, if it should at all
+    pub snooze_days: Option<u32>,
+    /// Which tracks of the source playlist to sort, and in what order, unless given when starting
+    pub queue: QueueConfig,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct PlaylistConfig {
+    /// Letter that checks the playlist, along with its number
+    pub hotkey: Option<char>,
+    /// Favourites are shown first
+    pub favourite: bool,
+    pub group: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct GroupConfig {
+    pub name: String,
+    #[serde(default)]
+    pub collapsed: bool,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            hooks: Vec::new(),
+            auto_snapshots: 10,
+            buffer: None,
+            playlists: HashMap::new(),
+            groups: Vec::new(),
+            snooze_days: None,
+            queue: QueueConfig::default(),
+        }
+    }
+}
+
+impl Config {
+    pub fn load() -> Config {
+        // the file is the user's own, so it's left as it is for them to fix
+        storage::load(CONFIG_FILE).unwrap_or_else(|e| {
+            log::error!("{}", e);
+            ui::config_load_warning(&e);
+            Config::default()
+        })
+    }
+}
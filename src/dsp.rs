@@ -0,0 +1,138 @@
+use std::f32::consts::PI;
+
+/// In-place radix-2 Cooley-Tukey FFT. Both slices must have the same power of two length.
+pub fn fft(re: &mut [f32], im: &mut [f32]) {
+    let n = re.len();
+    debug_assert!(n.is_power_of_two() && im.len() == n);
+
+    // reorder input by bit-reversed index
+    let mut j = 0;
+    for i in 1..n {
+        let mut bit = n >> 1;
+        while j & bit != 0 {
+            j ^= bit;
+            bit >>= 1;
+        }
+        j |= bit;
+        if i < j {
+            re.swap(i, j);
+            im.swap(i, j);
+        }
+    }
+
+    // combine butterflies of doubling size
+    let mut len = 2;
+    while len <= n {
+        let angle = -2.0 * PI / len as f32;
+        for start in (0..n).step_by(len) {
+            for k in 0..len / 2 {
+                let (w_im, w_re) = (angle * k as f32).sin_cos();
+                let (a, b) = (start + k, start + k + len / 2);
+                let t_re = re[b] * w_re - im[b] * w_im;
+                let t_im = re[b] * w_im + im[b] * w_re;
+                re[b] = re[a] - t_re;
+                im[b] = im[a] - t_im;
+                re[a] += t_re;
+                im[a] += t_im;
+            }
+        }
+        len <<= 1;
+    }
+}
+
+/// Hann window coefficient for position `i` of a window of size `n`
+pub fn hann(i: usize, n: usize) -> f32 {
+    0.5 - 0.5 * (2.0 * PI * i as f32 / (n - 1) as f32).cos()
+}
+
+/// Magnitudes of the positive frequencies of a Hann-windowed frame, whose length must be a power of two
+pub fn magnitude_spectrum(frame: &[f32]) -> Vec<f32> {
+    let n = frame.len();
+    let mut re: Vec<f32> = frame
+        .iter()
+        .enumerate()
+        .map(|(i, sample)| sample * hann(i, n))
+        .collect();
+    let mut im = vec![0.0; n];
+
+    fft(&mut re, &mut im);
+
+    re.iter()
+        .zip(im.iter())
+        .take(n / 2)
+        .map(|(re, im)| (re * re + im * im).sqrt())
+        .collect()
+}
+
+/// Frequency in Hz represented by `bin` of a spectrum computed from `frame_len` samples
+pub fn bin_frequency(bin: usize, frame_len: usize, sample_rate: u32) -> f32 {
+    bin as f32 * sample_rate as f32 / frame_len as f32
+}
+
+/// Magnitude spectra of overlapping frames of `frame_len` samples, `hop` samples apart
+pub fn stft(samples: &[f32], frame_len: usize, hop: usize) -> Vec<Vec<f32>> {
+    if samples.len() < frame_len {
+        return Vec::new();
+    }
+
+    (0..=(samples.len() - frame_len) / hop)
+        .map(|frame| magnitude_spectrum(&samples[frame * hop..frame * hop + frame_len]))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sine(freq: f32, sample_rate: u32, len: usize) -> Vec<f32> {
+        (0..len)
+            .map(|i| (2.0 * PI * freq * i as f32 / sample_rate as f32).sin())
+            .collect()
+    }
+
+    fn loudest_bin(spectrum: &[f32]) -> usize {
+        (0..spectrum.len())
+            .max_by(|a, b| spectrum[*a].total_cmp(&spectrum[*b]))
+            .unwrap()
+    }
+
+    #[test]
+    fn fft_matches_the_definition() {
+        let signal: Vec<f32> = (0..16).map(|i| ((i * 7) % 5) as f32 - 2.0).collect();
+        let (mut re, mut im) = (signal.clone(), vec![0.0; 16]);
+        fft(&mut re, &mut im);
+
+        for k in 0..16 {
+            let (mut dft_re, mut dft_im) = (0.0, 0.0);
+            for (i, sample) in signal.iter().enumerate() {
+                let angle = -2.0 * PI * (k * i) as f32 / 16.0;
+                dft_re += sample * angle.cos();
+                dft_im += sample * angle.sin();
+            }
+            assert!((re[k] - dft_re).abs() < 1e-3, "bin {}", k);
+            assert!((im[k] - dft_im).abs() < 1e-3, "bin {}", k);
+        }
+    }
+
+    #[test]
+    fn sine_peaks_at_its_frequency() {
+        let sample_rate = 22_050;
+        for freq in [440.0, 1000.0, 5000.0] {
+            let spectrum = magnitude_spectrum(&sine(freq, sample_rate, 2048));
+            let peak = bin_frequency(loudest_bin(&spectrum), 2048, sample_rate);
+
+            // bins are about 10.8Hz apart
+            assert!(
+                (peak - freq).abs() < 11.0,
+                "{}Hz peaked at {}Hz",
+                freq,
+                peak
+            );
+        }
+    }
+
+    #[test]
+    fn silence_has_no_energy() {
+        assert!(magnitude_spectrum(&[0.0; 256]).iter().all(|m| *m == 0.0));
+    }
+}
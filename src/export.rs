@@ -0,0 +1,384 @@
+use std::{
+    collections::HashSet,
+    error::Error,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use chrono::{DateTime, Utc};
+use clap::ValueEnum;
+use rspotify::{model::SimplifiedPlaylist, prelude::Id, AuthCodePkceSpotify};
+use serde::Serialize;
+
+use crate::spotify::{self, PlaylistTrack};
+
+#[derive(ValueEnum, Clone, Copy)]
+pub enum Format {
+    M3u8,
+    Xspf,
+    Csv,
+    Json,
+}
+
+impl Format {
+    fn extension(&self) -> &'static str {
+        match self {
+            Format::M3u8 => "m3u8",
+            Format::Xspf => "xspf",
+            Format::Csv => "csv",
+            Format::Json => "json",
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct ExportedTrack {
+    title: String,
+    artists: Vec<String>,
+    album: String,
+    duration_ms: i64,
+    isrc: Option<String>,
+    uri: String,
+    added_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Serialize)]
+struct ExportedPlaylist {
+    name: String,
+    uri: String,
+    tracks: Vec<ExportedTrack>,
+}
+
+impl ExportedTrack {
+    fn new(item: &PlaylistTrack) -> Option<ExportedTrack> {
+        let track = &item.track;
+        Some(ExportedTrack {
+            title: track.name.clone(),
+            artists: track
+                .artists
+                .iter()
+                .map(|artist| artist.name.clone())
+                .collect(),
+            album: track.album.name.clone(),
+            duration_ms: track.duration.num_milliseconds(),
+            isrc: track.external_ids.get("isrc").cloned(),
+            // local files have no URI, and are of no use outside of the user's own Spotify
+            uri: track.id.as_ref()?.uri(),
+            added_at: item.added_at,
+        })
+    }
+}
+
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+/// Extended M3U, with ISRCs and when tracks were added in directives of their own, which players
+/// ignore as comments
+fn m3u8(playlist: &ExportedPlaylist) -> String {
+    let mut text = format!("#EXTM3U\n#PLAYLIST:{}\n", playlist.name);
+    for track in &playlist.tracks {
+        text += &format!(
+            "#EXTINF:{},{} - {}\n#EXTALB:{}\n",
+            track.duration_ms / 1000,
+            track.artists.join(", "),
+            track.title,
+            track.album
+        );
+        if let Some(isrc) = &track.isrc {
+            text += &format!("#EXTISRC:{}\n", isrc);
+        }
+        if let Some(added_at) = &track.added_at {
+            text += &format!("#EXTADDED:{}\n", added_at.to_rfc3339());
+        }
+        text += &format!("{}\n", track.uri);
+    }
+    text
+}
+
+fn xspf(playlist: &ExportedPlaylist) -> String {
+    let mut text = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    text += "<playlist version=\"1\" xmlns=\"http://xspf.org/ns/0/\">\n";
+    text += &format!("  <title>{}</title>\n", escape_xml(&playlist.name));
+    text += &format!("  <location>{}</location>\n", playlist.uri);
+    text += "  <trackList>\n";
+    for track in &playlist.tracks {
+        text += "    <track>\n";
+        text += &format!("      <location>{}</location>\n", track.uri);
+        if let Some(isrc) = &track.isrc {
+            text += &format!(
+                "      <identifier>urn:isrc:{}</identifier>\n",
+                escape_xml(isrc)
+            );
+        }
+        text += &format!("      <title>{}</title>\n", escape_xml(&track.title));
+        text += &format!(
+            "      <creator>{}</creator>\n",
+            escape_xml(&track.artists.join(", "))
+        );
+        text += &format!("      <album>{}</album>\n", escape_xml(&track.album));
+        text += &format!("      <duration>{}</duration>\n", track.duration_ms);
+        if let Some(added_at) = &track.added_at {
+            text += &format!(
+                "      <meta rel=\"https://github.com/franciscunha/sortify/added-at\">{}</meta>\n",
+                added_at.to_rfc3339()
+            );
+        }
+        text += "    </track>\n";
+    }
+    text += "  </trackList>\n</playlist>\n";
+    text
+}
+
+fn csv(playlist: &ExportedPlaylist) -> Result<String, Box<dyn Error>> {
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    writer.write_record([
+        "title",
+        "artists",
+        "album",
+        "duration_ms",
+        "isrc",
+        "uri",
+        "added_at",
+    ])?;
+    for track in &playlist.tracks {
+        writer.write_record([
+            track.title.clone(),
+            // artist names can have commas in them
+            track.artists.join("; "),
+            track.album.clone(),
+            track.duration_ms.to_string(),
+            track.isrc.clone().unwrap_or_default(),
+            track.uri.clone(),
+            track
+                .added_at
+                .map(|added_at| added_at.to_rfc3339())
+                .unwrap_or_default(),
+        ])?;
+    }
+    Ok(String::from_utf8(writer.into_inner()?)?)
+}
+
+fn contents(playlist: &ExportedPlaylist, format: Format) -> Result<String, Box<dyn Error>> {
+    Ok(match format {
+        Format::M3u8 => m3u8(playlist),
+        Format::Xspf => xspf(playlist),
+        Format::Csv => csv(playlist)?,
+        Format::Json => serde_json::to_string_pretty(playlist)?,
+    })
+}
+
+/// A file name from the playlist's name, without characters file systems may not allow
+fn file_name(name: &str, format: Format, taken: &mut HashSet<String>) -> String {
+    let base: String = name
+        .chars()
+        .map(|c| {
+            if c.is_alphanumeric() || " -_()".contains(c) {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    let base = match base.trim() {
+        "" => "playlist",
+        trimmed => trimmed,
+    };
+
+    let mut file_name = format!("{}.{}", base, format.extension());
+    let mut suffix = 2;
+    while !taken.insert(file_name.to_lowercase()) {
+        file_name = format!("{} ({}).{}", base, suffix, format.extension());
+        suffix += 1;
+    }
+    file_name
+}
+
+/// Writes each playlist to its own file in the directory, returning the files and how many
+/// tracks went into each
+pub fn export(
+    spotify: &AuthCodePkceSpotify,
+    playlists: &[&SimplifiedPlaylist],
+    format: Format,
+    directory: &Path,
+) -> Result<Vec<(PathBuf, usize)>, Box<dyn Error>> {
+    fs::create_dir_all(directory)?;
+    let mut taken = HashSet::new();
+    let mut written = Vec::new();
+
+    for playlist in playlists {
+        log::info!("Exporting playlist {}", playlist.id);
+        let exported = ExportedPlaylist {
+            name: playlist.name.clone(),
+            uri: playlist.id.uri(),
+            tracks: spotify::items_in_playlist(spotify, playlist.id.clone_static())
+                .iter()
+                .filter_map(ExportedTrack::new)
+                .collect(),
+        };
+
+        let path = directory.join(file_name(&playlist.name, format, &mut taken));
+        fs::write(&path, contents(&exported, format)?)?;
+        written.push((path, exported.tracks.len()));
+    }
+
+    Ok(written)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+    use crate::testing;
+
+    /// A playlist with a track that has every detail, one that has none of the optional ones and
+    /// a local file
+    fn playlist() -> ExportedPlaylist {
+        let mut full = testing::track("0000000000000000000001", "So What", "Miles Davis", 562_000);
+        full.album.name = String::from("Kind of Blue");
+        full.artists.push(full.artists[0].clone());
+        full.artists[1].name = String::from("John Coltrane");
+        full.external_ids
+            .insert(String::from("isrc"), String::from("USSM15900113"));
+        let bare = testing::track(
+            "0000000000000000000002",
+            "Blue & Green",
+            "Bill Evans",
+            337_500,
+        );
+        let mut local = testing::track("0000000000000000000003", "Demo", "Me", 1000);
+        local.id = None;
+
+        let items = [
+            PlaylistTrack {
+                track: full,
+                added_at: Some(Utc.with_ymd_and_hms(2024, 5, 1, 12, 30, 0).unwrap()),
+                added_by: None,
+            },
+            PlaylistTrack {
+                track: bare,
+                added_at: None,
+                added_by: None,
+            },
+            PlaylistTrack {
+                track: local,
+                added_at: None,
+                added_by: None,
+            },
+        ];
+
+        ExportedPlaylist {
+            name: String::from("Jazz <3"),
+            uri: String::from("spotify:playlist:0000000000000000000000"),
+            tracks: items.iter().filter_map(ExportedTrack::new).collect(),
+        }
+    }
+
+    #[test]
+    fn m3u8_has_isrc_and_added_date_when_known() {
+        assert_eq!(
+            contents(&playlist(), Format::M3u8).unwrap(),
+            "#EXTM3U\n\
+             #PLAYLIST:Jazz <3\n\
+             #EXTINF:562,Miles Davis, John Coltrane - So What\n\
+             #EXTALB:Kind of Blue\n\
+             #EXTISRC:USSM15900113\n\
+             #EXTADDED:2024-05-01T12:30:00+00:00\n\
+             spotify:track:0000000000000000000001\n\
+             #EXTINF:337,Bill Evans - Blue & Green\n\
+             #EXTALB:Album\n\
+             spotify:track:0000000000000000000002\n"
+        );
+    }
+
+    #[test]
+    fn xspf_escapes_text() {
+        let xspf = contents(&playlist(), Format::Xspf).unwrap();
+
+        assert!(xspf.contains("<title>Jazz &lt;3</title>"));
+        assert!(xspf.contains("<title>Blue &amp; Green</title>"));
+        assert!(xspf.contains("<identifier>urn:isrc:USSM15900113</identifier>"));
+        assert!(xspf.contains(">2024-05-01T12:30:00+00:00</meta>"));
+        assert_eq!(xspf.matches("<track>").count(), 2);
+    }
+
+    #[test]
+    fn csv_has_a_row_per_track() {
+        let csv = contents(&playlist(), Format::Csv).unwrap();
+        let mut reader = csv::Reader::from_reader(csv.as_bytes());
+        let rows: Vec<Vec<String>> = reader
+            .records()
+            .map(|record| record.unwrap().iter().map(String::from).collect())
+            .collect();
+
+        assert_eq!(
+            reader.headers().unwrap(),
+            vec![
+                "title",
+                "artists",
+                "album",
+                "duration_ms",
+                "isrc",
+                "uri",
+                "added_at"
+            ]
+        );
+        assert_eq!(
+            rows,
+            vec![
+                vec![
+                    "So What",
+                    "Miles Davis; John Coltrane",
+                    "Kind of Blue",
+                    "562000",
+                    "USSM15900113",
+                    "spotify:track:0000000000000000000001",
+                    "2024-05-01T12:30:00+00:00",
+                ],
+                vec![
+                    "Blue & Green",
+                    "Bill Evans",
+                    "Album",
+                    "337500",
+                    "",
+                    "spotify:track:0000000000000000000002",
+                    "",
+                ],
+            ]
+        );
+    }
+
+    #[test]
+    fn json_keeps_missing_details_as_null() {
+        let json: serde_json::Value =
+            serde_json::from_str(&contents(&playlist(), Format::Json).unwrap()).unwrap();
+
+        assert_eq!(json["name"], "Jazz <3");
+        assert_eq!(json["tracks"].as_array().unwrap().len(), 2);
+        assert_eq!(json["tracks"][0]["artists"][1], "John Coltrane");
+        assert_eq!(json["tracks"][0]["isrc"], "USSM15900113");
+        assert_eq!(json["tracks"][0]["added_at"], "2024-05-01T12:30:00Z");
+        assert!(json["tracks"][1]["isrc"].is_null());
+        assert!(json["tracks"][1]["added_at"].is_null());
+    }
+
+    #[test]
+    fn file_names_are_safe_and_distinct() {
+        let mut taken = HashSet::new();
+
+        assert_eq!(
+            file_name("Jazz/Blues", Format::Csv, &mut taken),
+            "Jazz_Blues.csv"
+        );
+        assert_eq!(
+            file_name("jazz_blues", Format::Csv, &mut taken),
+            "jazz_blues (2).csv"
+        );
+        assert_eq!(file_name("  ", Format::M3u8, &mut taken), "playlist.m3u8");
+    }
+}
@@ -0,0 +1,192 @@
+/// Something to pick by typing part of its name or description
+pub struct Candidate<'a> {
+    pub name: &'a str,
+    pub description: Option<&'a str>,
+}
+
+/// How well a candidate matches a query, and where
+pub struct Hit {
+    /// Position of the candidate in the list filtered
+    pub index: usize,
+    pub score: i64,
+    /// Indices of the matched characters of the name, empty if only the description matched
+    pub name: Vec<usize>,
+    pub description: Option<Vec<usize>>,
+}
+
+const CONSECUTIVE_BONUS: i64 = 5;
+const WORD_START_BONUS: i64 = 3;
+const MAX_GAP_PENALTY: i64 = 3;
+
+fn lowercase(c: char) -> char {
+    c.to_lowercase().next().unwrap_or(c)
+}
+
+/// Matches the query's characters in order, from the given start in the text
+fn match_from(query: &[char], text: &[char], start: usize) -> Option<(i64, Vec<usize>)> {
+    let mut positions = Vec::with_capacity(query.len());
+    let mut score = 0;
+    let mut at = start;
+
+    for &wanted in query {
+        let found = (at..text.len()).find(|&i| lowercase(text[i]) == wanted)?;
+        score += 1;
+        match positions.last() {
+            Some(&last) if found == last + 1 => score += CONSECUTIVE_BONUS,
+            Some(&last) => score -= ((found - last - 1) as i64).min(MAX_GAP_PENALTY),
+            None => (),
+        }
+        if found == 0 || !text[found - 1].is_alphanumeric() {
+            score += WORD_START_BONUS;
+        }
+        positions.push(found);
+        at = found + 1;
+    }
+
+    Some((score, positions))
+}
+
+/// Whether the text has the query's characters in order, ignoring case and spaces in the query,
+/// with a score that favours runs of characters and the starts of words
+pub fn find(query: &str, text: &str) -> Option<(i64, Vec<usize>)> {
+    let query: Vec<char> = query
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .map(lowercase)
+        .collect();
+    let first = *query.first()?;
+    let text: Vec<char> = text.chars().collect();
+
+    // matching from the first occurrence alone would miss "jazz" in "just jazz" as a word
+    (0..text.len())
+        .filter(|&i| lowercase(text[i]) == first)
+        .filter_map(|start| match_from(&query, &text, start))
+        .max_by_key(|(score, _)| *score)
+}
+
+/// The candidates that match, best first. Names count for more than descriptions.
+pub fn filter(query: &str, candidates: &[Candidate]) -> Vec<Hit> {
+    let mut hits: Vec<Hit> = candidates
+        .iter()
+        .enumerate()
+        .filter_map(|(index, candidate)| {
+            let by_name = find(query, candidate.name);
+            let by_description = candidate
+                .description
+                .and_then(|description| find(query, description));
+            let score = match (&by_name, &by_description) {
+                (Some((score, _)), _) => score * 2,
+                (None, Some((score, _))) => *score,
+                (None, None) => return None,
+            };
+            Some(Hit {
+                index,
+                score,
+                name: by_name.map(|(_, positions)| positions).unwrap_or_default(),
+                description: by_description.map(|(_, positions)| positions),
+            })
+        })
+        .collect();
+
+    // sorting is stable, so equally good matches keep their order
+    hits.sort_by_key(|hit| std::cmp::Reverse(hit.score));
+    hits
+}
+
+/// The text with each run of matched characters passed through `mark`, however the UI shows them
+pub fn highlight(text: &str, positions: &[usize], mark: impl Fn(&str) -> String) -> String {
+    let mut highlighted = String::new();
+    let mut run = String::new();
+    for (i, c) in text.chars().enumerate() {
+        if positions.contains(&i) {
+            run.push(c);
+            continue;
+        }
+        if !run.is_empty() {
+            highlighted += &mark(&run);
+            run.clear();
+        }
+        highlighted.push(c);
+    }
+    if !run.is_empty() {
+        highlighted += &mark(&run);
+    }
+    highlighted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn positions(query: &str, text: &str) -> Option<Vec<usize>> {
+        find(query, text).map(|(_, positions)| positions)
+    }
+
+    #[test]
+    fn characters_match_in_order_ignoring_case_and_spaces() {
+        assert_eq!(positions("dhs", "Deep House"), Some(vec![0, 5, 8]));
+        assert_eq!(positions("DEEP h", "deep house"), Some(vec![0, 1, 2, 3, 5]));
+        assert_eq!(positions("hd", "Deep House"), None);
+        assert_eq!(positions("", "Deep House"), None);
+        assert_eq!(positions(" ", "Deep House"), None);
+    }
+
+    #[test]
+    fn whole_words_beat_the_first_occurrence() {
+        assert_eq!(positions("jazz", "just jazz"), Some(vec![5, 6, 7, 8]));
+    }
+
+    #[test]
+    fn runs_score_higher_than_scattered_characters() {
+        let (run, _) = find("chill", "Chill vibes").unwrap();
+        let (scattered, _) = find("chill", "Chopin: the ill").unwrap();
+        assert!(run > scattered);
+    }
+
+    #[test]
+    fn names_count_for_more_than_descriptions() {
+        let candidates = [
+            Candidate {
+                name: "Evening",
+                description: Some("Slow jazz for late"),
+            },
+            Candidate {
+                name: "Workout",
+                description: None,
+            },
+            Candidate {
+                name: "Jazz classics",
+                description: None,
+            },
+        ];
+        let hits = filter("jazz", &candidates);
+
+        assert_eq!(hits.iter().map(|hit| hit.index).collect::<Vec<_>>(), [2, 0]);
+        assert_eq!(hits[0].name, [0, 1, 2, 3]);
+        assert!(hits[0].description.is_none());
+        assert!(hits[1].name.is_empty());
+        assert_eq!(hits[1].description, Some(vec![5, 6, 7, 8]));
+    }
+
+    #[test]
+    fn equal_matches_keep_their_order() {
+        let candidates = [
+            Candidate {
+                name: "Rock",
+                description: None,
+            },
+            Candidate {
+                name: "Rock",
+                description: None,
+            },
+        ];
+        let hits = filter("rock", &candidates);
+        assert_eq!(hits.iter().map(|hit| hit.index).collect::<Vec<_>>(), [0, 1]);
+    }
+
+    #[test]
+    fn highlight_marks_runs() {
+        let marked = highlight("Deep House", &[0, 1, 5], |run| format!("[{}]", run));
+        assert_eq!(marked, "[De]ep [H]ouse");
+    }
+}
@@ -0,0 +1,390 @@
+use std::{
+    io::Write,
+    process::{Command, Stdio},
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use rspotify::{
+    model::{FullTrack, PlaylistId, SimplifiedPlaylist},
+    prelude::Id,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{services::TrackAction, spotify::SpotifyPlaylistsError};
+
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+fn default_timeout() -> u64 {
+    5
+}
+
+#[derive(Deserialize, PartialEq, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum EventKind {
+    Sorted,
+    Removed,
+    Skipped,
+    SessionFinished,
+    Error,
+}
+
+/// A command run by the shell whenever one of the events happens, with the event as JSON on stdin
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct HookConfig {
+    command: String,
+    /// Events to run for, all of them if empty
+    #[serde(default)]
+    events: Vec<EventKind>,
+    /// The command is killed if it takes longer than this
+    #[serde(default = "default_timeout")]
+    timeout_seconds: u64,
+}
+
+#[derive(Serialize)]
+pub struct TrackInfo {
+    id: Option<String>,
+    name: String,
+    artists: Vec<String>,
+    album: String,
+}
+
+#[derive(Serialize)]
+pub struct PlaylistInfo {
+    id: String,
+    name: String,
+}
+
+#[derive(Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event {
+    Sorted {
+        track: TrackInfo,
+        playlists: Vec<PlaylistInfo>,
+    },
+    Removed {
+        track: TrackInfo,
+    },
+    Skipped {
+        track: TrackInfo,
+    },
+    SessionFinished {
+        source_playlist: String,
+        /// Whether every track in the source playlist was gone through
+        sorted_all: bool,
+    },
+    Error {
+        track: TrackInfo,
+        message: String,
+    },
+}
+
+impl TrackInfo {
+    fn new(track: &FullTrack) -> TrackInfo {
+        TrackInfo {
+            id: track.id.as_ref().map(|id| id.id().to_string()),
+            name: track.name.clone(),
+            artists: track
+                .artists
+                .iter()
+                .map(|artist| artist.name.clone())
+                .collect(),
+            album: track.album.name.clone(),
+        }
+    }
+}
+
+impl Event {
+    /// The event for the outcome of acting on a track
+    pub fn from_result(
+        track: &FullTrack,
+        result: &Result<TrackAction, SpotifyPlaylistsError>,
+        playlists: &[SimplifiedPlaylist],
+    ) -> Event {
+        let playlist_info = |id: &PlaylistId| PlaylistInfo {
+            id: id.id().to_string(),
+            name: playlists
+                .iter()
+                .find(|playlist| &playlist.id == id)
+                .map(|playlist| playlist.name.clone())
+                .unwrap_or_default(),
+        };
+
+        match result {
+            Ok(TrackAction::Add(playlist_ids)) => Event::Sorted {
+                track: TrackInfo::new(track),
+                playlists: playlist_ids.iter().map(playlist_info).collect(),
+            },
+            Ok(TrackAction::Remove(_)) => Event::Removed {
+                track: TrackInfo::new(track),
+            },
+            Ok(TrackAction::Skip) => Event::Skipped {
+                track: TrackInfo::new(track),
+            },
+            Err(e) => Event::Error {
+                track: TrackInfo::new(track),
+                message: e.message(),
+            },
+        }
+    }
+
+    pub fn error(track: &FullTrack, message: &str) -> Event {
+        Event::Error {
+            track: TrackInfo::new(track),
+            message: message.to_string(),
+        }
+    }
+
+    fn kind(&self) -> EventKind {
+        match self {
+            Event::Sorted { .. } => EventKind::Sorted,
+            Event::Removed { .. } => EventKind::Removed,
+            Event::Skipped { .. } => EventKind::Skipped,
+            Event::SessionFinished { .. } => EventKind::SessionFinished,
+            Event::Error { .. } => EventKind::Error,
+        }
+    }
+}
+
+fn shell(command: &str) -> Command {
+    if cfg!(windows) {
+        let mut shell = Command::new("cmd");
+        shell.arg("/C").arg(command);
+        shell
+    } else {
+        let mut shell = Command::new("sh");
+        shell.arg("-c").arg(command);
+        shell
+    }
+}
+
+fn run(command: &str, input: &str, timeout: Duration) -> Result<(), String> {
+    let mut child = shell(command)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .map_err(|e| e.to_string())?;
+
+    // dropping stdin closes it, so the command sees the end of the input
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin
+            .write_all(input.as_bytes())
+            .map_err(|e| e.to_string())?;
+    }
+
+    let started = Instant::now();
+    loop {
+        match child.try_wait().map_err(|e| e.to_string())? {
+            Some(status) if status.success() => return Ok(()),
+            Some(status) => return Err(format!("exited with {}", status)),
+            None if started.elapsed() > timeout => {
+                _ = child.kill();
+                _ = child.wait();
+                return Err(format!("timed out after {}s", timeout.as_secs()));
+            }
+            None => thread::sleep(POLL_INTERVAL),
+        }
+    }
+}
+
+/// Runs the configured commands in the background, so slow ones don't hold up sorting
+pub struct Hooks {
+    hooks: Vec<HookConfig>,
+    running: Vec<JoinHandle<()>>,
+}
+
+impl Hooks {
+    pub fn new(hooks: Vec<HookConfig>) -> Hooks {
+        Hooks {
+            hooks,
+            running: Vec::new(),
+        }
+    }
+
+    pub fn emit(&mut self, event: Event) {
+        self.running.retain(|handle| !handle.is_finished());
+
+        let kind = event.kind();
+        let matching: Vec<&HookConfig> = self
+            .hooks
+            .iter()
+            .filter(|hook| hook.events.is_empty() || hook.events.contains(&kind))
+            .collect();
+        if matching.is_empty() {
+            return;
+        }
+
+        let input = match serde_json::to_string(&event) {
+            Ok(input) => input,
+            Err(e) => {
+                log::error!("Failed to serialize hook event: {}", e);
+                return;
+            }
+        };
+
+        for hook in matching {
+            let command = hook.command.clone();
+            let input = input.clone();
+            let timeout = Duration::from_secs(hook.timeout_seconds);
+            self.running.push(thread::spawn(move || {
+                if let Err(e) = run(&command, &input, timeout) {
+                    log::warn!("Hook '{}' failed: {}", command, e);
+                }
+            }));
+        }
+    }
+
+    /// Waits for commands still running, which are bounded by their timeouts
+    pub fn finish(&mut self) {
+        for handle in self.running.drain(..) {
+            _ = handle.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, path::PathBuf};
+
+    use serde_json::{json, Value};
+
+    use super::*;
+    use crate::testing;
+
+    fn track() -> FullTrack {
+        testing::track("0000000000000000000009", "So What", "Miles Davis", 1)
+    }
+
+    fn as_json(event: &Event) -> Value {
+        serde_json::to_value(event).unwrap()
+    }
+
+    /// A file in a temporary directory of the test's own, which goes away with it
+    struct TempFile(PathBuf);
+
+    impl TempFile {
+        fn new(test: &str) -> TempFile {
+            let directory =
+                env::temp_dir().join(format!("sortify-hooks-{}-{}", std::process::id(), test));
+            fs::create_dir_all(&directory).unwrap();
+            TempFile(directory.join("out"))
+        }
+
+        fn contents(&self) -> String {
+            fs::read_to_string(&self.0).unwrap_or_default()
+        }
+    }
+
+    impl Drop for TempFile {
+        fn drop(&mut self) {
+            _ = fs::remove_dir_all(self.0.parent().unwrap());
+        }
+    }
+
+    #[test]
+    fn events_are_tagged_with_their_kind() {
+        let playlists = vec![testing::playlist("0000000000000000000001", "Jazz")];
+        let sorted = Event::from_result(
+            &track(),
+            &Ok(TrackAction::Add(vec![playlists[0].id.clone_static()])),
+            &playlists,
+        );
+
+        assert_eq!(
+            as_json(&sorted),
+            json!({
+                "event": "sorted",
+                "track": {
+                    "id": "0000000000000000000009",
+                    "name": "So What",
+                    "artists": ["Miles Davis"],
+                    "album": "Album",
+                },
+                "playlists": [{ "id": "0000000000000000000001", "name": "Jazz" }],
+            })
+        );
+        assert_eq!(
+            as_json(&Event::SessionFinished {
+                source_playlist: String::from("Inbox"),
+                sorted_all: true,
+            }),
+            json!({ "event": "session_finished", "source_playlist": "Inbox", "sorted_all": true })
+        );
+        assert_eq!(
+            as_json(&Event::error(&track(), "no connection"))["message"],
+            "no connection"
+        );
+        assert_eq!(
+            as_json(&Event::from_result(&track(), &Ok(TrackAction::Skip), &[]))["event"],
+            "skipped"
+        );
+    }
+
+    #[test]
+    fn hook_settings_have_defaults() {
+        let hook: HookConfig = serde_json::from_str(r#"{"command": "true"}"#).unwrap();
+
+        assert!(hook.events.is_empty());
+        assert_eq!(hook.timeout_seconds, 5);
+        assert!(serde_json::from_str::<HookConfig>(r#"{"command": "true", "on": []}"#).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn commands_get_the_event_on_stdin() {
+        let file = TempFile::new("stdin");
+        let command = format!("cat > '{}'", file.0.display());
+
+        run(&command, "{\"event\":\"skipped\"}", Duration::from_secs(5)).unwrap();
+
+        assert_eq!(file.contents(), "{\"event\":\"skipped\"}");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn failing_and_slow_commands_are_errors() {
+        assert_eq!(
+            run("exit 3", "", Duration::from_secs(5)),
+            Err(String::from("exited with exit status: 3"))
+        );
+
+        let started = Instant::now();
+        let slow = run("sleep 5", "", Duration::from_millis(200));
+        assert!(slow.unwrap_err().starts_with("timed out"));
+        assert!(started.elapsed() < Duration::from_secs(4));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn hooks_only_run_for_their_events() {
+        let file = TempFile::new("events");
+        let hook = |events: &str| -> HookConfig {
+            serde_json::from_value(json!({
+                "command": format!("cat >> '{}'; echo >> '{}'", file.0.display(), file.0.display()),
+                "events": serde_json::from_str::<Value>(events).unwrap(),
+            }))
+            .unwrap()
+        };
+        let mut hooks = Hooks::new(vec![hook(r#"["removed"]"#)]);
+
+        hooks.emit(Event::from_result(&track(), &Ok(TrackAction::Skip), &[]));
+        hooks.emit(Event::from_result(
+            &track(),
+            &Ok(TrackAction::Remove(
+                PlaylistId::from_id("0000000000000000000000").unwrap(),
+            )),
+            &[],
+        ));
+        hooks.finish();
+
+        let lines: Vec<Value> = file
+            .contents()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 1);
+        assert_eq!(lines[0]["event"], "removed");
+    }
+}
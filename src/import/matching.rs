@@ -0,0 +1,330 @@
+use rspotify::{
+    model::{FullTrack, TrackId},
+    AuthCodePkceSpotify,
+};
+
+use super::LocalTrack;
+use crate::spotify;
+
+/// How far apart a file's length and a track's can be for them to be the same recording
+const DURATION_TOLERANCE_MS: i64 = 3000;
+/// How many search results to consider
+const CANDIDATES: u32 = 5;
+
+/// Where tracks are looked up, so matching can be tried out without Spotify
+pub trait Catalog {
+    fn track(&self, id: &TrackId<'static>) -> Option<FullTrack>;
+    fn search(&self, query: &str, limit: u32) -> Vec<FullTrack>;
+}
+
+impl Catalog for AuthCodePkceSpotify {
+    fn track(&self, id: &TrackId<'static>) -> Option<FullTrack> {
+        spotify::track(self, id)
+    }
+
+    fn search(&self, query: &str, limit: u32) -> Vec<FullTrack> {
+        spotify::search_tracks(self, query, limit)
+    }
+}
+
+pub enum Match {
+    Found(Box<FullTrack>),
+    /// Tracks that could each be the one, best first
+    Ambiguous(Vec<FullTrack>),
+    NotFound,
+}
+
+/// Lowercase words without punctuation or anything in brackets, so "Song (2011 Remaster)" and
+/// "song" compare equal
+fn normalise(text: &str) -> String {
+    // " - Remastered 2011" and the like
+    let text = text.split(" - ").next().unwrap_or(text);
+    let mut depth: i32 = 0;
+    let mut kept = String::new();
+    for c in text.to_lowercase().chars() {
+        match c {
+            '(' | '[' => depth += 1,
+            ')' | ']' => depth = (depth - 1).max(0),
+            _ if depth > 0 => (),
+            c if c.is_alphanumeric() => kept.push(c),
+            _ => kept.push(' '),
+        }
+    }
+    kept.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+fn similar(a: &str, b: &str) -> bool {
+    let (a, b) = (normalise(a), normalise(b));
+    !a.is_empty() && !b.is_empty() && (a.contains(&b) || b.contains(&a))
+}
+
+/// Whether the track could be the file, going by title, artists and length
+fn is_plausible(local: &LocalTrack, track: &FullTrack) -> bool {
+    let same_title = similar(&local.title, &track.name);
+    let same_artist = local.artists.is_empty()
+        || local.artists.iter().any(|artist| {
+            track
+                .artists
+                .iter()
+                .any(|other| similar(artist, &other.name))
+        });
+    let same_length = local.duration_ms.is_none_or(|duration_ms| {
+        (duration_ms - track.duration.num_milliseconds()).abs() <= DURATION_TOLERANCE_MS
+    });
+    same_title && same_artist && same_length
+}
+
+fn search(local: &LocalTrack, catalog: &impl Catalog) -> Vec<FullTrack> {
+    let title = local.title.replace('"', "");
+    let filtered = match local.artists.first() {
+        Some(artist) => format!("track:\"{}\" artist:\"{}\"", title, artist.replace('"', "")),
+        None => format!("track:\"{}\"", title),
+    };
+    let results = catalog.search(&filtered, CANDIDATES);
+    if !results.is_empty() {
+        return results;
+    }
+    // field filters are strict about spelling, plain searches less so
+    catalog.search(&local.summary(), CANDIDATES)
+}
+
+/// Finds the file's track by its Spotify ID, then its ISRC, then by searching for its artist and
+/// title
+pub fn find(local: &LocalTrack, catalog: &impl Catalog) -> Match {
+    if let Some(track) = local.spotify_id.as_ref().and_then(|id| catalog.track(id)) {
+        return Match::Found(Box::new(track));
+    }
+
+    if let Some(isrc) = &local.isrc {
+        // the same recording can be on several releases, and the first is the most popular
+        if let Some(track) = catalog
+            .search(&format!("isrc:{}", isrc), 1)
+            .into_iter()
+            .next()
+        {
+            return Match::Found(Box::new(track));
+        }
+    }
+
+    let results = search(local, catalog);
+    let mut plausible: Vec<FullTrack> = results
+        .iter()
+        .filter(|track| is_plausible(local, track))
+        .cloned()
+        .collect();
+
+    match plausible.len() {
+        0 if results.is_empty() => Match::NotFound,
+        // whatever the search found may still be it, e.g. if the file is tagged differently
+        0 => Match::Ambiguous(results),
+        1 => Match::Found(Box::new(plausible.remove(0))),
+        _ => {
+            // a single, an album and a compilation of the same song aren't worth asking about
+            let first = &plausible[0];
+            let all_same = plausible.iter().all(|track| {
+                normalise(&track.name) == normalise(&first.name)
+                    && track.artists.first().map(|artist| &artist.name)
+                        == first.artists.first().map(|artist| &artist.name)
+            });
+            if all_same {
+                Match::Found(Box::new(plausible.remove(0)))
+            } else {
+                Match::Ambiguous(plausible)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, collections::HashMap};
+
+    use rspotify::prelude::Id;
+
+    use super::*;
+    use crate::testing::track;
+
+    /// Looks tracks up in memory, keeping the queries it was asked
+    #[derive(Default)]
+    struct FakeCatalog {
+        tracks: Vec<FullTrack>,
+        results: HashMap<String, Vec<FullTrack>>,
+        queries: RefCell<Vec<String>>,
+    }
+
+    impl Catalog for FakeCatalog {
+        fn track(&self, id: &TrackId<'static>) -> Option<FullTrack> {
+            self.tracks
+                .iter()
+                .find(|track| track.id.as_ref() == Some(id))
+                .cloned()
+        }
+
+        fn search(&self, query: &str, limit: u32) -> Vec<FullTrack> {
+            self.queries.borrow_mut().push(query.to_string());
+            let results = self.results.get(query).cloned().unwrap_or_default();
+            results.into_iter().take(limit as usize).collect()
+        }
+    }
+
+    fn local(artist: &str, title: &str, duration_ms: Option<i64>) -> LocalTrack {
+        LocalTrack {
+            title: title.to_string(),
+            artists: vec![artist.to_string()],
+            duration_ms,
+            isrc: None,
+            spotify_id: None,
+        }
+    }
+
+    fn found_id(found: Match) -> String {
+        match found {
+            Match::Found(track) => track.id.unwrap().id().to_string(),
+            Match::Ambiguous(_) => panic!("expected a match, got several"),
+            Match::NotFound => panic!("expected a match, got none"),
+        }
+    }
+
+    const BY_ID: &str = "0000000000000000000001";
+    const BY_ISRC: &str = "0000000000000000000002";
+    const BY_SEARCH: &str = "0000000000000000000003";
+    const OTHER: &str = "0000000000000000000004";
+    const QUERY: &str = "track:\"Hey Jude\" artist:\"The Beatles\"";
+
+    #[test]
+    fn uri_comes_before_isrc_and_search() {
+        let catalog = FakeCatalog {
+            tracks: vec![track(BY_ID, "Hey Jude", "The Beatles", 431_000)],
+            results: HashMap::from([
+                (
+                    String::from("isrc:GBAYE0601498"),
+                    vec![track(BY_ISRC, "Hey Jude", "The Beatles", 431_000)],
+                ),
+                (
+                    String::from(QUERY),
+                    vec![track(BY_SEARCH, "Hey Jude", "The Beatles", 431_000)],
+                ),
+            ]),
+            ..Default::default()
+        };
+        let mut file = local("The Beatles", "Hey Jude", None);
+        file.isrc = Some(String::from("GBAYE0601498"));
+        file.spotify_id = TrackId::from_id(BY_ID).ok();
+
+        assert_eq!(found_id(find(&file, &catalog)), BY_ID);
+        assert!(catalog.queries.borrow().is_empty());
+
+        file.spotify_id = TrackId::from_id(OTHER).ok();
+        assert_eq!(found_id(find(&file, &catalog)), BY_ISRC);
+
+        file.isrc = None;
+        assert_eq!(found_id(find(&file, &catalog)), BY_SEARCH);
+    }
+
+    #[test]
+    fn isrc_without_results_falls_back_to_search() {
+        let catalog = FakeCatalog {
+            results: HashMap::from([(
+                String::from(QUERY),
+                vec![track(BY_SEARCH, "Hey Jude", "The Beatles", 431_000)],
+            )]),
+            ..Default::default()
+        };
+        let mut file = local("The Beatles", "Hey Jude", None);
+        file.isrc = Some(String::from("GBAYE0601498"));
+
+        assert_eq!(found_id(find(&file, &catalog)), BY_SEARCH);
+        assert_eq!(*catalog.queries.borrow(), ["isrc:GBAYE0601498", QUERY]);
+    }
+
+    #[test]
+    fn plain_search_when_filtered_search_finds_nothing() {
+        let catalog = FakeCatalog {
+            results: HashMap::from([(
+                String::from("The Beatles - Hey Jude"),
+                vec![track(BY_SEARCH, "Hey Jude", "The Beatles", 431_000)],
+            )]),
+            ..Default::default()
+        };
+        let file = local("The Beatles", "Hey Jude", None);
+
+        assert_eq!(found_id(find(&file, &catalog)), BY_SEARCH);
+        assert_eq!(*catalog.queries.borrow(), [QUERY, "The Beatles - Hey Jude"]);
+    }
+
+    #[test]
+    fn length_within_tolerance_matches() {
+        let catalog = FakeCatalog {
+            results: HashMap::from([(
+                String::from(QUERY),
+                vec![track(BY_SEARCH, "Hey Jude", "The Beatles", 431_000)],
+            )]),
+            ..Default::default()
+        };
+
+        let close = local(
+            "The Beatles",
+            "Hey Jude",
+            Some(431_000 + DURATION_TOLERANCE_MS),
+        );
+        assert_eq!(found_id(find(&close, &catalog)), BY_SEARCH);
+
+        // still offered, as the file may just be cut differently
+        let far = local("The Beatles", "Hey Jude", Some(431_000 + 10_000));
+        assert!(matches!(find(&far, &catalog), Match::Ambiguous(tracks) if tracks.len() == 1));
+    }
+
+    #[test]
+    fn different_plausible_tracks_are_ambiguous() {
+        let catalog = FakeCatalog {
+            results: HashMap::from([(
+                String::from("track:\"Yesterday\""),
+                vec![
+                    track(BY_SEARCH, "Yesterday", "The Beatles", 125_000),
+                    track(OTHER, "Yesterday", "Ray Charles", 184_000),
+                ],
+            )]),
+            ..Default::default()
+        };
+        let file = LocalTrack {
+            artists: Vec::new(),
+            ..local("", "Yesterday", None)
+        };
+
+        assert!(matches!(find(&file, &catalog), Match::Ambiguous(tracks) if tracks.len() == 2));
+    }
+
+    #[test]
+    fn releases_of_the_same_song_are_one_match() {
+        let catalog = FakeCatalog {
+            results: HashMap::from([(
+                String::from(QUERY),
+                vec![
+                    track(BY_SEARCH, "Hey Jude", "The Beatles", 431_000),
+                    track(OTHER, "Hey Jude - Remastered 2015", "The Beatles", 431_000),
+                ],
+            )]),
+            ..Default::default()
+        };
+        let file = local("The Beatles", "Hey Jude", None);
+
+        assert_eq!(found_id(find(&file, &catalog)), BY_SEARCH);
+    }
+
+    #[test]
+    fn nothing_found() {
+        let catalog = FakeCatalog::default();
+        let file = local("The Beatles", "Hey Jude", None);
+
+        assert!(matches!(find(&file, &catalog), Match::NotFound));
+    }
+
+    #[test]
+    fn normalise_drops_versions_and_punctuation() {
+        assert_eq!(normalise("Song (2011 Remaster)"), "song");
+        assert_eq!(normalise("Song - Remastered 2011"), "song");
+        assert_eq!(normalise("Song [Live] (Edit)"), "song");
+        assert_eq!(normalise("Don't  Stop Me Now!"), "don t stop me now");
+        assert_eq!(normalise("(Intro)"), "");
+    }
+}
@@ -0,0 +1,249 @@
+use std::{fs, path::Path};
+
+use rspotify::model::{SimplifiedPlaylist, TrackId};
+use serde::Deserialize;
+
+use crate::spotify;
+
+pub mod matching;
+
+/// A track as listed in a playlist file from outside Spotify
+pub struct LocalTrack {
+    pub title: String,
+    pub artists: Vec<String>,
+    pub duration_ms: Option<i64>,
+    pub isrc: Option<String>,
+    /// Files exported from Spotify list the tracks themselves, which saves searching for them
+    pub spotify_id: Option<TrackId<'static>>,
+}
+
+impl LocalTrack {
+    /// A track known only by "Artist - Title", or just its title
+    pub fn from_summary(text: &str) -> LocalTrack {
+        let (artists, title) = split_artist_title(text);
+        LocalTrack {
+            title,
+            artists,
+            duration_ms: None,
+            isrc: None,
+            spotify_id: None,
+        }
+    }
+
+    pub fn summary(&self) -> String {
+        if self.artists.is_empty() {
+            self.title.clone()
+        } else {
+            format!("{} - {}", self.artists.join(", "), self.title)
+        }
+    }
+}
+
+/// Where imported tracks go
+pub enum Destination<'a> {
+    /// A playlist to create with this name
+    New(&'a str),
+    /// The source playlist, to sort them from there
+    Source(&'a SimplifiedPlaylist),
+}
+
+impl Destination<'_> {
+    pub fn name(&self) -> &str {
+        match self {
+            Destination::New(name) => name,
+            Destination::Source(playlist) => &playlist.name,
+        }
+    }
+}
+
+/// A track as written in a CSV file, with the columns of an export and any others ignored
+#[derive(Deserialize)]
+struct CsvRow {
+    title: String,
+    #[serde(default)]
+    artists: String,
+    duration_ms: Option<i64>,
+    isrc: Option<String>,
+    uri: Option<String>,
+}
+
+fn spotify_id(reference: &str) -> Option<TrackId<'static>> {
+    // anything else is a path to a file
+    let is_spotify =
+        reference.starts_with("spotify:track:") || reference.contains("open.spotify.com/track/");
+    if !is_spotify {
+        return None;
+    }
+    TrackId::from_id(spotify::track_id(reference).to_string()).ok()
+}
+
+/// "Artist - Title", as in #EXTINF lines and many file names
+fn split_artist_title(text: &str) -> (Vec<String>, String) {
+    match text.split_once(" - ") {
+        Some((artists, title)) => (
+            artists
+                .split(',')
+                .map(str::trim)
+                .filter(|artist| !artist.is_empty())
+                .map(String::from)
+                .collect(),
+            title.trim().to_string(),
+        ),
+        None => (Vec::new(), text.trim().to_string()),
+    }
+}
+
+/// Extended M3U, where each location may follow an #EXTINF:seconds,Artist - Title line. Without
+/// one, the artist and title come from the file name.
+fn read_m3u(contents: &str) -> Vec<LocalTrack> {
+    let mut tracks = Vec::new();
+    let mut info: Option<(Option<i64>, String)> = None;
+
+    for line in contents.lines().map(str::trim) {
+        if let Some(extinf) = line.strip_prefix("#EXTINF:") {
+            let (seconds, text) = extinf.split_once(',').unwrap_or(("", extinf));
+            // -1 stands for an unknown length
+            let duration_ms = seconds
+                .trim()
+                .parse::<i64>()
+                .ok()
+                .filter(|seconds| *seconds > 0)
+                .map(|seconds| seconds * 1000);
+            info = Some((duration_ms, text.to_string()));
+            continue;
+        }
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let (duration_ms, text) = info.take().unwrap_or_else(|| {
+            let file_name = Path::new(line)
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().to_string())
+                .unwrap_or_default();
+            (None, file_name)
+        });
+        tracks.push(LocalTrack {
+            duration_ms,
+            spotify_id: spotify_id(line),
+            ..LocalTrack::from_summary(&text)
+        });
+    }
+
+    tracks
+}
+
+fn read_csv(path: &Path) -> Result<Vec<LocalTrack>, String> {
+    let mut reader = csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .from_path(path)
+        .map_err(|e| e.to_string())?;
+    reader
+        .deserialize::<CsvRow>()
+        .enumerate()
+        .map(|(index, row)| {
+            // the header is line 1
+            let row = row.map_err(|e| format!("line {}: {}", index + 2, e))?;
+            Ok(LocalTrack {
+                title: row.title,
+                artists: row
+                    .artists
+                    .split(';')
+                    .map(str::trim)
+                    .filter(|artist| !artist.is_empty())
+                    .map(String::from)
+                    .collect(),
+                duration_ms: row.duration_ms,
+                isrc: row.isrc.filter(|isrc| !isrc.is_empty()),
+                spotify_id: row.uri.as_deref().and_then(spotify_id),
+            })
+        })
+        .collect()
+}
+
+/// Reads the tracks of an M3U or CSV file, told apart by its extension
+pub fn read(path: &Path) -> Result<Vec<LocalTrack>, String> {
+    let is_csv = path
+        .extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("csv"));
+
+    if is_csv {
+        read_csv(path)
+    } else {
+        let contents = fs::read_to_string(path).map_err(|e| e.to_string())?;
+        Ok(read_m3u(&contents))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use rspotify::prelude::Id;
+
+    use super::*;
+
+    #[test]
+    fn m3u_reads_extinf_lines_and_file_names() {
+        let tracks = read_m3u(
+            "#EXTM3U\n\
+             #EXTINF:215,Daft Punk, Pharrell Williams - Get Lucky\n\
+             music/get lucky.mp3\n\
+             #EXTINF:-1,Untitled\n\
+             untitled.flac\n\
+             \n\
+             music/Air - La Femme d'Argent.mp3\n\
+             #EXTINF:431,The Beatles - Hey Jude\n\
+             spotify:track:0aym2LBJBk9DAYuHHutrIl\n",
+        );
+
+        assert_eq!(tracks.len(), 4);
+        assert_eq!(tracks[0].artists, ["Daft Punk", "Pharrell Williams"]);
+        assert_eq!(tracks[0].title, "Get Lucky");
+        assert_eq!(tracks[0].duration_ms, Some(215_000));
+        assert!(tracks[0].spotify_id.is_none());
+
+        assert!(tracks[1].artists.is_empty());
+        assert_eq!(tracks[1].title, "Untitled");
+        assert_eq!(tracks[1].duration_ms, None);
+
+        assert_eq!(tracks[2].artists, ["Air"]);
+        assert_eq!(tracks[2].title, "La Femme d'Argent");
+
+        assert_eq!(
+            tracks[3].spotify_id.as_ref().map(|id| id.id()),
+            Some("0aym2LBJBk9DAYuHHutrIl")
+        );
+    }
+
+    #[test]
+    fn csv_reads_export_columns() {
+        let path = env::temp_dir().join(format!("sortify-import-{}.csv", std::process::id()));
+        fs::write(
+            &path,
+            "title,artists,album,duration_ms,isrc,uri,added_at\n\
+             Get Lucky,Daft Punk; Pharrell Williams,Random Access Memories,248000,USQX91300108,\
+             spotify:track:69kOkLUCkxIZYexIgSG8rq,2024-01-01T00:00:00+00:00\n\
+             Untitled,,,,,,\n",
+        )
+        .unwrap();
+        let tracks = read(&path);
+        _ = fs::remove_file(&path);
+        let tracks = tracks.unwrap();
+
+        assert_eq!(tracks.len(), 2);
+        assert_eq!(tracks[0].title, "Get Lucky");
+        assert_eq!(tracks[0].artists, ["Daft Punk", "Pharrell Williams"]);
+        assert_eq!(tracks[0].duration_ms, Some(248_000));
+        assert_eq!(tracks[0].isrc.as_deref(), Some("USQX91300108"));
+        assert_eq!(
+            tracks[0].spotify_id.as_ref().map(|id| id.id()),
+            Some("69kOkLUCkxIZYexIgSG8rq")
+        );
+
+        assert!(tracks[1].artists.is_empty());
+        assert_eq!(tracks[1].duration_ms, None);
+        assert_eq!(tracks[1].isrc, None);
+        assert!(tracks[1].spotify_id.is_none());
+    }
+}
@@ -0,0 +1,145 @@
+use std::collections::{HashMap, HashSet};
+
+use rspotify::{
+    model::{ArtistId, FullTrack, PlaylistId, SimplifiedPlaylist, TrackId},
+    prelude::Id,
+    AuthCodePkceSpotify,
+};
+
+use crate::{spotify, storage};
+
+static GENRES_FILE: &str = "genres.json";
+
+/// Finds a playlist by its name, ignoring case, or by its ID
+pub fn find_playlist<'a>(
+    playlists: &'a [SimplifiedPlaylist],
+    name_or_id: &str,
+) -> Option<&'a SimplifiedPlaylist> {
+    playlists
+        .iter()
+        .find(|playlist| playlist.name.to_lowercase() == name_or_id.to_lowercase())
+        .or_else(|| {
+            playlists
+                .iter()
+                .find(|playlist| playlist.id.id() == name_or_id)
+        })
+}
+
+pub fn release_year(track: &FullTrack) -> Option<i32> {
+    track.album.release_date.as_ref()?.get(..4)?.parse().ok()
+}
+
+/// Which tracks are in each of the user's playlists, kept up to date as tracks are sorted
+#[derive(Default)]
+pub struct MembershipIndex {
+    tracks: HashMap<PlaylistId<'static>, Vec<FullTrack>>,
+}
+
+impl MembershipIndex {
+    pub fn build(
+        spotify: &AuthCodePkceSpotify,
+        playlists: &[SimplifiedPlaylist],
+    ) -> MembershipIndex {
+        let tracks = playlists
+            .iter()
+            .map(|playlist| {
+                let id = playlist.id.clone_static();
+                (id.clone(), spotify::tracks_in_playlist(spotify, id))
+            })
+            .collect();
+
+        MembershipIndex { tracks }
+    }
+
+    pub fn tracks(&self, playlist_id: &PlaylistId<'_>) -> &[FullTrack] {
+        self.tracks
+            .get(&playlist_id.clone_static())
+            .map(|tracks| tracks.as_slice())
+            .unwrap_or_default()
+    }
+
+    pub fn all_tracks(&self) -> impl Iterator<Item = &FullTrack> {
+        self.tracks.values().flatten()
+    }
+
+    pub fn add(&mut self, playlist_id: &PlaylistId<'_>, track: &FullTrack) {
+        let tracks = self.tracks.entry(playlist_id.clone_static()).or_default();
+        if !tracks.iter().any(|other| other.id == track.id) {
+            tracks.push(track.clone());
+        }
+    }
+
+    pub fn remove(&mut self, playlist_id: &PlaylistId<'_>, track_id: &TrackId<'_>) {
+        if let Some(tracks) = self.tracks.get_mut(&playlist_id.clone_static()) {
+            tracks.retain(|track| track.id.as_ref() != Some(&track_id.clone_static()));
+        }
+    }
+}
+
+/// Spotify genres of artists, fetched once and kept on disk since they rarely change
+#[derive(Default)]
+pub struct Genres {
+    by_artist: HashMap<String, Vec<String>>,
+}
+
+impl Genres {
+    pub fn load() -> Genres {
+        Genres {
+            by_artist: storage::load_or_set_aside(GENRES_FILE),
+        }
+    }
+
+    /// Fetches the genres of any artists of the tracks that aren't known yet
+    pub fn fetch_missing<'a>(
+        &mut self,
+        spotify: &AuthCodePkceSpotify,
+        tracks: impl Iterator<Item = &'a FullTrack>,
+    ) {
+        let missing: Vec<ArtistId<'static>> = tracks
+            .flat_map(|track| track.artists.iter())
+            .filter_map(|artist| artist.id.clone())
+            .filter(|id| !self.by_artist.contains_key(id.id()))
+            .collect::<HashSet<_>>()
+            .into_iter()
+            .collect();
+
+        if missing.is_empty() {
+            return;
+        }
+        log::info!("Fetching genres of {} artists", missing.len());
+
+        self.by_artist
+            .extend(spotify::artist_genres(spotify, &missing));
+        if let Err(e) = storage::save(GENRES_FILE, &self.by_artist) {
+            log::error!("Failed to save genres: {}", e);
+        }
+    }
+
+    /// Genres of all of the track's artists, without repeats
+    pub fn of_track(&self, track: &FullTrack) -> HashSet<&String> {
+        track
+            .artists
+            .iter()
+            .filter_map(|artist| self.by_artist.get(artist.id.as_ref()?.id()))
+            .flatten()
+            .collect()
+    }
+}
+
+#[cfg(test)]
+impl Genres {
+    /// Genres already known by artist ID, without the file or the API
+    pub fn known(by_artist: &[(&str, &[&str])]) -> Genres {
+        Genres {
+            by_artist: by_artist
+                .iter()
+                .map(|(artist, genres)| {
+                    (
+                        artist.to_string(),
+                        genres.iter().map(|genre| genre.to_string()).collect(),
+                    )
+                })
+                .collect(),
+        }
+    }
+}
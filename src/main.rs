@@ -1,51 +1,337 @@
-use std::{collections::HashMap, ops::ControlFlow};
+use std::collections::HashMap;
 
+use clap::Parser;
+
+mod analysis;
 pub mod audio;
+mod cli;
+mod config;
+mod dsp;
+mod export;
+mod fuzzy;
+mod hooks;
+mod import;
+mod library;
 mod logger;
+mod plan;
+mod presets;
+mod queue;
+mod rules;
+mod scripting;
+mod search;
 mod services;
+mod session;
+mod snapshot;
+mod snooze;
 mod spotify;
+mod storage;
+mod suggestions;
+mod summary;
+#[cfg(test)]
+mod testing;
 mod ui;
 
 fn main() {
+    let cli = cli::Cli::parse();
     let _logger = logger::init(); // logger only works if this is alive
     log::info!("Initialized sortify");
+    let config = config::Config::load();
+
+    // a file of decisions is read before anything else, so mistakes in it show up straight away
+    let rows = match &cli.command {
+        Some(cli::Command::Apply { file }) => match plan::file::read(file) {
+            Ok(rows) => Some(rows),
+            Err(e) => {
+                log::error!("Failed to read decisions from {}: {}", file.display(), e);
+                ui::plan_errors(&[e]);
+                return;
+            }
+        },
+        _ => None,
+    };
+
+    // comparing snapshots needs no account, and the output may be piped elsewhere
+    if let Some(cli::Command::Diff { a, b, live, json }) = &cli.command {
+        let spotify = live.then(spotify::authenticate);
+        services::diff(spotify.as_ref(), a, b.as_deref(), *json);
+        return;
+    }
 
     ui::welcome();
 
     let spotify = spotify::authenticate();
     let playlists = spotify::my_playlists(&spotify);
-    let mut image_cache: HashMap<String, String> = HashMap::new();
+
+    // exporting doesn't change anything, so it's fine to do without asking
+    if let Some(cli::Command::Export {
+        playlists: names,
+        format,
+        output,
+    }) = &cli.command
+    {
+        services::export(&spotify, &playlists, names, *format, output);
+        return;
+    }
+    if let Some(cli::Command::Snapshot { output }) = &cli.command {
+        services::snapshot(&spotify, &playlists, output.as_deref());
+        return;
+    }
+
+    // queries come from stdin in bulk, which leaves no way to confirm the account
+    if let Some(cli::Command::Search { bulk: true }) = &cli.command {
+        let buffer = cli.source.as_ref().or(config.buffer.as_ref());
+        services::search(&spotify, &playlists, buffer, true);
+        return;
+    }
 
     if !ui::confirm_account(spotify::user_name(&spotify)) {
         ui::goodbye(None);
         return;
     }
 
-    let source_playlist_index = ui::choose_source(&playlists);
+    if let Some(cli::Command::Restore { file }) = &cli.command {
+        services::restore(&spotify, &playlists, file, config.auto_snapshots);
+        return;
+    }
+    if let Some(cli::Command::Search { bulk: false }) = &cli.command {
+        let buffer = cli.source.as_ref().or(config.buffer.as_ref());
+        services::search(&spotify, &playlists, buffer, false);
+        return;
+    }
+    if let Some(cli::Command::Import {
+        file,
+        create: Some(name),
+    }) = &cli.command
+    {
+        services::import(&spotify, file, import::Destination::New(name));
+        return;
+    }
+
+    let descriptions = spotify::playlist_descriptions(&spotify);
+    let source_playlist_index = match &cli.source {
+        Some(name) => match library::find_playlist(&playlists, name) {
+            Some(source) => playlists
+                .iter()
+                .position(|playlist| playlist.id == source.id)
+                .unwrap(),
+            None => {
+                ui::unknown_playlist(name);
+                return;
+            }
+        },
+        None => ui::choose_source(&playlists, &descriptions),
+    };
     let source_playlist_id = playlists[source_playlist_index].id.clone_static();
+    let source_playlist_name = playlists[source_playlist_index].name.clone();
     log::info!("Set source playlist, ID is {}", source_playlist_id);
 
-    let tracks = spotify::tracks_in_playlist(&spotify, source_playlist_id.clone_static());
-
-    log::info!("Initializing audio player");
-    let mut audio_player = audio::AudioPlayer::new();
-    if audio_player.is_none() {
-        log::warn!("Failed to initialize audio player");
+    if let Some(cli::Command::Import { file, create: None }) = &cli.command {
+        let source = import::Destination::Source(&playlists[source_playlist_index]);
+        if !services::import(&spotify, file, source) {
+            ui::goodbye(None);
+            return;
+        }
     }
 
-    for track in tracks {
-        if let ControlFlow::Break(_) = services::handle_track(
-            track,
+    let mut items = spotify::items_in_playlist(&spotify, source_playlist_id.clone_static());
+
+    let plan = match rows {
+        Some(rows) => match plan::file::resolve(rows, &items, &playlists, &source_playlist_id) {
+            Ok(plan) => Some(plan),
+            Err(errors) => {
+                log::error!("Failed to resolve decisions against the source playlist");
+                ui::plan_errors(&errors);
+                return;
+            }
+        },
+        None => None,
+    };
+
+    ui::loading("Indexing your playlists");
+    let membership = library::MembershipIndex::build(&spotify, &playlists);
+    // decisions from a file are applied to whichever tracks they're about
+    if plan.is_none() {
+        let queue_config = queue::QueueConfig {
+            order: cli.order.unwrap_or(config.queue.order),
+            added_more_than_days_ago: cli.older_than.or(config.queue.added_more_than_days_ago),
+            added_by: cli.added_by.clone().or(config.queue.added_by),
+            not_in: if cli.not_in.is_empty() {
+                config.queue.not_in
+            } else {
+                cli.not_in.clone()
+            },
+            not_in_any: cli.not_in_any || config.queue.not_in_any,
+        };
+        let total = items.len();
+        items = match queue::prepare(
+            items,
+            &queue_config,
+            &membership,
             &playlists,
-            &mut image_cache,
             &source_playlist_id,
-            &spotify,
-            &mut audio_player,
         ) {
-            ui::goodbye(None);
+            Ok(items) => items,
+            Err(name) => {
+                ui::unknown_playlist(&name);
+                return;
+            }
+        };
+        if items.len() < total {
+            log::info!("Queue filters left {} of {} tracks", items.len(), total);
+            ui::filtered_tracks(total - items.len());
+        }
+    }
+
+    let mut genres = library::Genres::load();
+    genres.fetch_missing(&spotify, membership.all_tracks());
+
+    let rules = match rules::load(&playlists, &source_playlist_id) {
+        Ok(rules) => rules,
+        Err(e) => {
+            log::error!("Failed to load rules: {}", e);
+            ui::rules_error(&e);
             return;
         }
+    };
+    let mut script = match scripting::Script::load() {
+        Ok(script) => script,
+        Err(e) => {
+            log::error!("Failed to load script: {}", e);
+            ui::script_error(&e);
+            return;
+        }
+    };
+    let matches = rules::plan(&rules, &items, &genres);
+    if cli.dry_run {
+        ui::rules_report(&rules, &matches, &items, &playlists);
+        return;
+    }
+
+    services::auto_snapshot(&spotify, &playlists, &membership, config.auto_snapshots);
+
+    let history = suggestions::classifier::History::load();
+    let classifier = suggestions::classifier::Classifier::load(&history);
+    let analysis_cache = analysis::load_cache();
+    // runs until done or until the application exits, whichever comes first
+    _ = suggestions::audio::build_profiles(&playlists, &membership, analysis_cache.clone());
+
+    log::info!("Initializing audio player");
+    let audio_player = audio::AudioPlayer::new();
+    if audio_player.is_none() {
+        log::warn!("Failed to initialize audio player");
+    }
+
+    let (layout, problems) = ui::chooser::Layout::new(config.playlists, config.groups);
+    for problem in problems {
+        log::warn!("Ignoring part of the config: {}", problem);
+        ui::config_warning(&problem);
+    }
+    let presets = presets::Presets::load();
+    if let Some(script) = &mut script {
+        let hotkeys: Vec<char> = layout.hotkeys().collect();
+        for problem in script.drop_taken_keys(&hotkeys, &presets) {
+            log::warn!("Ignoring part of the script: {}", problem);
+            ui::script_warning(&problem);
+        }
+    }
+
+    let summary = summary::Summary::new(
+        source_playlist_name.clone(),
+        membership.tracks(&source_playlist_id).len(),
+    );
+    let mut session = session::Session {
+        spotify,
+        playlists,
+        descriptions,
+        source_playlist_id,
+        membership,
+        genres,
+        history,
+        classifier,
+        audio_player,
+        image_cache: HashMap::new(),
+        analysis_cache,
+        script,
+        hooks: hooks::Hooks::new(config.hooks),
+        layout,
+        presets,
+        snoozed: snooze::Snoozed::load(),
+        snooze_days: config.snooze_days,
+        summary,
+    };
+
+    // decisions from a file are applied as they are
+    let any_matched = plan.is_none() && matches.iter().any(|indices| !indices.is_empty());
+    let items = if any_matched && ui::confirm_rules(&rules, &matches, &items, &session.playlists) {
+        services::apply_rules(items, &rules, &matches, &mut session)
+    } else {
+        items
+    };
+
+    let mut sorted_all = true;
+    if let Some(plan) = &plan {
+        sorted_all = services::sort_from_file(plan, &items, &mut session);
+    } else if cli.edit {
+        sorted_all = services::sort_in_editor(&items, &mut session);
+    } else {
+        // tracks skipped for a while are left for a later session
+        let (snoozed, items): (Vec<_>, Vec<_>) = items
+            .into_iter()
+            .partition(|item| session.snoozed.is_snoozed(&item.track));
+        if !snoozed.is_empty() {
+            ui::snoozed_tracks(snoozed.len());
+        }
+
+        let mut queue = queue::Queue::new(items);
+        while let Some(track) = queue.current() {
+            match services::handle_track(track, &queue, &mut session) {
+                queue::Step::Decided(status) => queue.advance(status),
+                queue::Step::DecidedGroup(positions, status) => {
+                    queue.advance_group(&positions, status)
+                }
+                queue::Step::Previous => {
+                    if let Some(position) = queue.previous() {
+                        queue.jump(position);
+                    }
+                }
+                queue::Step::Jump(position) => queue.jump(position),
+                queue::Step::Quit => {
+                    sorted_all = false;
+                    break;
+                }
+            }
+        }
+    }
+
+    if !session.summary.is_empty() {
+        let source_after = session.membership.tracks(&session.source_playlist_id).len();
+        session.summary.finish(source_after);
+        ui::summary(&session.summary);
+        if !session.summary.failures.is_empty()
+            && ui::utils::confirmation(String::from("Try the failed tracks again?"))
+        {
+            services::retry_failures(&mut session);
+            let source_after = session.membership.tracks(&session.source_playlist_id).len();
+            session.summary.finish(source_after);
+            ui::summary(&session.summary);
+        }
+        if let Some(path) = &cli.summary {
+            match session.summary.write(path) {
+                Ok(()) => ui::summary_written(path),
+                Err(e) => {
+                    log::error!("Failed to write summary to {}: {}", path.display(), e);
+                    ui::summary_error(&e.to_string());
+                }
+            }
+        }
     }
 
-    ui::goodbye(Some(&playlists[source_playlist_index].name));
+    analysis::save_cache(&session.analysis_cache);
+    session.classifier.catch_up(&session.history);
+    session.hooks.emit(hooks::Event::SessionFinished {
+        source_playlist: source_playlist_name.clone(),
+        sorted_all,
+    });
+    session.hooks.finish();
+    ui::goodbye(sorted_all.then_some(&source_playlist_name));
 }
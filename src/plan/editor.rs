@@ -0,0 +1,350 @@
+use std::collections::{HashMap, HashSet};
+
+use rspotify::{
+    model::{PlaylistId, SimplifiedPlaylist},
+    prelude::Id,
+};
+
+use super::Plan;
+use crate::{services::TrackAction, spotify::PlaylistTrack, ui};
+
+static DISCARD: &str = "d";
+static ERROR_PREFIX: &str = "# error: ";
+
+/// Short codes to type in place of playlist names, made of the initials of their words
+fn codes(
+    playlists: &[SimplifiedPlaylist],
+    source_playlist_id: &PlaylistId<'static>,
+) -> Vec<(String, usize)> {
+    let mut used: HashSet<String> = HashSet::from([DISCARD.to_string()]);
+    let mut codes = Vec::new();
+
+    for (index, playlist) in playlists.iter().enumerate() {
+        if &playlist.id == source_playlist_id {
+            continue;
+        }
+
+        let mut initials: String = playlist
+            .name
+            .split_whitespace()
+            .filter_map(|word| word.chars().find(|c| c.is_alphanumeric()))
+            .flat_map(char::to_lowercase)
+            .collect();
+        if initials.is_empty() {
+            initials = String::from("p");
+        }
+
+        let mut code = initials.clone();
+        let mut suffix = 2;
+        while used.contains(&code) {
+            code = format!("{}{}", initials, suffix);
+            suffix += 1;
+        }
+
+        used.insert(code.clone());
+        codes.push((code, index));
+    }
+
+    codes
+}
+
+/// The file the user edits, with a header explaining it and a line per track
+pub fn render(
+    items: &[PlaylistTrack],
+    playlists: &[SimplifiedPlaylist],
+    source_playlist_id: &PlaylistId<'static>,
+) -> String {
+    let codes = codes(playlists, source_playlist_id);
+    let width = codes.iter().map(|(code, _)| code.len()).max().unwrap_or(0);
+
+    let mut text = String::new();
+    text += "# Each line is: playlists | track ID | track\n";
+    text += "#\n";
+    text += "# Fill in the first column with the codes of the playlists to sort a track into,\n";
+    text += "# separated by spaces. Write d to remove it from the source without sorting,\n";
+    text += "# and leave it empty or delete the line to skip it. Save and quit when done.\n";
+    text += "#\n";
+    text += "# Playlists:\n";
+    for (code, index) in &codes {
+        text += &format!("#   {:width$}  {}\n", code, playlists[*index].name);
+    }
+    text += "\n";
+
+    let column = width.max(DISCARD.len()) * 3;
+    // a track in the source more than once gets one line, as a decision is about all its copies
+    let mut written: HashSet<&str> = HashSet::new();
+    for item in items {
+        let Some(id) = &item.track.id else {
+            continue;
+        };
+        if !written.insert(id.id()) {
+            continue;
+        }
+        text += &format!(
+            "{:column$} | {} | {}\n",
+            "",
+            id.id(),
+            ui::track::summary(&item.track)
+        );
+    }
+
+    text
+}
+
+/// Something wrong with a line of the edited file, counting lines from 0
+pub type LineError = (usize, String);
+
+/// Reads back the edited file, or lists everything wrong with it
+pub fn parse(
+    text: &str,
+    items: &[PlaylistTrack],
+    playlists: &[SimplifiedPlaylist],
+    source_playlist_id: &PlaylistId<'static>,
+) -> Result<Plan, Vec<LineError>> {
+    let codes: HashMap<String, usize> = codes(playlists, source_playlist_id).into_iter().collect();
+    let track_indices: HashMap<&str, usize> = items
+        .iter()
+        .enumerate()
+        .filter_map(|(index, item)| Some((item.track.id.as_ref()?.id(), index)))
+        .collect();
+
+    let mut plan = Plan::new();
+    let mut seen: HashSet<usize> = HashSet::new();
+    let mut errors = Vec::new();
+
+    for (number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let mut error = |message: String| errors.push((number, message));
+
+        let columns: Vec<&str> = line.splitn(3, '|').map(str::trim).collect();
+        if columns.len() < 2 {
+            error(String::from("expected playlists | track ID | track"));
+            continue;
+        }
+        let Some(&index) = track_indices.get(columns[1]) else {
+            error(format!(
+                "{} isn't a track in the source playlist",
+                columns[1]
+            ));
+            continue;
+        };
+        if !seen.insert(index) {
+            error(format!("{} is listed more than once", columns[1]));
+            continue;
+        }
+
+        let wanted: Vec<&str> = columns[0]
+            .split(|c: char| c.is_whitespace() || c == ',')
+            .filter(|code| !code.is_empty())
+            .collect();
+        if wanted.is_empty() {
+            continue;
+        }
+        if wanted.contains(&DISCARD) {
+            if wanted.len() > 1 {
+                error(String::from("a discarded track can't also be sorted"));
+            } else {
+                plan.push((
+                    index,
+                    TrackAction::Remove(source_playlist_id.clone_static()),
+                ));
+            }
+            continue;
+        }
+
+        let unknown: Vec<&str> = wanted
+            .iter()
+            .filter(|code| !codes.contains_key(**code))
+            .copied()
+            .collect();
+        if !unknown.is_empty() {
+            error(format!("unknown playlist code(s) {}", unknown.join(", ")));
+            continue;
+        }
+
+        let mut playlist_ids: Vec<PlaylistId<'static>> = Vec::new();
+        for code in wanted {
+            let id = playlists[codes[code]].id.clone_static();
+            if !playlist_ids.contains(&id) {
+                playlist_ids.push(id);
+            }
+        }
+        plan.push((index, TrackAction::Add(playlist_ids)));
+    }
+
+    if errors.is_empty() {
+        Ok(plan)
+    } else {
+        Err(errors)
+    }
+}
+
+/// The edited file with each error above the line it's about, replacing those of earlier attempts
+pub fn with_errors(text: &str, errors: &[LineError]) -> String {
+    let mut annotated = String::new();
+    for (number, line) in text.lines().enumerate() {
+        if line.starts_with(ERROR_PREFIX) {
+            continue;
+        }
+        for (_, message) in errors.iter().filter(|(line, _)| *line == number) {
+            annotated += &format!("{}{}\n", ERROR_PREFIX, message);
+        }
+        annotated += line;
+        annotated += "\n";
+    }
+    annotated
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{plan::is_complete, testing};
+
+    const SOURCE: &str = "0000000000000000000000";
+
+    fn playlists() -> Vec<SimplifiedPlaylist> {
+        vec![
+            testing::playlist(SOURCE, "Inbox"),
+            testing::playlist("0000000000000000000001", "Jazz classics"),
+            testing::playlist("0000000000000000000002", "Chill"),
+            testing::playlist("0000000000000000000003", "Cosy"),
+            testing::playlist("0000000000000000000004", "Disco"),
+        ]
+    }
+
+    fn source() -> PlaylistId<'static> {
+        PlaylistId::from_id(SOURCE).unwrap()
+    }
+
+    fn items(ids: &[u8]) -> Vec<PlaylistTrack> {
+        ids.iter()
+            .map(|id| PlaylistTrack {
+                track: testing::track(&format!("{:022}", id), "Track", "Artist", 1),
+                added_at: None,
+                added_by: None,
+            })
+            .collect()
+    }
+
+    /// The rendered file with the playlists column of the track's line filled in
+    fn fill(text: &str, track: u8, codes: &str) -> String {
+        let id = format!("| {:022} |", track);
+        text.lines()
+            .map(|line| {
+                if line.contains(&id) {
+                    format!("{} {}\n", codes, line.trim_start())
+                } else {
+                    format!("{}\n", line)
+                }
+            })
+            .collect()
+    }
+
+    fn parsed(text: &str, items: &[PlaylistTrack]) -> Result<Plan, Vec<LineError>> {
+        parse(text, items, &playlists(), &source())
+    }
+
+    fn line_of(text: &str, track: u8) -> usize {
+        let id = format!("{:022}", track);
+        text.lines().position(|line| line.contains(&id)).unwrap()
+    }
+
+    #[test]
+    fn codes_are_initials_made_unique() {
+        let codes: Vec<String> = codes(&playlists(), &source())
+            .into_iter()
+            .map(|(code, _)| code)
+            .collect();
+        // the source gets none, and d is taken by discarding
+        assert_eq!(codes, ["jc", "c", "c2", "d2"]);
+    }
+
+    #[test]
+    fn filled_in_file_reads_back_as_a_plan() {
+        let items = items(&[1, 2, 3, 4]);
+        let text = render(&items, &playlists(), &source());
+        let text = fill(&text, 1, "jc c");
+        let text = fill(&text, 2, "d");
+        let text = fill(&text, 4, "c2,c2");
+
+        let plan = parsed(&text, &items).unwrap();
+        let playlist_id = |index: usize| playlists()[index].id.clone_static();
+        assert_eq!(
+            plan,
+            [
+                (0, TrackAction::Add(vec![playlist_id(1), playlist_id(2)])),
+                (1, TrackAction::Remove(source())),
+                (3, TrackAction::Add(vec![playlist_id(3)])),
+            ]
+        );
+        assert!(!is_complete(&plan, &items));
+    }
+
+    #[test]
+    fn unknown_codes_are_errors() {
+        let items = items(&[1]);
+        let text = fill(&render(&items, &playlists(), &source()), 1, "jc zz");
+
+        assert_eq!(
+            parsed(&text, &items),
+            Err(vec![(
+                line_of(&text, 1),
+                String::from("unknown playlist code(s) zz")
+            )])
+        );
+    }
+
+    #[test]
+    fn discarding_and_sorting_are_errors() {
+        let items = items(&[1]);
+        let text = fill(&render(&items, &playlists(), &source()), 1, "d jc");
+
+        assert_eq!(
+            parsed(&text, &items),
+            Err(vec![(
+                line_of(&text, 1),
+                String::from("a discarded track can't also be sorted")
+            )])
+        );
+    }
+
+    #[test]
+    fn lines_for_the_same_track_are_errors() {
+        let items = items(&[1]);
+        let text = fill(&render(&items, &playlists(), &source()), 1, "jc");
+        let line = text.lines().nth(line_of(&text, 1)).unwrap().to_string();
+        let text = format!("{}{}\n", text, line);
+
+        let errors = parsed(&text, &items).unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].0, text.lines().count() - 1);
+        assert!(errors[0].1.ends_with("is listed more than once"));
+    }
+
+    #[test]
+    fn tracks_in_the_source_twice_get_one_line() {
+        let items = items(&[1, 2, 1]);
+        let text = render(&items, &playlists(), &source());
+        let id = format!("{:022}", 1);
+        assert_eq!(text.lines().filter(|line| line.contains(&id)).count(), 1);
+
+        let text = fill(&fill(&text, 1, "jc"), 2, "c");
+        let plan = parsed(&text, &items).unwrap();
+        assert_eq!(plan.len(), 2);
+        assert!(is_complete(&plan, &items));
+    }
+
+    #[test]
+    fn errors_go_above_their_lines_replacing_old_ones() {
+        let text = "first\nsecond\n";
+        let once = with_errors(text, &[(1, String::from("wrong"))]);
+        assert_eq!(once, "first\n# error: wrong\nsecond\n");
+
+        // lines are counted in the file as edited, which still has the old errors
+        let twice = with_errors(&once, &[(2, String::from("still wrong"))]);
+        assert_eq!(twice, "first\n# error: still wrong\nsecond\n");
+    }
+}
@@ -0,0 +1,314 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    path::Path,
+};
+
+use rspotify::{
+    model::{PlaylistId, SimplifiedPlaylist},
+    prelude::Id,
+};
+use serde::Deserialize;
+
+use super::Plan;
+use crate::{
+    library::find_playlist,
+    services::TrackAction,
+    spotify::{self, PlaylistTrack},
+};
+
+#[derive(Deserialize, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+enum RowAction {
+    Add,
+    Discard,
+    Skip,
+}
+
+/// A decision as written in a JSON file
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Row {
+    /// Spotify URI, link or ID of the track
+    track: String,
+    #[serde(default)]
+    playlists: Vec<String>,
+    /// Adding is assumed when there are playlists to add to
+    action: Option<RowAction>,
+}
+
+/// A decision as written in a CSV file, where playlists are separated by semicolons
+#[derive(Deserialize)]
+struct CsvRow {
+    track: String,
+    #[serde(default)]
+    playlists: String,
+    action: Option<RowAction>,
+}
+
+impl From<CsvRow> for Row {
+    fn from(row: CsvRow) -> Row {
+        Row {
+            track: row.track,
+            playlists: row
+                .playlists
+                .split(';')
+                .map(str::trim)
+                .filter(|name| !name.is_empty())
+                .map(String::from)
+                .collect(),
+            action: row.action,
+        }
+    }
+}
+
+/// Decisions labelled by where they are in the file, for error messages
+pub type Rows = Vec<(String, Row)>;
+
+/// Reads decisions prepared elsewhere, which needs nothing from Spotify so it can be done first
+pub fn read(path: &Path) -> Result<Rows, String> {
+    let is_json = path
+        .extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("json"));
+
+    if is_json {
+        let contents = fs::read_to_string(path).map_err(|e| e.to_string())?;
+        let rows: Vec<Row> = serde_json::from_str(&contents).map_err(|e| e.to_string())?;
+        Ok(rows
+            .into_iter()
+            .enumerate()
+            .map(|(index, row)| (format!("entry {}", index + 1), row))
+            .collect())
+    } else {
+        let mut reader = csv::ReaderBuilder::new()
+            .trim(csv::Trim::All)
+            .from_path(path)
+            .map_err(|e| e.to_string())?;
+        reader
+            .deserialize::<CsvRow>()
+            .enumerate()
+            .map(|(index, row)| {
+                // the header is line 1
+                let label = format!("line {}", index + 2);
+                row.map(|row| (label.clone(), Row::from(row)))
+                    .map_err(|e| format!("{}: {}", label, e))
+            })
+            .collect()
+    }
+}
+
+/// Resolves the decisions' tracks against the source playlist and playlists by name or ID, or
+/// lists everything wrong with them
+pub fn resolve(
+    rows: Rows,
+    items: &[PlaylistTrack],
+    playlists: &[SimplifiedPlaylist],
+    source_playlist_id: &PlaylistId<'static>,
+) -> Result<Plan, Vec<String>> {
+    let track_indices: HashMap<&str, usize> = items
+        .iter()
+        .enumerate()
+        .filter_map(|(index, item)| Some((item.track.id.as_ref()?.id(), index)))
+        .collect();
+
+    let mut plan = Plan::new();
+    let mut seen: HashSet<usize> = HashSet::new();
+    let mut errors = Vec::new();
+
+    for (label, row) in rows {
+        let mut error = |message: String| errors.push(format!("{}: {}", label, message));
+
+        let id = spotify::track_id(&row.track);
+        let Some(&index) = track_indices.get(id) else {
+            error(format!(
+                "{} isn't a track in the source playlist",
+                row.track
+            ));
+            continue;
+        };
+        if !seen.insert(index) {
+            error(format!("{} is listed more than once", row.track));
+            continue;
+        }
+
+        let action = row.action.unwrap_or(if row.playlists.is_empty() {
+            RowAction::Skip
+        } else {
+            RowAction::Add
+        });
+        match action {
+            RowAction::Add if row.playlists.is_empty() => {
+                error(String::from("there are no playlists to add to"))
+            }
+            RowAction::Add => {
+                let resolved: Result<Vec<PlaylistId<'static>>, String> = row
+                    .playlists
+                    .iter()
+                    .map(|name| match find_playlist(playlists, name) {
+                        Some(playlist) if &playlist.id == source_playlist_id => {
+                            Err(format!("{} is the source playlist", name))
+                        }
+                        Some(playlist) => Ok(playlist.id.clone_static()),
+                        None => Err(format!("no playlist called {}", name)),
+                    })
+                    .collect();
+
+                match resolved {
+                    Ok(resolved) => {
+                        let mut playlist_ids: Vec<PlaylistId<'static>> = Vec::new();
+                        for id in resolved {
+                            if !playlist_ids.contains(&id) {
+                                playlist_ids.push(id);
+                            }
+                        }
+                        plan.push((index, TrackAction::Add(playlist_ids)));
+                    }
+                    Err(e) => error(e),
+                }
+            }
+            RowAction::Discard if !row.playlists.is_empty() => {
+                error(String::from("a discarded track can't also be sorted"))
+            }
+            RowAction::Discard => plan.push((
+                index,
+                TrackAction::Remove(source_playlist_id.clone_static()),
+            )),
+            RowAction::Skip => (),
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(plan)
+    } else {
+        Err(errors)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use super::*;
+    use crate::testing;
+
+    const SOURCE: &str = "0000000000000000000000";
+
+    fn playlists() -> Vec<SimplifiedPlaylist> {
+        vec![
+            testing::playlist(SOURCE, "Inbox"),
+            testing::playlist("0000000000000000000001", "Jazz classics"),
+            testing::playlist("0000000000000000000002", "Chill"),
+        ]
+    }
+
+    fn source() -> PlaylistId<'static> {
+        PlaylistId::from_id(SOURCE).unwrap()
+    }
+
+    fn playlist_id(index: usize) -> PlaylistId<'static> {
+        playlists()[index].id.clone_static()
+    }
+
+    fn items() -> Vec<PlaylistTrack> {
+        (1..=4)
+            .map(|id| PlaylistTrack {
+                track: testing::track(&format!("{:022}", id), "Track", "Artist", 1),
+                added_at: None,
+                added_by: None,
+            })
+            .collect()
+    }
+
+    /// Reads the contents as a file with the given name, in a directory of its own
+    fn read_as(name: &str, contents: &str) -> Result<Rows, String> {
+        let directory = env::temp_dir().join(format!("sortify-plan-{}", std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        let path = directory.join(name);
+        fs::write(&path, contents).unwrap();
+        let rows = read(&path);
+        _ = fs::remove_file(&path);
+        rows
+    }
+
+    fn resolved(rows: Rows) -> Result<Plan, Vec<String>> {
+        resolve(rows, &items(), &playlists(), &source())
+    }
+
+    #[test]
+    fn csv_and_json_read_the_same() {
+        let csv = read_as(
+            "same.csv",
+            "track,playlists,action\n\
+             spotify:track:0000000000000000000001,Jazz classics; chill,\n\
+             https://open.spotify.com/track/0000000000000000000002?si=x,,discard\n\
+             0000000000000000000003,,\n",
+        )
+        .unwrap();
+        let json = read_as(
+            "same.json",
+            r#"[
+                {
+                    "track": "spotify:track:0000000000000000000001",
+                    "playlists": ["Jazz classics", "chill"]
+                },
+                {
+                    "track": "https://open.spotify.com/track/0000000000000000000002?si=x",
+                    "action": "discard"
+                },
+                { "track": "0000000000000000000003" }
+            ]"#,
+        )
+        .unwrap();
+
+        let labels =
+            |rows: &Rows| -> Vec<String> { rows.iter().map(|(label, _)| label.clone()).collect() };
+        assert_eq!(labels(&csv), ["line 2", "line 3", "line 4"]);
+        assert_eq!(labels(&json), ["entry 1", "entry 2", "entry 3"]);
+
+        let expected = vec![
+            (0, TrackAction::Add(vec![playlist_id(1), playlist_id(2)])),
+            (1, TrackAction::Remove(source())),
+        ];
+        assert_eq!(resolved(csv), Ok(expected.clone()));
+        assert_eq!(resolved(json), Ok(expected));
+    }
+
+    #[test]
+    fn unreadable_files_say_where() {
+        let csv = read_as("bad.csv", "track,playlists,action\n1,,add\n2,,later\n");
+        assert!(csv.is_err_and(|e| e.starts_with("line 3:")));
+
+        let json = read_as("bad.json", r#"[{ "track": "1", "playlist": [] }]"#);
+        assert!(json.is_err_and(|e| e.contains("playlist")));
+    }
+
+    #[test]
+    fn every_problem_is_listed() {
+        let rows = read_as(
+            "problems.json",
+            r#"[
+                { "track": "0000000000000000000009", "playlists": ["Chill"] },
+                { "track": "0000000000000000000001", "playlists": ["Rock"] },
+                { "track": "0000000000000000000002", "playlists": ["Inbox"] },
+                { "track": "0000000000000000000002", "playlists": ["Chill"] },
+                { "track": "0000000000000000000003", "action": "add" },
+                { "track": "0000000000000000000004", "playlists": ["Chill"], "action": "discard" }
+            ]"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            resolved(rows),
+            Err(vec![
+                String::from(
+                    "entry 1: 0000000000000000000009 isn't a track in the source playlist"
+                ),
+                String::from("entry 2: no playlist called Rock"),
+                String::from("entry 3: Inbox is the source playlist"),
+                String::from("entry 4: 0000000000000000000002 is listed more than once"),
+                String::from("entry 5: there are no playlists to add to"),
+                String::from("entry 6: a discarded track can't also be sorted"),
+            ])
+        );
+    }
+}
@@ -0,0 +1,22 @@
+pub mod editor;
+pub mod file;
+
+use std::collections::HashSet;
+
+use rspotify::prelude::Id;
+
+use crate::{services::TrackAction, spotify::PlaylistTrack};
+
+/// A decision for each of the tracks that aren't skipped, as pairs of index into the source
+/// playlist's items and what to do with it
+pub type Plan = Vec<(usize, TrackAction)>;
+
+/// Whether the plan decides on every track of the source there is to decide on, which is each
+/// track with an ID once however often it's listed
+pub fn is_complete(plan: &Plan, items: &[PlaylistTrack]) -> bool {
+    let track_ids: HashSet<&str> = items
+        .iter()
+        .filter_map(|item| Some(item.track.id.as_ref()?.id()))
+        .collect();
+    plan.len() == track_ids.len()
+}
@@ -0,0 +1,56 @@
+use rspotify::{model::PlaylistId, prelude::Id};
+use serde::{Deserialize, Serialize};
+
+use crate::storage;
+
+static PRESETS_FILE: &str = "presets.json";
+
+/// Playlists that tracks often go into together, checked with one key
+#[derive(Serialize, Deserialize)]
+pub struct Preset {
+    pub name: String,
+    pub key: char,
+    /// IDs, so renaming a playlist doesn't matter
+    pub playlists: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+#[serde(default)]
+pub struct Presets {
+    pub presets: Vec<Preset>,
+    /// IDs of the playlists the last track went into
+    pub previous: Vec<String>,
+}
+
+fn ids(playlists: &[PlaylistId]) -> Vec<String> {
+    playlists.iter().map(|id| id.id().to_string()).collect()
+}
+
+impl Presets {
+    pub fn load() -> Presets {
+        storage::load_or_set_aside(PRESETS_FILE)
+    }
+
+    fn save(&self) {
+        if let Err(e) = storage::save(PRESETS_FILE, self) {
+            log::error!("Failed to save presets: {}", e);
+        }
+    }
+
+    /// Adds a preset, in place of any with the same key
+    pub fn add(&mut self, name: String, key: char, playlists: &[PlaylistId]) {
+        log::info!("Saving preset {} on key {}", name, key);
+        self.presets.retain(|preset| preset.key != key);
+        self.presets.push(Preset {
+            name,
+            key,
+            playlists: ids(playlists),
+        });
+        self.save();
+    }
+
+    pub fn remember(&mut self, playlists: &[PlaylistId]) {
+        self.previous = ids(playlists);
+        self.save();
+    }
+}
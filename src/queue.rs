@@ -0,0 +1,383 @@
+use std::{
+    cmp::Reverse,
+    hash::{BuildHasher, RandomState},
+};
+
+use chrono::{TimeDelta, Utc};
+use clap::ValueEnum;
+use rspotify::model::{FullTrack, PlaylistId, SimplifiedPlaylist};
+use serde::Deserialize;
+
+use crate::{
+    library::{find_playlist, MembershipIndex},
+    spotify::PlaylistTrack,
+};
+
+#[derive(ValueEnum, Deserialize, Clone, Copy, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Order {
+    /// As in the source playlist
+    #[default]
+    Playlist,
+    /// Added to the source playlist longest ago first
+    Oldest,
+    Newest,
+    Shuffle,
+    /// By the name of the first artist, keeping each artist's tracks together
+    Artist,
+    /// Album by album, in track order
+    Album,
+}
+
+/// Which tracks of the source playlist to sort, and in what order
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct QueueConfig {
+    pub order: Order,
+    pub added_more_than_days_ago: Option<i64>,
+    /// User ID of whoever added the track to the source playlist
+    pub added_by: Option<String>,
+    /// Names or IDs of playlists whose tracks are left out
+    pub not_in: Vec<String>,
+    /// Leaves out tracks already in any playlist other than the source
+    pub not_in_any: bool,
+}
+
+/// Filters and orders the tracks of the source playlist as configured
+pub fn prepare(
+    mut items: Vec<PlaylistTrack>,
+    config: &QueueConfig,
+    membership: &MembershipIndex,
+    playlists: &[SimplifiedPlaylist],
+    source_playlist_id: &PlaylistId<'static>,
+) -> Result<Vec<PlaylistTrack>, String> {
+    let excluded: Vec<PlaylistId> = if config.not_in_any {
+        playlists
+            .iter()
+            .map(|playlist| playlist.id.clone_static())
+            .filter(|id| id != source_playlist_id)
+            .collect()
+    } else {
+        config
+            .not_in
+            .iter()
+            .map(|name| {
+                find_playlist(playlists, name)
+                    .map(|playlist| playlist.id.clone_static())
+                    .ok_or(name.clone())
+            })
+            .collect::<Result<_, _>>()?
+    };
+    let is_in = |track: &FullTrack, id: &PlaylistId| {
+        membership
+            .tracks(id)
+            .iter()
+            .any(|other| other.id.is_some() && other.id == track.id)
+    };
+
+    items.retain(|item| {
+        let age = item.added_at.map(|added_at| Utc::now() - added_at);
+        config
+            .added_more_than_days_ago
+            .is_none_or(|days| age.is_some_and(|age| age > TimeDelta::days(days)))
+            && config.added_by.as_ref().is_none_or(|user| {
+                item.added_by
+                    .as_ref()
+                    .is_some_and(|added_by| added_by.eq_ignore_ascii_case(user))
+            })
+            && !excluded.iter().any(|id| is_in(&item.track, id))
+    });
+
+    // sorting is stable, so tracks otherwise equal keep the playlist's order
+    let first_artist = |item: &PlaylistTrack| {
+        item.track
+            .artists
+            .first()
+            .map(|artist| artist.name.to_lowercase())
+            .unwrap_or_default()
+    };
+    match config.order {
+        Order::Playlist => (),
+        Order::Oldest => items.sort_by_key(|item| item.added_at),
+        Order::Newest => items.sort_by_key(|item| Reverse(item.added_at)),
+        Order::Shuffle => {
+            // hashing with a random key is random enough for an order, without another crate
+            let state = RandomState::new();
+            items.sort_by_cached_key(|item| state.hash_one(&item.track.id));
+        }
+        Order::Artist => items.sort_by_cached_key(first_artist),
+        Order::Album => items.sort_by_cached_key(|item| {
+            (
+                item.track.album.name.to_lowercase(),
+                item.track
+                    .album
+                    .artists
+                    .first()
+                    .map(|artist| artist.name.to_lowercase()),
+                item.track.disc_number,
+                item.track.track_number,
+            )
+        }),
+    }
+
+    Ok(items)
+}
+
+/// What has happened to a track of the source playlist this session
+#[derive(Clone, Copy, PartialEq)]
+pub enum Status {
+    Pending,
+    Sorted,
+    Removed,
+    Skipped,
+    /// Comes back once the pending tracks are done
+    Deferred,
+}
+
+impl Status {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Status::Pending => "pending",
+            Status::Sorted => "sorted",
+            Status::Removed => "removed",
+            Status::Skipped => "skipped",
+            Status::Deferred => "deferred",
+        }
+    }
+
+    /// Whether the track is still in the source playlist, so it can be decided on (again)
+    pub fn is_open(&self) -> bool {
+        matches!(self, Status::Pending | Status::Skipped | Status::Deferred)
+    }
+}
+
+/// Where to go after the track on screen
+pub enum Step {
+    Decided(Status),
+    /// For the tracks at these positions in the queue, the current one among them
+    DecidedGroup(Vec<usize>, Status),
+    Previous,
+    /// To the track at this position in the queue
+    Jump(usize),
+    Quit,
+}
+
+/// Pending tracks right after the current one that share its album or, failing that, its
+/// artist, to decide on all at once
+pub struct Group<'a> {
+    /// What they share, e.g. "from the album Blue" or "by Joni Mitchell"
+    pub shared: String,
+    /// Positions in the queue, the current track's first
+    pub tracks: Vec<(usize, &'a FullTrack)>,
+}
+
+/// The tracks of the source playlist, in the order they're sorted, and how far along the user is
+pub struct Queue {
+    items: Vec<PlaylistTrack>,
+    statuses: Vec<Status>,
+    position: usize,
+}
+
+impl Queue {
+    pub fn new(items: Vec<PlaylistTrack>) -> Queue {
+        Queue {
+            statuses: vec![Status::Pending; items.len()],
+            items,
+            position: 0,
+        }
+    }
+
+    /// The track to decide on, if any are left
+    pub fn current(&self) -> Option<&FullTrack> {
+        self.statuses
+            .get(self.position)
+            .filter(|status| **status == Status::Pending)
+            .map(|_| &self.items[self.position].track)
+    }
+
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    pub fn track(&self, position: usize) -> &FullTrack {
+        &self.items[position].track
+    }
+
+    pub fn total(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn tracks(&self) -> impl Iterator<Item = (&FullTrack, Status)> {
+        self.items
+            .iter()
+            .map(|item| &item.track)
+            .zip(self.statuses.iter().copied())
+    }
+
+    /// The closest earlier track still in the source playlist
+    pub fn previous(&self) -> Option<usize> {
+        (0..self.position)
+            .rev()
+            .find(|&i| self.statuses[i].is_open())
+    }
+
+    pub fn status(&self, position: usize) -> Option<Status> {
+        self.statuses.get(position).copied()
+    }
+
+    pub fn is_open(&self, position: usize) -> bool {
+        self.status(position).is_some_and(|status| status.is_open())
+    }
+
+    /// Moves to a track still in the source playlist, to decide on it again if it was skipped or
+    /// deferred
+    pub fn jump(&mut self, position: usize) {
+        if self.is_open(position) {
+            self.statuses[position] = Status::Pending;
+            self.position = position;
+        }
+    }
+
+    pub fn group(&self) -> Option<Group<'_>> {
+        let current = self.current()?;
+        let run = |same: &dyn Fn(&FullTrack) -> bool| -> Vec<(usize, &FullTrack)> {
+            (self.position..self.items.len())
+                .take_while(|&i| {
+                    let track = &self.items[i].track;
+                    self.statuses[i] == Status::Pending && track.id.is_some() && same(track)
+                })
+                .map(|i| (i, &self.items[i].track))
+                .collect()
+        };
+
+        let by_album =
+            run(&|track| current.album.id.is_some() && track.album.id == current.album.id);
+        if by_album.len() > 1 {
+            return Some(Group {
+                shared: format!("from the album {}", current.album.name),
+                tracks: by_album,
+            });
+        }
+        let artist = current
+            .artists
+            .first()
+            .filter(|artist| artist.id.is_some())?;
+        let by_artist = run(&|track| {
+            track
+                .artists
+                .first()
+                .is_some_and(|other| other.id == artist.id)
+        });
+        (by_artist.len() > 1).then(|| Group {
+            shared: format!("by {}", artist.name),
+            tracks: by_artist,
+        })
+    }
+
+    /// Records what happened to the tracks of a group, and moves on as after a single one
+    pub fn advance_group(&mut self, positions: &[usize], status: Status) {
+        for position in positions {
+            self.statuses[*position] = status;
+        }
+        self.advance(status);
+    }
+
+    /// Records what happened to the current track and moves on to the next pending one, coming
+    /// back round to any jumped over, then to those deferred
+    pub fn advance(&mut self, status: Status) {
+        self.statuses[self.position] = status;
+        let len = self.items.len();
+        // both searches start after the current track, so one just deferred comes back last
+        let after = |status: Status| {
+            (1..=len)
+                .map(|offset| (self.position + offset) % len)
+                .find(|&i| self.statuses[i] == status)
+        };
+        let next = after(Status::Pending).or_else(|| after(Status::Deferred));
+        match next {
+            Some(position) => self.jump(position),
+            None => self.position = len,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::track;
+
+    fn queue(len: usize) -> Queue {
+        Queue::new(
+            (0..len)
+                .map(|i| PlaylistTrack {
+                    track: track(&format!("{:022}", i), &format!("Track {}", i), "Artist", 1),
+                    added_at: None,
+                    added_by: None,
+                })
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn advance_moves_to_the_next_pending_track() {
+        let mut queue = queue(3);
+        queue.advance(Status::Sorted);
+        assert_eq!(queue.position(), 1);
+        queue.advance(Status::Removed);
+        assert_eq!(queue.position(), 2);
+        queue.advance(Status::Skipped);
+        assert!(queue.current().is_none());
+    }
+
+    #[test]
+    fn advance_comes_back_round_to_tracks_jumped_over() {
+        let mut queue = queue(4);
+        queue.jump(2);
+        queue.advance(Status::Sorted);
+        assert_eq!(queue.position(), 3);
+        queue.advance(Status::Sorted);
+        assert_eq!(queue.position(), 0);
+        queue.advance(Status::Sorted);
+        assert_eq!(queue.position(), 1);
+    }
+
+    #[test]
+    fn deferred_tracks_come_after_pending_ones() {
+        let mut queue = queue(3);
+        queue.advance(Status::Deferred);
+        assert_eq!(queue.position(), 1);
+        queue.advance(Status::Sorted);
+        queue.advance(Status::Sorted);
+        assert_eq!(queue.position(), 0);
+        assert!(queue.current().is_some());
+        queue.advance(Status::Sorted);
+        assert!(queue.current().is_none());
+    }
+
+    #[test]
+    fn deferred_tracks_come_back_in_turn() {
+        let mut queue = queue(3);
+        queue.advance(Status::Deferred);
+        queue.advance(Status::Deferred);
+        queue.advance(Status::Sorted);
+        assert_eq!(queue.position(), 0);
+        // deferring it again moves on to the other one rather than straight back to it
+        queue.advance(Status::Deferred);
+        assert_eq!(queue.position(), 1);
+        queue.advance(Status::Deferred);
+        assert_eq!(queue.position(), 0);
+    }
+
+    #[test]
+    fn previous_and_jump_only_reach_open_tracks() {
+        let mut queue = queue(4);
+        queue.advance(Status::Skipped);
+        queue.advance(Status::Sorted);
+        assert_eq!(queue.previous(), Some(0));
+        queue.jump(1);
+        assert_eq!(queue.position(), 2);
+        queue.jump(0);
+        assert_eq!(queue.position(), 0);
+        assert!(queue.current().is_some());
+    }
+}
@@ -0,0 +1,359 @@
+use std::fs;
+
+use chrono::{TimeDelta, Utc};
+use rspotify::model::{PlaylistId, SimplifiedPlaylist};
+use serde::Deserialize;
+
+use crate::{
+    library::{find_playlist, release_year, Genres},
+    services::TrackAction,
+    spotify::PlaylistTrack,
+    storage,
+};
+
+static RULES_FILE: &str = "rules.json";
+
+/// All conditions that are set must hold for a rule to match. Text is matched ignoring case.
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct Conditions {
+    /// Any of the track's artists is called this
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    pub released_from: Option<i32>,
+    pub released_until: Option<i32>,
+    /// Any of the artists' genres contains this, e.g. "rock" matches "indie rock"
+    pub genre: Option<String>,
+    /// User ID of whoever added the track to the source playlist
+    pub added_by: Option<String>,
+    pub added_more_than_days_ago: Option<i64>,
+    pub added_less_than_days_ago: Option<i64>,
+    pub explicit: Option<bool>,
+    pub min_duration_seconds: Option<i64>,
+    pub max_duration_seconds: Option<i64>,
+    /// Spotify's popularity, from 0 to 100
+    pub min_popularity: Option<u32>,
+    pub max_popularity: Option<u32>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "lowercase", deny_unknown_fields)]
+enum RuleAction {
+    /// Names or IDs of the playlists to sort the track into
+    Add(Vec<String>),
+    /// Remove from the source without sorting
+    Discard,
+    /// Leave the track in the source and don't ask about it this session
+    Skip,
+}
+
+/// A rule as written in the rules file
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RuleSpec {
+    name: String,
+    #[serde(default)]
+    when: Conditions,
+    then: RuleAction,
+}
+
+pub struct Rule {
+    pub name: String,
+    pub conditions: Conditions,
+    pub action: TrackAction,
+}
+
+fn same_text(a: &str, b: &str) -> bool {
+    a.to_lowercase() == b.to_lowercase()
+}
+
+impl Conditions {
+    pub fn matches(&self, item: &PlaylistTrack, genres: &Genres) -> bool {
+        let track = &item.track;
+        let year = release_year(track);
+        let age = item.added_at.map(|added_at| Utc::now() - added_at);
+        let duration = track.duration.num_seconds();
+
+        self.artist.as_ref().is_none_or(|artist| {
+            track
+                .artists
+                .iter()
+                .any(|other| same_text(&other.name, artist))
+        }) && self
+            .album
+            .as_ref()
+            .is_none_or(|album| same_text(&track.album.name, album))
+            && self
+                .released_from
+                .is_none_or(|from| year.is_some_and(|year| year >= from))
+            && self
+                .released_until
+                .is_none_or(|until| year.is_some_and(|year| year <= until))
+            && self.genre.as_ref().is_none_or(|genre| {
+                genres
+                    .of_track(track)
+                    .iter()
+                    .any(|other| other.to_lowercase().contains(&genre.to_lowercase()))
+            })
+            && self.added_by.as_ref().is_none_or(|user| {
+                item.added_by
+                    .as_ref()
+                    .is_some_and(|added_by| same_text(added_by, user))
+            })
+            && self
+                .added_more_than_days_ago
+                .is_none_or(|days| age.is_some_and(|age| age > TimeDelta::days(days)))
+            && self
+                .added_less_than_days_ago
+                .is_none_or(|days| age.is_some_and(|age| age < TimeDelta::days(days)))
+            && self
+                .explicit
+                .is_none_or(|explicit| track.explicit == explicit)
+            && self.min_duration_seconds.is_none_or(|min| duration >= min)
+            && self.max_duration_seconds.is_none_or(|max| duration <= max)
+            && self
+                .min_popularity
+                .is_none_or(|min| track.popularity >= min)
+            && self
+                .max_popularity
+                .is_none_or(|max| track.popularity <= max)
+    }
+}
+
+fn resolve(
+    spec: RuleSpec,
+    playlists: &[SimplifiedPlaylist],
+    source_playlist_id: &PlaylistId<'static>,
+) -> Result<Rule, String> {
+    let action = match spec.then {
+        RuleAction::Add(names) => TrackAction::Add(
+            names
+                .iter()
+                .map(|name| {
+                    find_playlist(playlists, name)
+                        .map(|playlist| playlist.id.clone_static())
+                        .ok_or(format!("Rule '{}': no playlist called {}", spec.name, name))
+                })
+                .collect::<Result<Vec<PlaylistId<'static>>, String>>()?,
+        ),
+        RuleAction::Discard => TrackAction::Remove(source_playlist_id.clone_static()),
+        RuleAction::Skip => TrackAction::Skip,
+    };
+
+    Ok(Rule {
+        name: spec.name,
+        conditions: spec.when,
+        action,
+    })
+}
+
+/// Reads the optional rules file from the data directory, resolving playlist names
+pub fn load(
+    playlists: &[SimplifiedPlaylist],
+    source_playlist_id: &PlaylistId<'static>,
+) -> Result<Vec<Rule>, String> {
+    let path = storage::path(RULES_FILE);
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+
+    let contents = fs::read_to_string(&path).map_err(|e| e.to_string())?;
+    let specs: Vec<RuleSpec> =
+        serde_json::from_str(&contents).map_err(|e| format!("{}: {}", path.display(), e))?;
+
+    specs
+        .into_iter()
+        .map(|spec| resolve(spec, playlists, source_playlist_id))
+        .collect()
+}
+
+/// For each rule, the indices of the items it decides on. Each item is decided by the first rule
+/// that matches it, and items no rule matches are left out.
+pub fn plan(rules: &[Rule], items: &[PlaylistTrack], genres: &Genres) -> Vec<Vec<usize>> {
+    let mut plan: Vec<Vec<usize>> = vec![Vec::new(); rules.len()];
+
+    for (index, item) in items.iter().enumerate() {
+        if let Some(rule) = rules
+            .iter()
+            .position(|rule| rule.conditions.matches(item, genres))
+        {
+            plan[rule].push(index);
+        }
+    }
+
+    plan
+}
+
+#[cfg(test)]
+mod tests {
+    use rspotify::model::ArtistId;
+
+    use super::*;
+    use crate::testing;
+
+    const SOURCE: &str = "0000000000000000000000";
+    const ARTIST: &str = "0000000000000000000009";
+
+    /// An explicit four-minute track from 1965 with a popularity of 60, added ten days ago by "alice"
+    fn item() -> PlaylistTrack {
+        let mut track = testing::track("0000000000000000000001", "Track", "Miles Davis", 240_000);
+        track.album.name = String::from("E.S.P.");
+        track.album.release_date = Some(String::from("1965-08-16"));
+        track.artists[0].id = Some(ArtistId::from_id(ARTIST).unwrap());
+        track.explicit = true;
+        track.popularity = 60;
+
+        PlaylistTrack {
+            track,
+            added_at: Some(Utc::now() - TimeDelta::days(10)),
+            added_by: Some(String::from("alice")),
+        }
+    }
+
+    fn genres() -> Genres {
+        Genres::known(&[(ARTIST, &["cool jazz", "hard bop"])])
+    }
+
+    fn conditions(json: &str) -> Conditions {
+        serde_json::from_str(json).unwrap()
+    }
+
+    fn matches(json: &str) -> bool {
+        conditions(json).matches(&item(), &genres())
+    }
+
+    fn rule(name: &str, when: &str) -> Rule {
+        Rule {
+            name: String::from(name),
+            conditions: conditions(when),
+            action: TrackAction::Skip,
+        }
+    }
+
+    #[test]
+    fn no_conditions_match_everything() {
+        assert!(matches("{}"));
+    }
+
+    #[test]
+    fn text_is_matched_ignoring_case() {
+        assert!(matches(r#"{"artist": "miles davis", "album": "e.s.p."}"#));
+        assert!(matches(r#"{"added_by": "Alice"}"#));
+        assert!(!matches(r#"{"artist": "Miles"}"#));
+        assert!(!matches(r#"{"album": "Kind of Blue"}"#));
+    }
+
+    #[test]
+    fn genres_match_on_part_of_the_name() {
+        assert!(matches(r#"{"genre": "Bop"}"#));
+        assert!(!matches(r#"{"genre": "rock"}"#));
+        assert!(!conditions(r#"{"genre": "bop"}"#).matches(&item(), &Genres::default()));
+    }
+
+    #[test]
+    fn ranges_include_their_ends() {
+        assert!(matches(
+            r#"{"released_from": 1965, "released_until": 1965}"#
+        ));
+        assert!(!matches(r#"{"released_from": 1966}"#));
+        assert!(!matches(r#"{"released_until": 1964}"#));
+        assert!(matches(
+            r#"{"min_duration_seconds": 240, "max_duration_seconds": 240}"#
+        ));
+        assert!(!matches(r#"{"max_duration_seconds": 239}"#));
+        assert!(matches(r#"{"min_popularity": 60, "max_popularity": 60}"#));
+        assert!(!matches(r#"{"min_popularity": 61}"#));
+    }
+
+    #[test]
+    fn age_and_explicit() {
+        assert!(matches(
+            r#"{"added_more_than_days_ago": 9, "added_less_than_days_ago": 11}"#
+        ));
+        assert!(!matches(r#"{"added_more_than_days_ago": 11}"#));
+        assert!(!matches(r#"{"added_less_than_days_ago": 9}"#));
+        assert!(matches(r#"{"explicit": true}"#));
+        assert!(!matches(r#"{"explicit": false}"#));
+    }
+
+    #[test]
+    fn missing_details_fail_conditions_on_them() {
+        let mut item = item();
+        item.track.album.release_date = None;
+        item.added_at = None;
+        item.added_by = None;
+
+        for json in [
+            r#"{"released_from": 1900}"#,
+            r#"{"released_until": 2100}"#,
+            r#"{"added_by": "alice"}"#,
+            r#"{"added_more_than_days_ago": 0}"#,
+            r#"{"added_less_than_days_ago": 100000}"#,
+        ] {
+            assert!(!conditions(json).matches(&item, &genres()), "{}", json);
+        }
+    }
+
+    #[test]
+    fn unknown_conditions_are_rejected() {
+        assert!(serde_json::from_str::<Conditions>(r#"{"artists": "Miles Davis"}"#).is_err());
+    }
+
+    #[test]
+    fn first_matching_rule_decides() {
+        let mut other = item();
+        other.track.explicit = false;
+        other.track.popularity = 10;
+        let items = vec![item(), other, item()];
+        let rules = vec![
+            rule("explicit", r#"{"explicit": true}"#),
+            rule("everything", "{}"),
+            rule("never reached", "{}"),
+        ];
+
+        assert_eq!(
+            plan(&rules, &items, &genres()),
+            vec![vec![0, 2], vec![1], vec![]]
+        );
+    }
+
+    #[test]
+    fn unmatched_items_are_left_out() {
+        let rules = vec![rule("popular", r#"{"min_popularity": 90}"#)];
+
+        assert_eq!(
+            plan(&rules, &[item()], &genres()),
+            vec![Vec::<usize>::new()]
+        );
+    }
+
+    #[test]
+    fn actions_resolve_playlist_names() {
+        let playlists = vec![
+            testing::playlist(SOURCE, "Inbox"),
+            testing::playlist("0000000000000000000001", "Jazz classics"),
+        ];
+        let source = PlaylistId::from_id(SOURCE).unwrap();
+        let resolved = |json: &str| {
+            resolve(serde_json::from_str(json).unwrap(), &playlists, &source)
+                .map(|rule| rule.action)
+        };
+
+        assert_eq!(
+            resolved(r#"{"name": "jazz", "then": {"add": ["jazz classics"]}}"#),
+            Ok(TrackAction::Add(vec![playlists[1].id.clone_static()]))
+        );
+        assert_eq!(
+            resolved(r#"{"name": "junk", "then": "discard"}"#),
+            Ok(TrackAction::Remove(source.clone_static()))
+        );
+        assert_eq!(
+            resolved(r#"{"name": "later", "then": "skip"}"#),
+            Ok(TrackAction::Skip)
+        );
+        assert_eq!(
+            resolved(r#"{"name": "rock", "then": {"add": ["Rock"]}}"#),
+            Err(String::from("Rule 'rock': no playlist called Rock"))
+        );
+    }
+}
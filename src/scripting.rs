@@ -0,0 +1,572 @@
+use std::{cell::RefCell, fs, mem, path::Path, rc::Rc};
+
+use rhai::{Array, Dynamic, Engine, EvalAltResult, ImmutableString, Map, Scope, AST};
+use rspotify::{
+    model::{FullTrack, PlaylistId, SimplifiedPlaylist},
+    prelude::Id,
+};
+
+use crate::{
+    library::{find_playlist, release_year, Genres, MembershipIndex},
+    presets::Presets,
+    services::TrackAction,
+    storage, ui,
+};
+
+static SCRIPT_FILE: &str = "script.rhai";
+static DECIDE_FN: &str = "decide";
+
+/// What a script asks for, with playlists still given by name or ID
+#[derive(Clone)]
+enum ScriptAction {
+    Add(Vec<String>),
+    Select(Vec<String>),
+    Discard,
+    Skip,
+}
+
+/// What a script decided about a track
+pub enum Outcome {
+    /// Act without asking the user
+    Act(TrackAction),
+    /// Indices of playlists to start out checked on the track screen
+    Select(Vec<usize>),
+}
+
+/// A key added to the track screen, which calls a function of the script with the track
+pub struct Binding {
+    pub key: String,
+    pub description: String,
+    function: String,
+}
+
+/// The session's library, lent to the script for the duration of a call
+#[derive(Default)]
+struct Library {
+    playlists: Vec<SimplifiedPlaylist>,
+    membership: MembershipIndex,
+    genres: Genres,
+}
+
+pub struct Script {
+    engine: Engine,
+    ast: AST,
+    bindings: Vec<Binding>,
+    library: Rc<RefCell<Library>>,
+}
+
+fn names(value: Array) -> Vec<String> {
+    value.into_iter().map(|name| name.to_string()).collect()
+}
+
+fn track_map(track: &FullTrack, genres: &Genres) -> Map {
+    let strings = |strings: Vec<String>| -> Dynamic {
+        strings
+            .into_iter()
+            .map(Dynamic::from)
+            .collect::<Array>()
+            .into()
+    };
+
+    let mut map = Map::new();
+    map.insert(
+        "id".into(),
+        track
+            .id
+            .as_ref()
+            .map_or(Dynamic::UNIT, |id| id.id().to_string().into()),
+    );
+    map.insert("name".into(), track.name.clone().into());
+    map.insert(
+        "artists".into(),
+        strings(track.artists.iter().map(|a| a.name.clone()).collect()),
+    );
+    map.insert("album".into(), track.album.name.clone().into());
+    map.insert(
+        "release_date".into(),
+        track
+            .album
+            .release_date
+            .clone()
+            .map_or(Dynamic::UNIT, Dynamic::from),
+    );
+    map.insert(
+        "year".into(),
+        release_year(track).map_or(Dynamic::UNIT, |year| (year as i64).into()),
+    );
+    map.insert("duration".into(), track.duration.num_seconds().into());
+    map.insert("popularity".into(), (track.popularity as i64).into());
+    map.insert("explicit".into(), track.explicit.into());
+    map.insert(
+        "isrc".into(),
+        track
+            .external_ids
+            .get("isrc")
+            .cloned()
+            .map_or(Dynamic::UNIT, Dynamic::from),
+    );
+    map.insert(
+        "genres".into(),
+        strings(genres.of_track(track).into_iter().cloned().collect()),
+    );
+    map
+}
+
+fn register_api(
+    engine: &mut Engine,
+    library: &Rc<RefCell<Library>>,
+    bindings: &Rc<RefCell<Vec<Binding>>>,
+) {
+    engine.on_print(|text| log::info!("Script: {}", text));
+    engine.on_debug(|text, _, position| log::debug!("Script at {}: {}", position, text));
+
+    engine
+        .register_type_with_name::<ScriptAction>("Action")
+        .register_fn("add", |playlists: Array| {
+            ScriptAction::Add(names(playlists))
+        })
+        .register_fn("add", |playlist: ImmutableString| {
+            ScriptAction::Add(vec![playlist.to_string()])
+        })
+        .register_fn("select", |playlists: Array| {
+            ScriptAction::Select(names(playlists))
+        })
+        .register_fn("select", |playlist: ImmutableString| {
+            ScriptAction::Select(vec![playlist.to_string()])
+        })
+        .register_fn("discard", || ScriptAction::Discard)
+        .register_fn("skip", || ScriptAction::Skip);
+
+    let lent = library.clone();
+    engine.register_fn("playlists", move || -> Array {
+        let library = lent.borrow();
+        library
+            .playlists
+            .iter()
+            .map(|playlist| {
+                let mut map = Map::new();
+                map.insert("id".into(), playlist.id.id().to_string().into());
+                map.insert("name".into(), playlist.name.clone().into());
+                map.insert(
+                    "size".into(),
+                    (library.membership.tracks(&playlist.id).len() as i64).into(),
+                );
+                Dynamic::from_map(map)
+            })
+            .collect()
+    });
+
+    let lent = library.clone();
+    engine.register_fn(
+        "tracks_in",
+        move |playlist: ImmutableString| -> Result<Array, Box<EvalAltResult>> {
+            let library = lent.borrow();
+            let playlist = find_playlist(&library.playlists, &playlist)
+                .ok_or(format!("No playlist called {}", playlist))?;
+            Ok(library
+                .membership
+                .tracks(&playlist.id)
+                .iter()
+                .map(|track| Dynamic::from_map(track_map(track, &library.genres)))
+                .collect())
+        },
+    );
+
+    let lent = library.clone();
+    engine.register_fn(
+        "in_playlist",
+        move |playlist: ImmutableString,
+              track_id: ImmutableString|
+              -> Result<bool, Box<EvalAltResult>> {
+            let library = lent.borrow();
+            let playlist = find_playlist(&library.playlists, &playlist)
+                .ok_or(format!("No playlist called {}", playlist))?;
+            Ok(library.membership.tracks(&playlist.id).iter().any(|track| {
+                track
+                    .id
+                    .as_ref()
+                    .is_some_and(|id| id.id() == track_id.as_str())
+            }))
+        },
+    );
+
+    let bound = bindings.clone();
+    engine.register_fn(
+        "bind",
+        move |key: ImmutableString,
+              description: ImmutableString,
+              function: ImmutableString|
+              -> Result<(), Box<EvalAltResult>> {
+            if ui::RESERVED_KEYS.contains(&key.as_str())
+                || ui::selection::WORDS.contains(&key.as_str())
+                || key.parse::<usize>().is_ok()
+            {
+                return Err(format!("Key '{}' is already used by the track screen", key).into());
+            }
+            bound.borrow_mut().push(Binding {
+                key: key.to_string(),
+                description: description.to_string(),
+                function: function.to_string(),
+            });
+            Ok(())
+        },
+    );
+}
+
+impl Script {
+    /// Compiles and runs the optional script in the data directory, which registers its key
+    /// bindings as it runs
+    pub fn load() -> Result<Option<Script>, String> {
+        let path = storage::path(SCRIPT_FILE);
+        if !path.exists() {
+            return Ok(None);
+        }
+        let source = fs::read_to_string(&path).map_err(|e| e.to_string())?;
+
+        Script::compile(source, &path).map(Some)
+    }
+
+    fn compile(source: String, path: &Path) -> Result<Script, String> {
+        let library = Rc::new(RefCell::new(Library::default()));
+        let bindings = Rc::new(RefCell::new(Vec::new()));
+        let mut engine = Engine::new();
+        register_api(&mut engine, &library, &bindings);
+
+        let ast = engine
+            .compile(source)
+            .map_err(|e| format!("{}: {}", path.display(), e))?;
+        engine
+            .run_ast(&ast)
+            .map_err(|e| format!("{}: {}", path.display(), e))?;
+
+        let bindings = bindings.take();
+        for binding in &bindings {
+            if !ast
+                .iter_functions()
+                .any(|f| f.name == binding.function && f.params.len() == 1)
+            {
+                return Err(format!(
+                    "{}: key '{}' is bound to {}, which isn't a function taking a track",
+                    path.display(),
+                    binding.key,
+                    binding.function
+                ));
+            }
+        }
+        log::info!("Loaded script with {} key bindings", bindings.len());
+
+        Ok(Script {
+            engine,
+            ast,
+            bindings,
+            library,
+        })
+    }
+
+    pub fn bindings(&self) -> &[Binding] {
+        &self.bindings
+    }
+
+    /// Leaves out bindings to keys the track screen already gives to a playlist or a preset,
+    /// returning why
+    pub fn drop_taken_keys(&mut self, hotkeys: &[char], presets: &Presets) -> Vec<String> {
+        let mut problems = Vec::new();
+
+        self.bindings.retain(|binding| {
+            let is_key = |key: &char| binding.key == key.to_string();
+            let problem = if hotkeys.iter().any(is_key) {
+                Some(format!(
+                    "key '{}' of {} is already a playlist's hotkey",
+                    binding.key, binding.function
+                ))
+            } else {
+                presets
+                    .presets
+                    .iter()
+                    .find(|preset| is_key(&preset.key))
+                    .map(|preset| {
+                        format!(
+                            "key '{}' of {} is already used by preset {}",
+                            binding.key, binding.function, preset.name
+                        )
+                    })
+            };
+
+            let keep = problem.is_none();
+            problems.extend(problem);
+            keep
+        });
+
+        problems
+    }
+
+    /// Asks the script's `decide` function, if it has one, what to do with a track
+    pub fn decide(
+        &self,
+        track: &FullTrack,
+        playlists: &mut Vec<SimplifiedPlaylist>,
+        membership: &mut MembershipIndex,
+        genres: &mut Genres,
+        source_playlist_id: &PlaylistId<'static>,
+    ) -> Result<Option<Outcome>, String> {
+        if !self
+            .ast
+            .iter_functions()
+            .any(|f| f.name == DECIDE_FN && f.params.len() == 1)
+        {
+            return Ok(None);
+        }
+        self.call(
+            DECIDE_FN,
+            track,
+            playlists,
+            membership,
+            genres,
+            source_playlist_id,
+        )
+    }
+
+    /// Runs the function bound to one of the script's keys
+    pub fn run_binding(
+        &self,
+        binding: usize,
+        track: &FullTrack,
+        playlists: &mut Vec<SimplifiedPlaylist>,
+        membership: &mut MembershipIndex,
+        genres: &mut Genres,
+        source_playlist_id: &PlaylistId<'static>,
+    ) -> Result<Option<Outcome>, String> {
+        let function = &self.bindings[binding].function;
+        self.call(
+            function,
+            track,
+            playlists,
+            membership,
+            genres,
+            source_playlist_id,
+        )
+    }
+
+    fn call(
+        &self,
+        function: &str,
+        track: &FullTrack,
+        playlists: &mut Vec<SimplifiedPlaylist>,
+        membership: &mut MembershipIndex,
+        genres: &mut Genres,
+        source_playlist_id: &PlaylistId<'static>,
+    ) -> Result<Option<Outcome>, String> {
+        let argument = Dynamic::from_map(track_map(track, genres));
+
+        // the library is moved in and back out rather than copied, as it can be large
+        self.library.replace(Library {
+            playlists: mem::take(playlists),
+            membership: mem::take(membership),
+            genres: mem::take(genres),
+        });
+        let result =
+            self.engine
+                .call_fn::<Dynamic>(&mut Scope::new(), &self.ast, function, (argument,));
+        let library = self.library.take();
+        *playlists = library.playlists;
+        *membership = library.membership;
+        *genres = library.genres;
+
+        let value = result.map_err(|e| format!("{}: {}", function, e))?;
+        if value.is_unit() {
+            return Ok(None);
+        }
+        let action = value.try_cast::<ScriptAction>().ok_or(format!(
+            "{} should return add(...), select(...), discard(), skip() or nothing",
+            function
+        ))?;
+
+        let resolve = |names: Vec<String>| -> Result<Vec<usize>, String> {
+            names
+                .iter()
+                .map(|name| {
+                    find_playlist(playlists, name)
+                        .and_then(|found| {
+                            playlists
+                                .iter()
+                                .position(|playlist| playlist.id == found.id)
+                        })
+                        .ok_or(format!("{}: no playlist called {}", function, name))
+                })
+                .collect()
+        };
+
+        Ok(Some(match action {
+            ScriptAction::Add(names) => Outcome::Act(TrackAction::Add(
+                resolve(names)?
+                    .into_iter()
+                    .map(|index| playlists[index].id.clone_static())
+                    .collect(),
+            )),
+            ScriptAction::Select(names) => Outcome::Select(resolve(names)?),
+            ScriptAction::Discard => {
+                Outcome::Act(TrackAction::Remove(source_playlist_id.clone_static()))
+            }
+            ScriptAction::Skip => Outcome::Act(TrackAction::Skip),
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{presets::Preset, testing};
+
+    const SOURCE: &str = "0000000000000000000000";
+
+    fn compiled(source: &str) -> Result<Script, String> {
+        Script::compile(source.to_string(), Path::new("script.rhai"))
+    }
+
+    fn playlists() -> Vec<SimplifiedPlaylist> {
+        vec![
+            testing::playlist(SOURCE, "Inbox"),
+            testing::playlist("0000000000000000000001", "Jazz classics"),
+            testing::playlist("0000000000000000000002", "Chill"),
+        ]
+    }
+
+    /// What the script's `decide` makes of a track by the artist
+    fn decided(source: &str, artist: &str) -> Result<Option<Outcome>, String> {
+        let script = compiled(source).unwrap();
+        let track = testing::track("0000000000000000000009", "Track", artist, 1);
+        script.decide(
+            &track,
+            &mut playlists(),
+            &mut MembershipIndex::default(),
+            &mut Genres::default(),
+            &PlaylistId::from_id(SOURCE).unwrap(),
+        )
+    }
+
+    fn keys(script: &Script) -> Vec<&str> {
+        script
+            .bindings()
+            .iter()
+            .map(|binding| binding.key.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn bindings_are_registered_as_the_script_runs() {
+        let script = compiled(
+            r#"
+            bind("j", "Jazz it", "jazz");
+            bind("later", "Skip for now", "later");
+            fn jazz(track) { add("Jazz classics") }
+            fn later(track) { skip() }
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(keys(&script), vec!["j", "later"]);
+        assert_eq!(script.bindings()[0].description, "Jazz it");
+    }
+
+    #[test]
+    fn keys_of_the_track_screen_cant_be_bound() {
+        for key in ["q", "3", "clear", "invert"] {
+            let source = format!(r#"bind("{}", "", "f"); fn f(track) {{}}"#, key);
+            let error = compiled(&source).err().unwrap();
+            assert!(
+                error.contains("already used by the track screen"),
+                "{}",
+                error
+            );
+        }
+    }
+
+    #[test]
+    fn bound_functions_must_take_a_track() {
+        let error = compiled(r#"bind("j", "", "jazz"); fn jazz() {}"#)
+            .err()
+            .unwrap();
+
+        assert!(error.contains("key 'j' is bound to jazz"), "{}", error);
+    }
+
+    #[test]
+    fn keys_taken_by_hotkeys_and_presets_are_dropped() {
+        let mut script = compiled(
+            r#"
+            bind("j", "", "f");
+            bind("k", "", "f");
+            bind("t", "", "f");
+            fn f(track) {}
+            "#,
+        )
+        .unwrap();
+        let presets = Presets {
+            presets: vec![Preset {
+                name: String::from("Evening"),
+                key: 'k',
+                playlists: Vec::new(),
+            }],
+            previous: Vec::new(),
+        };
+
+        let problems = script.drop_taken_keys(&['j'], &presets);
+
+        assert_eq!(keys(&script), vec!["t"]);
+        assert_eq!(
+            problems,
+            vec![
+                "key 'j' of f is already a playlist's hotkey",
+                "key 'k' of f is already used by preset Evening",
+            ]
+        );
+    }
+
+    #[test]
+    fn decisions_resolve_playlist_names() {
+        let source = r#"
+            fn decide(track) {
+                if "Miles Davis" in track.artists { return add(["jazz classics", "Chill"]); }
+                if track.artists[0] == "Bill Evans" { return select("Chill"); }
+                if track.artists[0] == "Noise" { return discard(); }
+            }
+        "#;
+
+        let Ok(Some(Outcome::Act(TrackAction::Add(ids)))) = decided(source, "Miles Davis") else {
+            panic!("expected the track to be added");
+        };
+        assert_eq!(
+            ids,
+            vec![
+                playlists()[1].id.clone_static(),
+                playlists()[2].id.clone_static()
+            ]
+        );
+        assert!(matches!(
+            decided(source, "Bill Evans"),
+            Ok(Some(Outcome::Select(indices))) if indices == vec![2]
+        ));
+        assert!(matches!(
+            decided(source, "Noise"),
+            Ok(Some(Outcome::Act(TrackAction::Remove(id)))) if id.id() == SOURCE
+        ));
+        assert!(matches!(decided(source, "Someone else"), Ok(None)));
+    }
+
+    #[test]
+    fn bad_decisions_are_errors() {
+        let unknown = decided(r#"fn decide(track) { add("Rock") }"#, "Artist");
+        assert_eq!(unknown.err().unwrap(), "decide: no playlist called Rock");
+
+        let wrong_type = decided(r#"fn decide(track) { 42 }"#, "Artist");
+        assert!(wrong_type
+            .err()
+            .unwrap()
+            .starts_with("decide should return"));
+    }
+
+    #[test]
+    fn scripts_without_decide_decide_nothing() {
+        assert!(matches!(decided("let x = 1;", "Artist"), Ok(None)));
+    }
+}
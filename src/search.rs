@@ -0,0 +1,85 @@
+use std::collections::HashSet;
+
+use rspotify::{
+    model::{FullArtist, FullTrack, PlaylistId, SimplifiedAlbum, SimplifiedPlaylist, TrackId},
+    AuthCodePkceSpotify, ClientResult,
+};
+
+use crate::spotify;
+
+const TRACKS: u32 = 5;
+const ALBUMS: u32 = 3;
+const ARTISTS: u32 = 3;
+
+/// Something found by searching, which stands for one or more tracks
+pub enum Hit {
+    Track(Box<FullTrack>),
+    Album(SimplifiedAlbum),
+    /// Stands for the artist's most popular tracks
+    Artist(FullArtist),
+}
+
+impl Hit {
+    pub fn track_ids(&self, spotify: &AuthCodePkceSpotify) -> Vec<TrackId<'static>> {
+        match self {
+            Hit::Track(track) => track.id.iter().cloned().collect(),
+            Hit::Album(album) => match &album.id {
+                Some(id) => spotify::album_tracks(spotify, id.clone()),
+                None => Vec::new(),
+            },
+            Hit::Artist(artist) => spotify::artist_top_tracks(spotify, artist.id.clone()),
+        }
+    }
+}
+
+/// The best few tracks, albums and artists for the query, in that order
+pub fn search(spotify: &AuthCodePkceSpotify, query: &str) -> Vec<Hit> {
+    let tracks = spotify::search_tracks(spotify, query, TRACKS)
+        .into_iter()
+        .map(|track| Hit::Track(Box::new(track)));
+    let albums = spotify::search_albums(spotify, query, ALBUMS)
+        .into_iter()
+        .map(Hit::Album);
+    let artists = spotify::search_artists(spotify, query, ARTISTS)
+        .into_iter()
+        .map(Hit::Artist);
+    tracks.chain(albums).chain(artists).collect()
+}
+
+/// The playlist where tracks to sort later are collected
+pub struct Buffer {
+    pub id: PlaylistId<'static>,
+    pub name: String,
+    /// Tracks already in it, which aren't added again
+    tracks: HashSet<TrackId<'static>>,
+}
+
+impl Buffer {
+    pub fn open(spotify: &AuthCodePkceSpotify, playlist: &SimplifiedPlaylist) -> Buffer {
+        Buffer {
+            id: playlist.id.clone_static(),
+            name: playlist.name.clone(),
+            tracks: spotify::tracks_in_playlist(spotify, playlist.id.clone_static())
+                .into_iter()
+                .filter_map(|track| track.id)
+                .collect(),
+        }
+    }
+
+    /// Adds the tracks that aren't in the buffer yet, returning how many that was
+    pub fn add(
+        &mut self,
+        spotify: &AuthCodePkceSpotify,
+        track_ids: Vec<TrackId<'static>>,
+    ) -> ClientResult<usize> {
+        let mut new = Vec::new();
+        for id in track_ids {
+            if !self.tracks.contains(&id) && !new.contains(&id) {
+                new.push(id);
+            }
+        }
+        spotify::add_tracks(spotify, &self.id, &new)?;
+        self.tracks.extend(new.iter().cloned());
+        Ok(new.len())
+    }
+}
@@ -1,16 +1,31 @@
-use std::{collections::HashMap, ops::ControlFlow};
+use std::{io, path::Path, time::Instant};
 
 use rspotify::{
-    model::{FullTrack, PlaylistId, SimplifiedPlaylist},
+    model::{FullTrack, PlaylistId, SimplifiedPlaylist, TrackId},
+    prelude::Id,
     AuthCodePkceSpotify,
 };
 
 use crate::{
-    audio::AudioPlayer,
-    spotify::{self, SpotifyPlaylistsError},
+    analysis, audio, export,
+    hooks::Event,
+    import::{self, matching, matching::Match},
+    library::{self, MembershipIndex},
+    plan::{self, Plan},
+    queue::{Queue, Status, Step},
+    rules::Rule,
+    scripting, search,
+    session::Session,
+    snapshot::{self, Snapshot},
+    spotify::{self, PlaylistTrack, SpotifyPlaylistsError},
+    storage,
+    suggestions::{self, classifier},
     ui,
 };
 
+static BATCH_FILE: &str = "batch.txt";
+
+#[derive(Clone, PartialEq, Debug)]
 pub enum TrackAction {
     Add(Vec<PlaylistId<'static>>),
     Remove(PlaylistId<'static>),
@@ -20,7 +35,7 @@ pub enum TrackAction {
 impl TrackAction {
     pub fn from_ui_track_action(
         ui_track_action: &ui::TrackAction,
-        playlists: &Vec<SimplifiedPlaylist>,
+        playlists: &[SimplifiedPlaylist],
         source_playlist_id: PlaylistId<'static>,
     ) -> TrackAction {
         match ui_track_action {
@@ -36,6 +51,33 @@ impl TrackAction {
             ui::TrackAction::ChangeVolume(_) => {
                 panic!("request to change volume was passed to services")
             }
+            ui::TrackAction::CycleVisualiser => {
+                panic!("request to change visualiser was passed to services")
+            }
+            ui::TrackAction::Custom { .. } => {
+                panic!("request to run a script binding was passed to services")
+            }
+            ui::TrackAction::EditPlaylists { .. } => {
+                panic!("request to change playlists was passed to services")
+            }
+            ui::TrackAction::ToggleGroup { .. } => {
+                panic!("request to collapse a group was passed to services")
+            }
+            ui::TrackAction::SavePreset { .. } => {
+                panic!("request to save a preset was passed to services")
+            }
+            ui::TrackAction::Previous
+            | ui::TrackAction::GoTo(_)
+            | ui::TrackAction::ListTracks
+            | ui::TrackAction::Defer => {
+                panic!("request to move through the queue was passed to services")
+            }
+            ui::TrackAction::Snooze(_) => {
+                panic!("request to snooze a track was passed to services")
+            }
+            ui::TrackAction::Batch { .. } => {
+                panic!("request to decide on a group of tracks was passed to services")
+            }
         }
     }
 }
@@ -61,98 +103,936 @@ fn handle_track_action(
                 .map(|_| action)
         }
         TrackAction::Remove(ref playlist_id) => {
-            // confirm desctructive action
-            if ui::utils::confirmation(format!(
-                "Do you wish to remove {} from the source playlist?",
-                ui::track::summary(track)
-            )) {
-                // on confirmation, call spotify api to remove from playlist
-                spotify::remove_from_playlist(spotify, &track_id, playlist_id).map(|_| action)
-            } else {
-                // on cancel, treat action as a skip
-                Ok(TrackAction::Skip)
-            }
+            // call spotify api to remove from playlist
+            spotify::remove_from_playlist(spotify, &track_id, playlist_id).map(|_| action)
         }
         // skip doesn't error
         TrackAction::Skip => Ok(action),
     }
 }
 
-pub fn handle_track(
-    track: FullTrack,
-    playlists: &Vec<SimplifiedPlaylist>,
-    image_cache: &mut HashMap<String, String>,
-    source_playlist_id: &PlaylistId<'_>,
-    spotify: &AuthCodePkceSpotify,
-    audio_player: &mut Option<AudioPlayer>,
-) -> ControlFlow<()> {
-    log::info!("Handling track {}", ui::track::summary(&track));
+/// Has the user, or their script, decide on the current track of the queue
+pub fn handle_track(track: &FullTrack, queue: &Queue, session: &mut Session) -> Step {
+    log::info!("Handling track {}", ui::track::summary(track));
 
-    if let None = track.id {
+    if track.id.is_none() {
         log::warn!("Track has no ID, skipping");
-        return ControlFlow::Continue(());
+        return Step::Decided(Status::Skipped);
+    }
+
+    session
+        .genres
+        .fetch_missing(&session.spotify, std::iter::once(track));
+
+    // the script gets the first say, so tracks it sorts don't need their preview downloaded
+    let mut scripted = Vec::new();
+    match run_script(track, None, session) {
+        Some(scripting::Outcome::Act(action)) => {
+            let result = perform(track, action, session);
+            // the script decides as the user would, so the model learns from it too
+            if let Ok(action) = &result {
+                let tokens = classifier::tokens(track, &session.genres, None);
+                remember(track, tokens, action, session);
+            }
+            let status = status(&result);
+            ui::rule_feedback("script", track, result);
+            return Step::Decided(status);
+        }
+        Some(scripting::Outcome::Select(indices)) => scripted = indices,
+        None => (),
+    }
+
+    let preview = audio::download_preview(track);
+    if preview.is_none() {
+        log::warn!("Failed to download track preview");
     }
 
     // start playing track preview in separate thread while other things load
-    if let Some(audio) = audio_player {
-        let res = audio.play_track_preview(&track);
+    if let (Some(audio), Some(preview)) = (session.audio_player.as_mut(), &preview) {
+        let res = audio.play_preview(preview.clone());
         if res.is_none() {
             log::warn!("Failed to play track preview");
         }
     }
 
+    let analysis = analysis::analyse(track, preview.as_deref(), &session.analysis_cache);
+    let tokens = classifier::tokens(track, &session.genres, analysis.as_ref());
+    let mut suggestions = suggestions::suggest(track, analysis.as_ref(), &tokens, session);
+    suggestions.scripted = scripted;
+
+    let shown = ui::track::display(track, &mut session.image_cache, analysis.as_ref());
+    let progress = ui::Progress {
+        number: queue.position() + 1,
+        total: queue.total(),
+        has_previous: queue.previous().is_some(),
+    };
+
+    let group = queue.group();
+    let shown_at = Instant::now();
+
     // spin up ui for a track and get user's interaction
-    let ui_action = loop {
+    let action = loop {
         let ui_action = ui::handle_track(
-            &track,
-            playlists,
-            image_cache,
-            audio_player
+            &shown,
+            &progress,
+            group.as_ref(),
+            &session
+                .layout
+                .arrange(&session.playlists, &session.descriptions)
+                .with_presets(&session.presets, &session.playlists),
+            session.audio_player.as_ref().map(|audio_player| {
+                (
+                    audio_player.volume(),
+                    audio_player.visualiser(),
+                    audio_player.samples(),
+                )
+            }),
+            &suggestions,
+            session
+                .script
                 .as_ref()
-                .map(|audio_player| audio_player.volume()),
+                .map(|script| script.bindings())
+                .unwrap_or_default(),
         );
 
-        // if asked to change volume, stay in loop to get different action
-        if let ui::TrackAction::ChangeVolume(up) = ui_action {
-            if let Some(audio) = audio_player {
-                if up {
-                    audio.volume_up();
+        // if asked to change volume or visualiser, stay in loop to get different action
+        match ui_action {
+            ui::TrackAction::ChangeVolume(up) => {
+                if let Some(audio) = &mut session.audio_player {
+                    if up {
+                        audio.volume_up();
+                    } else {
+                        audio.volume_down();
+                    }
+                }
+            }
+            ui::TrackAction::CycleVisualiser => {
+                if let Some(audio) = &mut session.audio_player {
+                    audio.cycle_visualiser();
+                }
+            }
+            // a script binding either decides, or checks more playlists and stays in the loop
+            ui::TrackAction::Custom {
+                binding,
+                mut checked,
+            } => {
+                match run_script(track, Some(binding), session) {
+                    Some(scripting::Outcome::Act(action)) => break action,
+                    Some(scripting::Outcome::Select(indices)) => {
+                        for index in indices {
+                            if !checked.contains(&index) {
+                                checked.push(index);
+                            }
+                        }
+                    }
+                    None => (),
+                }
+                suggestions.checked = Some(checked);
+            }
+            ui::TrackAction::SavePreset { name, key, checked } => {
+                let playlist_ids = playlist_ids(&checked, &session.playlists);
+                session.presets.add(name, key, &playlist_ids);
+                suggestions.checked = Some(checked);
+            }
+            ui::TrackAction::ToggleGroup { group, checked } => {
+                session.layout.toggle(&group);
+                suggestions.checked = Some(checked);
+            }
+            ui::TrackAction::EditPlaylists { edit, checked } => {
+                // indices shift as playlists come and go, so what's checked is kept by ID
+                let mut checked = playlist_ids(&checked, &session.playlists);
+                let scripted = playlist_ids(&suggestions.scripted, &session.playlists);
+                match edit_playlists(edit, session) {
+                    // a playlist made for the track is surely meant for it
+                    Ok(created) => checked.extend(created),
+                    Err(e) => {
+                        log::error!("Failed to change playlists: {}", e);
+                        ui::playlist_edit_error(&e);
+                    }
+                }
+
+                suggestions = suggestions::suggest(track, analysis.as_ref(), &tokens, session);
+                suggestions.scripted = playlist_indices(&scripted, &session.playlists);
+                suggestions.checked = Some(playlist_indices(&checked, &session.playlists));
+            }
+            // skipping may keep the track away from later sessions too
+            ui::TrackAction::Skip | ui::TrackAction::Snooze(_) => {
+                let days = match ui_action {
+                    ui::TrackAction::Snooze(days) => Some(days),
+                    _ => session.snooze_days,
+                };
+                if let Some(days) = days {
+                    session.snoozed.snooze(track, days);
+                }
+                break TrackAction::Skip;
+            }
+            ui::TrackAction::Batch { positions, checked } => {
+                let action = if checked.is_empty() {
+                    if !ui::utils::confirmation(format!(
+                        "Do you wish to remove {} tracks from the source playlist?",
+                        positions.len()
+                    )) {
+                        continue;
+                    }
+                    TrackAction::Remove(session.source_playlist_id.clone_static())
                 } else {
-                    audio.volume_down();
+                    let playlist_ids = playlist_ids(&checked, &session.playlists);
+                    session.presets.remember(&playlist_ids);
+                    TrackAction::Add(playlist_ids)
+                };
+
+                let tracks: Vec<&FullTrack> = positions
+                    .iter()
+                    .map(|position| queue.track(*position))
+                    .collect();
+                session
+                    .summary
+                    .time_decision(shown_at.elapsed(), tracks.len());
+                let status = perform_batch(&tracks, action, session);
+                if let Some(audio) = &session.audio_player {
+                    audio.stop();
+                }
+                return Step::DecidedGroup(positions, status);
+            }
+            ui::TrackAction::GoTo(number) if !queue.is_open(number.wrapping_sub(1)) => {
+                ui::unavailable_track(number, queue.status(number.wrapping_sub(1)));
+            }
+            // if user chose to quit or go elsewhere in the queue
+            ui::TrackAction::Quit
+            | ui::TrackAction::Previous
+            | ui::TrackAction::GoTo(_)
+            | ui::TrackAction::ListTracks
+            | ui::TrackAction::Defer => {
+                let step = match ui_action {
+                    ui::TrackAction::Previous => Step::Previous,
+                    ui::TrackAction::Defer => Step::Decided(Status::Deferred),
+                    ui::TrackAction::GoTo(number) => Step::Jump(number - 1),
+                    ui::TrackAction::ListTracks => match ui::choose_queued(queue) {
+                        Some(position) => Step::Jump(position),
+                        None => continue,
+                    },
+                    _ => Step::Quit,
+                };
+                // stop audio
+                if let Some(audio) = &session.audio_player {
+                    audio.stop();
+                }
+                // and let caller know
+                return step;
+            }
+            _ => {
+                let action = TrackAction::from_ui_track_action(
+                    &ui_action,
+                    &session.playlists,
+                    session.source_playlist_id.clone_static(),
+                );
+
+                // confirm desctructive action, treating a cancel as a skip
+                if let TrackAction::Remove(_) = action {
+                    if !ui::utils::confirmation(format!(
+                        "Do you wish to remove {} from the source playlist?",
+                        ui::track::summary(track)
+                    )) {
+                        break TrackAction::Skip;
+                    }
                 }
+                break action;
             }
-        } else {
-            break ui_action;
         }
     };
 
-    // if user chose to quit
-    if let ui::TrackAction::Quit = ui_action {
-        // stop audio
-        if let Some(audio) = audio_player {
-            audio.stop();
+    session.summary.time_decision(shown_at.elapsed(), 1);
+
+    // for the next track to go to the same place with one key
+    if let TrackAction::Add(playlist_ids) = &action {
+        if !playlist_ids.is_empty() {
+            session.presets.remember(playlist_ids);
         }
-        // and let caller know
-        return ControlFlow::Break(());
     }
 
-    // interact with spotify api
-    let result = handle_track_action(
-        spotify,
-        &track,
-        TrackAction::from_ui_track_action(&ui_action, playlists, source_playlist_id.clone_static()),
-        source_playlist_id.clone_static(),
-    );
+    let result = perform(track, action, session);
+
+    // remember the decision for the model to learn from
+    if let Ok(action) = &result {
+        remember(track, tokens, action, session);
+    }
+    let status = status(&result);
 
     // inform user of success or failure
-    ui::track_action_feedback(&track, result);
+    ui::track_action_feedback(track, result);
 
     // stop playing track preview before moving on to next one
-    if let Some(audio) = audio_player {
+    if let Some(audio) = &session.audio_player {
         audio.stop();
     }
 
-    ControlFlow::Continue(())
+    Step::Decided(status)
+}
+
+/// A track that couldn't be dealt with is still in the source playlist, as if skipped
+fn status(result: &Result<TrackAction, SpotifyPlaylistsError>) -> Status {
+    match result {
+        Ok(TrackAction::Add(_)) => Status::Sorted,
+        Ok(TrackAction::Remove(_)) => Status::Removed,
+        Ok(TrackAction::Skip) | Err(_) => Status::Skipped,
+    }
+}
+
+/// Records a decision of the user's in the history the model learns from
+fn remember(track: &FullTrack, tokens: Vec<String>, action: &TrackAction, session: &mut Session) {
+    match action {
+        TrackAction::Add(playlist_ids) => session.history.record(track, tokens, playlist_ids),
+        TrackAction::Remove(_) => session.history.record(track, tokens, &[]),
+        TrackAction::Skip => (),
+    }
+}
+
+/// Asks the user's script, if any, about a track: through its `decide` function, or through the
+/// function bound to a key when one is given. Errors are shown and treated as no decision.
+fn run_script(
+    track: &FullTrack,
+    binding: Option<usize>,
+    session: &mut Session,
+) -> Option<scripting::Outcome> {
+    let script = session.script.as_ref()?;
+    let result = match binding {
+        Some(binding) => script.run_binding(
+            binding,
+            track,
+            &mut session.playlists,
+            &mut session.membership,
+            &mut session.genres,
+            &session.source_playlist_id,
+        ),
+        None => script.decide(
+            track,
+            &mut session.playlists,
+            &mut session.membership,
+            &mut session.genres,
+            &session.source_playlist_id,
+        ),
+    };
+
+    result.unwrap_or_else(|e| {
+        log::error!("Script failed: {}", e);
+        ui::script_error(&e);
+        session.hooks.emit(Event::error(track, &e));
+        None
+    })
+}
+
+/// Interacts with the spotify api, keeping the membership index in sync so later suggestions take
+/// the decision into account
+fn perform(
+    track: &FullTrack,
+    action: TrackAction,
+    session: &mut Session,
+) -> Result<TrackAction, SpotifyPlaylistsError> {
+    let attempted = action.clone();
+    let result = handle_track_action(
+        &session.spotify,
+        track,
+        action,
+        session.source_playlist_id.clone_static(),
+    );
+    session
+        .summary
+        .record(track, &attempted, &result, &session.playlists);
+
+    if let Ok(action) = &result {
+        update_membership(
+            &mut session.membership,
+            track,
+            action,
+            &session.source_playlist_id,
+        );
+    }
+    session
+        .hooks
+        .emit(Event::from_result(track, &result, &session.playlists));
+
+    result
+}
+
+/// Like `perform`, for several tracks decided on together, with one request per playlist rather
+/// than per track. The outcome is the same for all of them.
+fn perform_batch(tracks: &[&FullTrack], action: TrackAction, session: &mut Session) -> Status {
+    log::info!("Deciding on {} tracks at once", tracks.len());
+    let track_ids: Vec<TrackId<'static>> =
+        tracks.iter().filter_map(|track| track.id.clone()).collect();
+
+    let result = match &action {
+        TrackAction::Add(playlist_ids) => {
+            // tracks already in a playlist aren't added again
+            let additions: Vec<(PlaylistId<'static>, Vec<TrackId<'static>>)> = playlist_ids
+                .iter()
+                .map(|playlist_id| {
+                    let present = session.membership.tracks(playlist_id);
+                    let new_ids = track_ids
+                        .iter()
+                        .filter(|id| !present.iter().any(|track| track.id.as_ref() == Some(id)))
+                        .cloned()
+                        .collect();
+                    (playlist_id.clone(), new_ids)
+                })
+                .collect();
+            spotify::sort_tracks(
+                &session.spotify,
+                &track_ids,
+                &additions,
+                &session.source_playlist_id,
+            )
+        }
+        TrackAction::Remove(playlist_id) => {
+            spotify::remove_tracks(&session.spotify, &track_ids, playlist_id)
+        }
+        TrackAction::Skip => Ok(()),
+    }
+    .map(|_| action.clone());
+
+    for track in tracks {
+        session
+            .summary
+            .record(track, &action, &result, &session.playlists);
+        if let Ok(action) = &result {
+            update_membership(
+                &mut session.membership,
+                track,
+                action,
+                &session.source_playlist_id,
+            );
+            let tokens = classifier::tokens(track, &session.genres, None);
+            remember(track, tokens, action, session);
+        }
+        session
+            .hooks
+            .emit(Event::from_result(track, &result, &session.playlists));
+        ui::rule_feedback("batch", track, result.clone());
+    }
+
+    status(&result)
+}
+
+/// Sorts the items decided on by rules as planned by `rules::plan`, returning the ones left for
+/// the user to decide on
+pub fn apply_rules(
+    items: Vec<PlaylistTrack>,
+    rules: &[Rule],
+    plan: &[Vec<usize>],
+    session: &mut Session,
+) -> Vec<PlaylistTrack> {
+    let mut decided = vec![false; items.len()];
+
+    for (rule, indices) in rules.iter().zip(plan) {
+        for index in indices {
+            let track = &items[*index].track;
+            decided[*index] = true;
+
+            if track.id.is_none() {
+                log::warn!("Track has no ID, skipping");
+                continue;
+            }
+
+            log::info!(
+                "Rule '{}' handling track {}",
+                rule.name,
+                ui::track::summary(track)
+            );
+            let result = perform(track, rule.action.clone(), session);
+            // the rules are the user's own decisions, so the model learns from them too
+            if let Ok(action) = &result {
+                let tokens = classifier::tokens(track, &session.genres, None);
+                remember(track, tokens, action, session);
+            }
+            ui::rule_feedback(&rule.name, track, result);
+        }
+    }
+
+    items
+        .into_iter()
+        .zip(decided)
+        .filter(|(_, decided)| !decided)
+        .map(|(item, _)| item)
+        .collect()
+}
+
+/// Lets the user decide on all the items at once in their editor, returning whether every one of
+/// them was decided on
+pub fn sort_in_editor(items: &[PlaylistTrack], session: &mut Session) -> bool {
+    let path = storage::path(BATCH_FILE);
+    let mut text = plan::editor::render(items, &session.playlists, &session.source_playlist_id);
+
+    let plan = loop {
+        let edited = match ui::utils::edit_in_editor(&path, &text) {
+            Ok(edited) => edited,
+            Err(e) => {
+                log::error!("Failed to edit batch file: {}", e);
+                ui::editor_error(&e);
+                return false;
+            }
+        };
+
+        match plan::editor::parse(
+            &edited,
+            items,
+            &session.playlists,
+            &session.source_playlist_id,
+        ) {
+            Ok(plan) => break plan,
+            Err(errors) => {
+                ui::plan_errors(
+                    &errors
+                        .iter()
+                        .map(|(line, message)| format!("line {}: {}", line + 1, message))
+                        .collect::<Vec<String>>(),
+                );
+                if !ui::utils::confirmation("Edit again?".to_string()) {
+                    return false;
+                }
+                text = plan::editor::with_errors(&edited, &errors);
+            }
+        }
+    };
+
+    let applied = apply_plan(items, &plan, "batch", session);
+    if applied {
+        // the file is only useful until it's been applied
+        _ = std::fs::remove_file(&path);
+    }
+
+    applied && plan::is_complete(&plan, items)
+}
+
+/// Applies decisions prepared in a CSV or JSON file, returning whether every one of the items
+/// was decided on
+pub fn sort_from_file(plan: &Plan, items: &[PlaylistTrack], session: &mut Session) -> bool {
+    apply_plan(items, plan, "file", session) && plan::is_complete(plan, items)
+}
+
+/// Shows the plan and, if the user agrees, carries it out, returning whether it was
+fn apply_plan(items: &[PlaylistTrack], plan: &Plan, label: &str, session: &mut Session) -> bool {
+    if plan.is_empty() || !ui::confirm_plan(plan, items, &session.playlists) {
+        return false;
+    }
+
+    for (index, action) in plan {
+        let track = &items[*index].track;
+        log::info!(
+            "Applying {} decision on track {}",
+            label,
+            ui::track::summary(track)
+        );
+
+        let result = perform(track, action.clone(), session);
+        // the decisions are the user's own, so the model learns from them too
+        if let Ok(action) = &result {
+            let tokens = classifier::tokens(track, &session.genres, None);
+            remember(track, tokens, action, session);
+        }
+        ui::rule_feedback(label, track, result);
+    }
+
+    true
+}
+
+/// Exports the playlists with the given names or IDs, or all of them if none are given
+pub fn export(
+    spotify: &AuthCodePkceSpotify,
+    playlists: &[SimplifiedPlaylist],
+    names: &[String],
+    format: export::Format,
+    directory: &Path,
+) {
+    let mut chosen: Vec<&SimplifiedPlaylist> = Vec::new();
+    for name in names {
+        match library::find_playlist(playlists, name) {
+            Some(playlist) => chosen.push(playlist),
+            None => {
+                ui::unknown_playlist(name);
+                return;
+            }
+        }
+    }
+    if names.is_empty() {
+        chosen = playlists.iter().collect();
+    }
+
+    ui::loading("Exporting");
+    match export::export(spotify, &chosen, format, directory) {
+        Ok(written) => ui::exported(&written),
+        Err(e) => {
+            log::error!("Failed to export playlists: {}", e);
+            ui::export_error(&e.to_string());
+        }
+    }
+}
+
+/// Snapshots the playlists and liked songs to the given file, or the data directory
+pub fn snapshot(
+    spotify: &AuthCodePkceSpotify,
+    playlists: &[SimplifiedPlaylist],
+    output: Option<&Path>,
+) {
+    ui::loading("Taking a snapshot of your library");
+    let membership = MembershipIndex::build(spotify, playlists);
+    let snapshot = Snapshot::take(spotify, playlists, &membership);
+
+    let path = output
+        .map(Path::to_path_buf)
+        .unwrap_or_else(|| snapshot.default_path(false));
+    match snapshot.save(&path) {
+        Ok(_) => ui::snapshot_saved(&path),
+        Err(e) => {
+            log::error!("Failed to save snapshot: {}", e);
+            ui::snapshot_error(&e.to_string());
+        }
+    }
+}
+
+/// Takes a snapshot before anything is changed, so there's always something to restore from
+pub fn auto_snapshot(
+    spotify: &AuthCodePkceSpotify,
+    playlists: &[SimplifiedPlaylist],
+    membership: &MembershipIndex,
+    keep: usize,
+) {
+    if keep == 0 {
+        return;
+    }
+    let snapshot = Snapshot::take(spotify, playlists, membership);
+    match snapshot::save_auto(&snapshot, keep) {
+        Ok(path) => log::info!("Saved snapshot to {}", path.display()),
+        Err(e) => {
+            log::error!("Failed to save snapshot: {}", e);
+            ui::snapshot_error(&e.to_string());
+        }
+    }
+}
+
+/// Finds the tracks of a local playlist file on Spotify and adds them to the destination,
+/// returning whether any were
+pub fn import(
+    spotify: &AuthCodePkceSpotify,
+    file: &Path,
+    destination: import::Destination,
+) -> bool {
+    let locals = match import::read(file) {
+        Ok(locals) => locals,
+        Err(e) => {
+            log::error!("Failed to read {}: {}", file.display(), e);
+            ui::import_error(&e);
+            return false;
+        }
+    };
+
+    // tracks already in the source would only be sorted twice
+    let mut found: Vec<TrackId<'static>> = match &destination {
+        import::Destination::New(_) => Vec::new(),
+        import::Destination::Source(playlist) => {
+            spotify::tracks_in_playlist(spotify, playlist.id.clone_static())
+                .into_iter()
+                .filter_map(|track| track.id)
+                .collect()
+        }
+    };
+    let already_there = found.len();
+    let mut missing: Vec<&import::LocalTrack> = Vec::new();
+
+    ui::loading("Finding the tracks on Spotify");
+    for local in &locals {
+        let track = match matching::find(local, spotify) {
+            Match::Found(track) => Some(*track),
+            Match::Ambiguous(candidates) => {
+                ui::choose_match(local, &candidates).map(|index| candidates[index].clone())
+            }
+            Match::NotFound => None,
+        };
+        match track.and_then(|track| track.id) {
+            Some(id) if found.contains(&id) => (),
+            Some(id) => found.push(id),
+            None => {
+                log::info!("Found no match for {}", local.summary());
+                missing.push(local);
+            }
+        }
+    }
+
+    let found = &found[already_there..];
+    if !ui::confirm_import(found.len(), &missing, destination.name()) {
+        return false;
+    }
+
+    let result = match &destination {
+        import::Destination::New(name) => spotify::create_playlist(spotify, name, None, None)
+            .and_then(|created| spotify::add_tracks(spotify, &created.id, found)),
+        import::Destination::Source(playlist) => {
+            spotify::add_tracks(spotify, &playlist.id.clone_static(), found)
+        }
+    };
+    match result {
+        Ok(()) => {
+            ui::added_tracks(found.len(), destination.name());
+            true
+        }
+        Err(e) => {
+            log::error!("Failed to import into {}: {}", destination.name(), e);
+            ui::import_error(&e.to_string());
+            false
+        }
+    }
+}
+
+/// Searches for tracks to add to the buffer playlist, either asking for queries and which results
+/// to add, or adding the best match of each "artist - title" line of stdin
+pub fn search(
+    spotify: &AuthCodePkceSpotify,
+    playlists: &[SimplifiedPlaylist],
+    buffer: Option<&String>,
+    bulk: bool,
+) {
+    let Some(name) = buffer else {
+        ui::no_buffer();
+        return;
+    };
+    let Some(playlist) = library::find_playlist(playlists, name) else {
+        ui::unknown_playlist(name);
+        return;
+    };
+    let mut buffer = search::Buffer::open(spotify, playlist);
+
+    if bulk {
+        let queries: Vec<import::LocalTrack> = io::stdin()
+            .lines()
+            .map_while(Result::ok)
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| import::LocalTrack::from_summary(&line))
+            .collect();
+
+        let mut found = Vec::new();
+        let mut missing = Vec::new();
+        for query in &queries {
+            // nobody is there to pick between tracks, so only sure matches are added
+            match matching::find(query, spotify) {
+                Match::Found(track) => found.extend(track.id),
+                _ => {
+                    log::info!("Found no sure match for {}", query.summary());
+                    missing.push(query);
+                }
+            }
+        }
+
+        match buffer.add(spotify, found) {
+            Ok(added) => ui::added_tracks(added, &buffer.name),
+            Err(e) => {
+                log::error!("Failed to add tracks to buffer {}: {}", buffer.id, e);
+                ui::buffer_error(&e.to_string());
+            }
+        }
+        if !missing.is_empty() {
+            ui::not_found(&missing);
+        }
+        return;
+    }
+
+    loop {
+        let query = ui::search_query();
+        if query.is_empty() {
+            break;
+        }
+        let hits = search::search(spotify, &query);
+        let track_ids: Vec<TrackId<'static>> = ui::choose_hits(&hits)
+            .into_iter()
+            .flat_map(|index| hits[index].track_ids(spotify))
+            .collect();
+        if track_ids.is_empty() {
+            continue;
+        }
+
+        match buffer.add(spotify, track_ids) {
+            Ok(added) => ui::added_tracks(added, &buffer.name),
+            Err(e) => {
+                log::error!("Failed to add tracks to buffer {}: {}", buffer.id, e);
+                ui::buffer_error(&e.to_string());
+            }
+        }
+    }
+}
+
+/// Restores playlists and liked songs from a snapshot, once the user agrees
+pub fn restore(
+    spotify: &AuthCodePkceSpotify,
+    playlists: &[SimplifiedPlaylist],
+    file: &Path,
+    keep: usize,
+) {
+    let snapshot = match Snapshot::load(file) {
+        Ok(snapshot) => snapshot,
+        Err(e) => {
+            log::error!("Failed to read snapshot {}: {}", file.display(), e);
+            ui::snapshot_error(&e);
+            return;
+        }
+    };
+
+    ui::loading("Comparing your library to the snapshot");
+    let membership = MembershipIndex::build(spotify, playlists);
+    let liked = spotify::liked_tracks(spotify);
+    let restore = snapshot.plan_restore(playlists, &membership, &liked);
+
+    if !ui::confirm_restore(&restore) {
+        return;
+    }
+    // restoring can move a lot of tracks around, so it deserves a safety net of its own
+    auto_snapshot(spotify, playlists, &membership, keep.max(1));
+
+    let errors = snapshot::restore(spotify, &restore);
+    ui::restored(&errors);
+}
+
+/// Compares a snapshot to a later one, or to the library as it is now if there is no later one
+pub fn diff(spotify: Option<&AuthCodePkceSpotify>, a: &Path, b: Option<&Path>, json: bool) {
+    let load = |path: &Path| {
+        Snapshot::load(path).inspect_err(|e| {
+            log::error!("Failed to read snapshot {}: {}", path.display(), e);
+            ui::snapshot_error(&format!("{}: {}", path.display(), e));
+        })
+    };
+
+    let Ok(before) = load(a) else {
+        return;
+    };
+    let after = match (b, spotify) {
+        (Some(b), _) => match load(b) {
+            Ok(after) => after,
+            Err(_) => return,
+        },
+        (None, Some(spotify)) => {
+            let playlists = spotify::my_playlists(spotify);
+            let membership = MembershipIndex::build(spotify, &playlists);
+            Snapshot::take(spotify, &playlists, &membership)
+        }
+        (None, None) => return,
+    };
+
+    let diff = before.diff(&after);
+    if json {
+        ui::diff_json(&diff);
+    } else {
+        ui::diff_report(&diff);
+    }
+}
+
+fn playlist_ids(indices: &[usize], playlists: &[SimplifiedPlaylist]) -> Vec<PlaylistId<'static>> {
+    indices
+        .iter()
+        .map(|index| playlists[*index].id.clone_static())
+        .collect()
+}
+
+fn playlist_indices(ids: &[PlaylistId<'static>], playlists: &[SimplifiedPlaylist]) -> Vec<usize> {
+    playlists
+        .iter()
+        .enumerate()
+        .filter(|(_, playlist)| ids.contains(&playlist.id))
+        .map(|(index, _)| index)
+        .collect()
+}
+
+/// Makes the change on Spotify and to the session's playlists, returning the ID of a playlist
+/// that was created
+fn edit_playlists(
+    edit: ui::PlaylistEdit,
+    session: &mut Session,
+) -> Result<Option<PlaylistId<'static>>, String> {
+    match edit {
+        ui::PlaylistEdit::Create {
+            name,
+            description,
+            public,
+        } => {
+            let description = (!description.is_empty()).then_some(description);
+            let playlist = spotify::create_playlist(
+                &session.spotify,
+                &name,
+                Some(public),
+                description.as_deref(),
+            )
+            .map_err(|e| e.to_string())?;
+            let id = playlist.id.clone_static();
+            if let Some(description) = description {
+                session
+                    .descriptions
+                    .insert(id.id().to_string(), description);
+            }
+            // added at the end, so the numbers of the others stay the same
+            session.playlists.push(playlist);
+            Ok(Some(id))
+        }
+        ui::PlaylistEdit::Rename { index, name } => {
+            let playlist = &mut session.playlists[index];
+            spotify::rename_playlist(&session.spotify, &playlist.id, &name)
+                .map_err(|e| e.to_string())?;
+            playlist.name = name;
+            Ok(None)
+        }
+        ui::PlaylistEdit::Delete(index) => {
+            if session.playlists[index].id == session.source_playlist_id {
+                return Err(String::from("the playlist being sorted can't be deleted"));
+            }
+            spotify::delete_playlist(&session.spotify, &session.playlists[index].id)
+                .map_err(|e| e.to_string())?;
+            session.playlists.remove(index);
+            Ok(None)
+        }
+    }
+}
+
+fn update_membership(
+    membership: &mut MembershipIndex,
+    track: &FullTrack,
+    action: &TrackAction,
+    source_playlist_id: &PlaylistId<'static>,
+) {
+    let Some(track_id) = &track.id else {
+        return;
+    };
+
+    match action {
+        TrackAction::Add(playlist_ids) => {
+            for playlist_id in playlist_ids {
+                membership.add(playlist_id, track);
+            }
+            membership.remove(source_playlist_id, track_id);
+        }
+        TrackAction::Remove(playlist_id) => membership.remove(playlist_id, track_id),
+        TrackAction::Skip => (),
+    }
+}
+
+/// Carries out the decisions that failed again, which are kept as failures if they still do
+pub fn retry_failures(session: &mut Session) {
+    for failure in std::mem::take(&mut session.summary.failures) {
+        log::info!("Retrying decision on track {}", failure.track);
+        let track = &failure.full_track;
+        let action = failure.remaining(|playlist_id| {
+            track.id.as_ref().is_some_and(|track_id| {
+                spotify::is_track_in_playlist(&session.spotify, playlist_id, track_id)
+            })
+        });
+        let result = perform(track, action, session);
+        // the decision as a whole has gone through, not just the part that was left
+        if result.is_ok() {
+            update_membership(
+                &mut session.membership,
+                track,
+                &failure.action,
+                &session.source_playlist_id,
+            );
+            let tokens = classifier::tokens(track, &session.genres, None);
+            remember(track, tokens, &failure.action, session);
+        }
+        ui::rule_feedback("retry", &failure.full_track, result);
+    }
 }
 
 pub fn log_out() -> bool {
@@ -0,0 +1,44 @@
+use std::collections::HashMap;
+
+use rspotify::{
+    model::{PlaylistId, SimplifiedPlaylist},
+    AuthCodePkceSpotify,
+};
+
+use crate::{
+    analysis::AnalysisCache,
+    audio::AudioPlayer,
+    hooks::Hooks,
+    library::{Genres, MembershipIndex},
+    presets::Presets,
+    scripting::Script,
+    snooze::Snoozed,
+    suggestions::classifier::{Classifier, History},
+    summary::Summary,
+    ui::chooser::Layout,
+};
+
+/// Everything that lives for as long as the user is sorting a source playlist
+pub struct Session {
+    pub spotify: AuthCodePkceSpotify,
+    pub playlists: Vec<SimplifiedPlaylist>,
+    /// Descriptions of the playlists that have one, by ID
+    pub descriptions: HashMap<String, String>,
+    pub source_playlist_id: PlaylistId<'static>,
+    pub membership: MembershipIndex,
+    pub genres: Genres,
+    pub history: History,
+    pub classifier: Classifier,
+    pub audio_player: Option<AudioPlayer>,
+    pub image_cache: HashMap<String, String>,
+    pub analysis_cache: AnalysisCache,
+    pub script: Option<Script>,
+    pub hooks: Hooks,
+    /// How the playlists are arranged on the track screen
+    pub layout: Layout,
+    pub presets: Presets,
+    pub snoozed: Snoozed,
+    /// How long skipping a track snoozes it for, if at all
+    pub snooze_days: Option<u32>,
+    pub summary: Summary,
+}
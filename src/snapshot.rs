@@ -0,0 +1,761 @@
+use std::{
+    collections::HashSet,
+    error::Error,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use chrono::{DateTime, Utc};
+use rspotify::{
+    model::{FullTrack, PlaylistId, SimplifiedPlaylist, TrackId},
+    prelude::Id,
+    AuthCodePkceSpotify, ClientResult,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{library::MembershipIndex, spotify, storage};
+
+// bumped whenever the format changes in a way older versions can't read
+const VERSION: u32 = 1;
+static SNAPSHOTS_DIR: &str = "snapshots";
+static AUTO_PREFIX: &str = "auto-";
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct SnapshotTrack {
+    id: String,
+    /// Name and artists aren't needed to restore, but make the archive readable
+    pub name: String,
+    pub artists: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct PlaylistSnapshot {
+    id: String,
+    name: String,
+    public: Option<bool>,
+    snapshot_id: String,
+    /// In playlist order
+    tracks: Vec<SnapshotTrack>,
+}
+
+/// The user's playlists and liked songs at a point in time
+#[derive(Serialize, Deserialize)]
+pub struct Snapshot {
+    version: u32,
+    taken_at: DateTime<Utc>,
+    playlists: Vec<PlaylistSnapshot>,
+    liked: Vec<SnapshotTrack>,
+}
+
+/// What restoring a playlist takes, for playlists that changed since the snapshot
+pub struct PlaylistRestore {
+    pub name: String,
+    /// None if the playlist no longer exists and has to be created
+    pub id: Option<PlaylistId<'static>>,
+    public: Option<bool>,
+    /// Tracks in the order they were in, which they'll be in again ahead of any others
+    tracks: Vec<TrackId<'static>>,
+    pub missing: usize,
+}
+
+pub struct Restore {
+    pub playlists: Vec<PlaylistRestore>,
+    /// Tracks to like again
+    pub liked: Vec<TrackId<'static>>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PlaylistChange {
+    Created,
+    Deleted,
+    Changed,
+}
+
+/// How a playlist differs between two snapshots
+#[derive(Serialize)]
+pub struct PlaylistDiff {
+    pub id: String,
+    pub name: String,
+    pub change: PlaylistChange,
+    /// The old name, if it was renamed
+    pub renamed_from: Option<String>,
+    pub added: Vec<SnapshotTrack>,
+    pub removed: Vec<SnapshotTrack>,
+    /// Whether tracks in both snapshots are in a different order
+    pub reordered: bool,
+}
+
+#[derive(Serialize)]
+pub struct Diff {
+    pub playlists: Vec<PlaylistDiff>,
+    pub liked: Vec<SnapshotTrack>,
+    pub unliked: Vec<SnapshotTrack>,
+}
+
+impl Diff {
+    pub fn is_empty(&self) -> bool {
+        self.playlists.is_empty() && self.liked.is_empty() && self.unliked.is_empty()
+    }
+}
+
+/// Tracks in `a` that aren't in `b`
+fn missing_from(a: &[SnapshotTrack], b: &[SnapshotTrack]) -> Vec<SnapshotTrack> {
+    let ids: HashSet<&String> = b.iter().map(|track| &track.id).collect();
+    let mut seen: HashSet<&String> = HashSet::new();
+    a.iter()
+        .filter(|track| !ids.contains(&track.id) && seen.insert(&track.id))
+        .cloned()
+        .collect()
+}
+
+/// Whether the tracks in both lists are in a different order in each
+fn is_reordered(a: &[SnapshotTrack], b: &[SnapshotTrack]) -> bool {
+    let common = |from: &[SnapshotTrack], other: &[SnapshotTrack]| -> Vec<String> {
+        let ids: HashSet<&String> = other.iter().map(|track| &track.id).collect();
+        from.iter()
+            .filter(|track| ids.contains(&track.id))
+            .map(|track| track.id.clone())
+            .collect()
+    };
+    common(a, b) != common(b, a)
+}
+
+impl SnapshotTrack {
+    fn new(track: &FullTrack) -> Option<SnapshotTrack> {
+        Some(SnapshotTrack {
+            // local files can't be added back through the API
+            id: track.id.as_ref()?.id().to_string(),
+            name: track.name.clone(),
+            artists: track
+                .artists
+                .iter()
+                .map(|artist| artist.name.clone())
+                .collect(),
+        })
+    }
+}
+
+fn track_ids(tracks: &[SnapshotTrack]) -> Vec<TrackId<'static>> {
+    tracks
+        .iter()
+        .filter_map(|track| TrackId::from_id(track.id.clone()).ok())
+        .collect()
+}
+
+fn current_ids(tracks: &[FullTrack]) -> Vec<TrackId<'static>> {
+    tracks.iter().filter_map(|track| track.id.clone()).collect()
+}
+
+impl Snapshot {
+    /// Snapshots the playlists as indexed, which saves fetching them again, plus liked songs
+    pub fn take(
+        spotify: &AuthCodePkceSpotify,
+        playlists: &[SimplifiedPlaylist],
+        membership: &MembershipIndex,
+    ) -> Snapshot {
+        Snapshot::new(playlists, membership, &spotify::liked_tracks(spotify))
+    }
+
+    fn new(
+        playlists: &[SimplifiedPlaylist],
+        membership: &MembershipIndex,
+        liked: &[FullTrack],
+    ) -> Snapshot {
+        Snapshot {
+            version: VERSION,
+            taken_at: Utc::now(),
+            playlists: playlists
+                .iter()
+                .map(|playlist| PlaylistSnapshot {
+                    id: playlist.id.id().to_string(),
+                    name: playlist.name.clone(),
+                    public: playlist.public,
+                    snapshot_id: playlist.snapshot_id.clone(),
+                    tracks: membership
+                        .tracks(&playlist.id)
+                        .iter()
+                        .filter_map(SnapshotTrack::new)
+                        .collect(),
+                })
+                .collect(),
+            liked: liked.iter().filter_map(SnapshotTrack::new).collect(),
+        }
+    }
+
+    pub fn load(path: &Path) -> Result<Snapshot, String> {
+        let contents = fs::read_to_string(path).map_err(|e| e.to_string())?;
+        let snapshot: Snapshot = serde_json::from_str(&contents).map_err(|e| e.to_string())?;
+        if snapshot.version > VERSION {
+            return Err(format!(
+                "made by a newer version of sortify (format {}, this version reads up to {})",
+                snapshot.version, VERSION
+            ));
+        }
+        Ok(snapshot)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    /// Where to keep the snapshot in the data directory when no other place is given
+    pub fn default_path(&self, auto: bool) -> PathBuf {
+        storage::path(SNAPSHOTS_DIR).join(format!(
+            "{}{}.json",
+            if auto { AUTO_PREFIX } else { "" },
+            self.taken_at.format("%Y-%m-%dT%H-%M-%S")
+        ))
+    }
+
+    /// What changed from this snapshot to a later one
+    pub fn diff(&self, later: &Snapshot) -> Diff {
+        let mut playlists = Vec::new();
+
+        for before in &self.playlists {
+            match later.playlists.iter().find(|after| after.id == before.id) {
+                Some(after) => {
+                    let added = missing_from(&after.tracks, &before.tracks);
+                    let removed = missing_from(&before.tracks, &after.tracks);
+                    let reordered = is_reordered(&before.tracks, &after.tracks);
+                    let renamed_from = (before.name != after.name).then(|| before.name.clone());
+
+                    if !added.is_empty()
+                        || !removed.is_empty()
+                        || reordered
+                        || renamed_from.is_some()
+                    {
+                        playlists.push(PlaylistDiff {
+                            id: after.id.clone(),
+                            name: after.name.clone(),
+                            change: PlaylistChange::Changed,
+                            renamed_from,
+                            added,
+                            removed,
+                            reordered,
+                        });
+                    }
+                }
+                None => playlists.push(PlaylistDiff {
+                    id: before.id.clone(),
+                    name: before.name.clone(),
+                    change: PlaylistChange::Deleted,
+                    renamed_from: None,
+                    added: Vec::new(),
+                    removed: missing_from(&before.tracks, &[]),
+                    reordered: false,
+                }),
+            }
+        }
+
+        for after in &later.playlists {
+            if !self.playlists.iter().any(|before| before.id == after.id) {
+                playlists.push(PlaylistDiff {
+                    id: after.id.clone(),
+                    name: after.name.clone(),
+                    change: PlaylistChange::Created,
+                    renamed_from: None,
+                    added: missing_from(&after.tracks, &[]),
+                    removed: Vec::new(),
+                    reordered: false,
+                });
+            }
+        }
+
+        Diff {
+            playlists,
+            liked: missing_from(&later.liked, &self.liked),
+            unliked: missing_from(&self.liked, &later.liked),
+        }
+    }
+
+    /// What it takes to bring the playlists and liked songs back to how they were. Tracks added
+    /// since are kept, after the restored ones.
+    pub fn plan_restore(
+        &self,
+        playlists: &[SimplifiedPlaylist],
+        membership: &MembershipIndex,
+        liked: &[FullTrack],
+    ) -> Restore {
+        let mut restores = Vec::new();
+
+        for archived in &self.playlists {
+            let existing = playlists
+                .iter()
+                .find(|playlist| playlist.id.id() == archived.id);
+            let current = existing
+                .map(|playlist| current_ids(membership.tracks(&playlist.id)))
+                .unwrap_or_default();
+
+            let tracks = track_ids(&archived.tracks);
+            let archived_ids: HashSet<&TrackId> = tracks.iter().collect();
+            let current_set: HashSet<&TrackId> = current.iter().collect();
+            let missing = archived_ids.difference(&current_set).count();
+            let newer = current.iter().filter(|id| !archived_ids.contains(id));
+            let restored: Vec<&TrackId> = tracks.iter().chain(newer).collect();
+
+            // tracks only differ in order when none are missing
+            if existing.is_some() && restored.iter().copied().eq(current.iter()) {
+                continue;
+            }
+            restores.push(PlaylistRestore {
+                name: archived.name.clone(),
+                id: existing.map(|playlist| playlist.id.clone_static()),
+                public: archived.public,
+                tracks,
+                missing,
+            });
+        }
+
+        let liked_now: HashSet<TrackId> = current_ids(liked).into_iter().collect();
+        Restore {
+            playlists: restores,
+            liked: track_ids(&self.liked)
+                .into_iter()
+                .filter(|id| !liked_now.contains(id))
+                .collect(),
+        }
+    }
+}
+
+/// A change to the order of a playlist's items, by position
+#[derive(PartialEq, Debug)]
+enum Step {
+    Move {
+        from: usize,
+        to: usize,
+    },
+    Insert {
+        tracks: Vec<TrackId<'static>>,
+        at: usize,
+    },
+}
+
+impl Step {
+    fn apply(&self, items: &mut Vec<Option<TrackId<'static>>>) {
+        match self {
+            Step::Move { from, to } => {
+                let item = items.remove(*from);
+                items.insert(*to, item);
+            }
+            Step::Insert { tracks, at } => {
+                items.splice(*at..*at, tracks.iter().cloned().map(Some));
+            }
+        }
+    }
+}
+
+/// What puts the tracks back in the order they were in at the start of a playlist with the items
+/// as they are now: inserting those missing and moving those out of place. Nothing is removed, so
+/// tracks keep when they were added and by whom, a failure part way leaves every track in the
+/// playlist, and anything else ends up after them.
+fn restore_steps(
+    mut current: Vec<Option<TrackId<'static>>>,
+    tracks: &[TrackId<'static>],
+) -> Vec<Step> {
+    let is_at = |id: &TrackId, item: &Option<TrackId>| item.as_ref() == Some(id);
+    let mut steps = Vec::new();
+
+    let mut position = 0;
+    while position < tracks.len() {
+        let id = &tracks[position];
+        let step = match current[position..].iter().position(|item| is_at(id, item)) {
+            Some(0) => {
+                position += 1;
+                continue;
+            }
+            Some(offset) => Step::Move {
+                from: position + offset,
+                to: position,
+            },
+            None => {
+                // tracks missing one after the other go back in together
+                let run: Vec<TrackId<'static>> = tracks[position..]
+                    .iter()
+                    .take_while(|id| !current[position..].iter().any(|item| is_at(id, item)))
+                    .cloned()
+                    .collect();
+                Step::Insert {
+                    tracks: run,
+                    at: position,
+                }
+            }
+        };
+
+        step.apply(&mut current);
+        position += match &step {
+            Step::Move { .. } => 1,
+            Step::Insert { tracks, .. } => tracks.len(),
+        };
+        steps.push(step);
+    }
+
+    steps
+}
+
+fn restore_tracks(
+    spotify: &AuthCodePkceSpotify,
+    playlist_id: &PlaylistId<'static>,
+    tracks: &[TrackId<'static>],
+) -> ClientResult<()> {
+    let current = spotify::playlist_positions(spotify, playlist_id)?;
+    for step in restore_steps(current, tracks) {
+        match step {
+            Step::Move { from, to } => spotify::move_item(spotify, playlist_id, from, to)?,
+            Step::Insert { tracks, at } => {
+                spotify::insert_tracks(spotify, playlist_id, &tracks, at)?
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Carries out a restore, returning what failed
+pub fn restore(spotify: &AuthCodePkceSpotify, restore: &Restore) -> Vec<String> {
+    let mut errors = Vec::new();
+
+    for playlist in &restore.playlists {
+        let id = match &playlist.id {
+            Some(id) => id.clone_static(),
+            None => {
+                match spotify::create_playlist(spotify, &playlist.name, playlist.public, None) {
+                    Ok(created) => created.id,
+                    Err(e) => {
+                        log::error!("Failed to create playlist {}: {}", playlist.name, e);
+                        errors.push(playlist.name.clone());
+                        continue;
+                    }
+                }
+            }
+        };
+
+        if let Err(e) = restore_tracks(spotify, &id, &playlist.tracks) {
+            log::error!("Failed to restore playlist {}: {}", playlist.name, e);
+            errors.push(playlist.name.clone());
+        }
+    }
+
+    if let Err(e) = spotify::like_tracks(spotify, &restore.liked) {
+        log::error!("Failed to restore liked songs: {}", e);
+        errors.push(String::from("Liked Songs"));
+    }
+
+    errors
+}
+
+/// Saves a snapshot taken automatically, removing the oldest ones beyond the number to keep
+pub fn save_auto(snapshot: &Snapshot, keep: usize) -> Result<PathBuf, Box<dyn Error>> {
+    let path = snapshot.default_path(true);
+    snapshot.save(&path)?;
+
+    // timestamps in the names sort in the order they were taken
+    let mut autos: Vec<PathBuf> = fs::read_dir(storage::path(SNAPSHOTS_DIR))?
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_name().to_string_lossy().starts_with(AUTO_PREFIX))
+        .map(|entry| entry.path())
+        .collect();
+    autos.sort();
+    for old in autos.iter().rev().skip(keep) {
+        _ = fs::remove_file(old);
+    }
+
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use super::*;
+    use crate::testing;
+
+    fn id(n: u8) -> String {
+        format!("{:022}", n)
+    }
+
+    fn track_id(n: u8) -> TrackId<'static> {
+        TrackId::from_id(id(n)).unwrap()
+    }
+
+    fn archived(n: u8) -> SnapshotTrack {
+        SnapshotTrack {
+            id: id(n),
+            name: format!("Track {}", n),
+            artists: vec![String::from("Artist")],
+        }
+    }
+
+    fn full(n: u8) -> FullTrack {
+        testing::track(&id(n), &format!("Track {}", n), "Artist", 1)
+    }
+
+    fn snapshot(playlists: &[(u8, &str, &[u8])], liked: &[u8]) -> Snapshot {
+        Snapshot {
+            version: VERSION,
+            taken_at: Utc::now(),
+            playlists: playlists
+                .iter()
+                .map(|(n, name, tracks)| PlaylistSnapshot {
+                    id: format!("{:022}", 100 + *n as u32),
+                    name: name.to_string(),
+                    public: Some(false),
+                    snapshot_id: String::new(),
+                    tracks: tracks.iter().copied().map(archived).collect(),
+                })
+                .collect(),
+            liked: liked.iter().copied().map(archived).collect(),
+        }
+    }
+
+    /// The playlists with the tracks they have now
+    fn live(playlists: &[(u8, &str, &[u8])]) -> (Vec<SimplifiedPlaylist>, MembershipIndex) {
+        let mut membership = MembershipIndex::default();
+        let playlists = playlists
+            .iter()
+            .map(|(n, name, tracks)| {
+                let playlist = testing::playlist(&format!("{:022}", 100 + *n as u32), name);
+                for track in tracks.iter().copied() {
+                    membership.add(&playlist.id, &full(track));
+                }
+                playlist
+            })
+            .collect();
+        (playlists, membership)
+    }
+
+    /// The playlist's tracks after carrying out the steps to restore the archived order
+    fn restored(current: &[Option<u8>], archived: &[u8]) -> Vec<Option<u8>> {
+        let mut items: Vec<Option<TrackId<'static>>> =
+            current.iter().map(|n| n.map(track_id)).collect();
+        let tracks: Vec<TrackId<'static>> = archived.iter().copied().map(track_id).collect();
+        for step in restore_steps(items.clone(), &tracks) {
+            step.apply(&mut items);
+        }
+        items
+            .iter()
+            .map(|item| {
+                item.as_ref()
+                    .map(|id| id.id().trim_start_matches('0').parse().unwrap())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn nothing_to_do_in_order() {
+        let tracks: Vec<TrackId<'static>> = (1..=3).map(track_id).collect();
+        let current = tracks.iter().cloned().map(Some).collect();
+
+        assert_eq!(restore_steps(current, &tracks), Vec::new());
+    }
+
+    #[test]
+    fn reordered_tracks_are_moved_back() {
+        assert_eq!(
+            restored(&[Some(3), Some(1), Some(2)], &[1, 2, 3]),
+            vec![Some(1), Some(2), Some(3)]
+        );
+        let tracks: Vec<TrackId<'static>> = (1..=3).map(track_id).collect();
+        let current = vec![Some(track_id(2)), Some(track_id(1)), Some(track_id(3))];
+        assert_eq!(
+            restore_steps(current, &tracks),
+            vec![Step::Move { from: 1, to: 0 }]
+        );
+    }
+
+    #[test]
+    fn removed_tracks_are_inserted_together() {
+        assert_eq!(
+            restored(&[Some(1), Some(4)], &[1, 2, 3, 4]),
+            vec![Some(1), Some(2), Some(3), Some(4)]
+        );
+        let tracks: Vec<TrackId<'static>> = (1..=4).map(track_id).collect();
+        let current = vec![Some(track_id(1)), Some(track_id(4))];
+        assert_eq!(
+            restore_steps(current, &tracks),
+            vec![Step::Insert {
+                tracks: vec![track_id(2), track_id(3)],
+                at: 1
+            }]
+        );
+    }
+
+    #[test]
+    fn newer_tracks_end_up_after_the_restored_ones() {
+        assert_eq!(
+            restored(&[Some(5), None, Some(3), Some(1)], &[1, 2, 3]),
+            vec![Some(1), Some(2), Some(3), Some(5), None]
+        );
+    }
+
+    #[test]
+    fn emptied_playlist_is_filled_again() {
+        assert_eq!(restored(&[], &[1, 2, 3]), vec![Some(1), Some(2), Some(3)]);
+    }
+
+    #[test]
+    fn unchanged_playlists_are_left_alone() {
+        let snapshot = snapshot(&[(1, "Jazz", &[1, 2]), (2, "Chill", &[3])], &[1]);
+        let (playlists, membership) = live(&[(1, "Jazz", &[1, 2]), (2, "Chill", &[3])]);
+        let restore = snapshot.plan_restore(&playlists, &membership, &[full(1)]);
+
+        assert!(restore.playlists.is_empty());
+        assert!(restore.liked.is_empty());
+    }
+
+    #[test]
+    fn tracks_added_since_are_kept() {
+        let snapshot = snapshot(&[(1, "Jazz", &[1, 2])], &[]);
+        let (playlists, membership) = live(&[(1, "Jazz", &[1, 2, 3])]);
+
+        assert!(snapshot
+            .plan_restore(&playlists, &membership, &[])
+            .playlists
+            .is_empty());
+    }
+
+    #[test]
+    fn changed_playlists_are_restored() {
+        let snapshot = snapshot(
+            &[
+                (1, "Jazz", &[1, 2, 3]),
+                (2, "Chill", &[4, 5]),
+                (3, "Gone", &[6]),
+            ],
+            &[1, 2],
+        );
+        let (playlists, membership) = live(&[(1, "Jazz", &[3, 1]), (2, "Chill", &[5, 4])]);
+        let restore = snapshot.plan_restore(&playlists, &membership, &[full(2)]);
+
+        let summary: Vec<(&str, bool, usize)> = restore
+            .playlists
+            .iter()
+            .map(|playlist| {
+                (
+                    playlist.name.as_str(),
+                    playlist.id.is_some(),
+                    playlist.missing,
+                )
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            vec![("Jazz", true, 1), ("Chill", true, 0), ("Gone", false, 1)]
+        );
+        assert_eq!(
+            restore.playlists[0].tracks,
+            vec![track_id(1), track_id(2), track_id(3)]
+        );
+        assert_eq!(restore.liked, vec![track_id(1)]);
+    }
+
+    fn ids(tracks: &[SnapshotTrack]) -> Vec<&str> {
+        tracks.iter().map(|track| track.id.as_str()).collect()
+    }
+
+    #[test]
+    fn same_snapshot_has_no_diff() {
+        let a = snapshot(&[(1, "Jazz", &[1, 2])], &[1]);
+        let b = snapshot(&[(1, "Jazz", &[1, 2])], &[1]);
+
+        assert!(a.diff(&b).is_empty());
+    }
+
+    #[test]
+    fn diff_of_changed_playlists() {
+        let before = snapshot(
+            &[
+                (1, "Jazz", &[1, 2, 3]),
+                (2, "Chill", &[4, 5]),
+                (3, "Cosy", &[6]),
+            ],
+            &[],
+        );
+        let after = snapshot(
+            &[
+                (1, "Jazz", &[1, 3, 7, 7]),
+                (2, "Chill", &[5, 4]),
+                (3, "Warm", &[6]),
+            ],
+            &[],
+        );
+        let diff = before.diff(&after);
+
+        assert_eq!(diff.playlists.len(), 3);
+        let jazz = &diff.playlists[0];
+        assert!(matches!(jazz.change, PlaylistChange::Changed));
+        assert_eq!(ids(&jazz.added), vec![id(7)]);
+        assert_eq!(ids(&jazz.removed), vec![id(2)]);
+        assert!(!jazz.reordered);
+        let chill = &diff.playlists[1];
+        assert!(chill.reordered && chill.added.is_empty() && chill.removed.is_empty());
+        let cosy = &diff.playlists[2];
+        assert_eq!(cosy.name, "Warm");
+        assert_eq!(cosy.renamed_from.as_deref(), Some("Cosy"));
+    }
+
+    #[test]
+    fn diff_of_created_and_deleted_playlists() {
+        let before = snapshot(&[(1, "Jazz", &[1, 2])], &[1, 2]);
+        let after = snapshot(&[(2, "Chill", &[3])], &[2, 3]);
+        let diff = before.diff(&after);
+
+        let changes: Vec<(&str, &PlaylistChange)> = diff
+            .playlists
+            .iter()
+            .map(|playlist| (playlist.name.as_str(), &playlist.change))
+            .collect();
+        assert!(matches!(
+            changes[..],
+            [
+                ("Jazz", PlaylistChange::Deleted),
+                ("Chill", PlaylistChange::Created)
+            ]
+        ));
+        assert_eq!(ids(&diff.playlists[0].removed), vec![id(1), id(2)]);
+        assert_eq!(ids(&diff.playlists[1].added), vec![id(3)]);
+        assert_eq!(ids(&diff.liked), vec![id(3)]);
+        assert_eq!(ids(&diff.unliked), vec![id(1)]);
+    }
+
+    #[test]
+    fn diff_of_saved_snapshot_against_live_library() {
+        let (playlists, membership) = live(&[(1, "Jazz", &[1, 2]), (2, "Chill", &[3])]);
+        let path = env::temp_dir()
+            .join(format!("sortify-snapshot-{}", std::process::id()))
+            .join("snapshot.json");
+        Snapshot::new(&playlists, &membership, &[full(1)])
+            .save(&path)
+            .unwrap();
+        let saved = Snapshot::load(&path);
+        _ = fs::remove_dir_all(path.parent().unwrap());
+        let saved = saved.unwrap();
+
+        let unchanged = Snapshot::new(&playlists, &membership, &[full(1)]);
+        assert!(saved.diff(&unchanged).is_empty());
+
+        let (playlists, membership) = live(&[(1, "Jazz", &[2, 1, 4])]);
+        let diff = saved.diff(&Snapshot::new(&playlists, &membership, &[]));
+        let jazz = &diff.playlists[0];
+        assert_eq!(ids(&jazz.added), vec![id(4)]);
+        assert!(jazz.reordered);
+        assert!(matches!(diff.playlists[1].change, PlaylistChange::Deleted));
+        assert_eq!(ids(&diff.unliked), vec![id(1)]);
+    }
+
+    #[test]
+    fn snapshots_from_newer_versions_are_refused() {
+        let path = env::temp_dir().join(format!("sortify-newer-{}.json", std::process::id()));
+        let mut newer = snapshot(&[], &[]);
+        newer.version = VERSION + 1;
+        newer.save(&path).unwrap();
+        let loaded = Snapshot::load(&path);
+        _ = fs::remove_file(&path);
+
+        assert!(loaded.is_err());
+    }
+}
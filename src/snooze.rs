@@ -0,0 +1,48 @@
+use std::collections::HashMap;
+
+use chrono::{DateTime, TimeDelta, Utc};
+use rspotify::{model::FullTrack, prelude::Id};
+use serde::{Deserialize, Serialize};
+
+use crate::storage;
+
+static SNOOZE_FILE: &str = "snoozed.json";
+
+/// Skipped tracks that shouldn't come up again for a while, even in later sessions
+#[derive(Serialize, Deserialize, Default)]
+#[serde(default)]
+pub struct Snoozed {
+    /// When each track, by ID, can come up again
+    until: HashMap<String, DateTime<Utc>>,
+}
+
+impl Snoozed {
+    /// Loads the snoozed tracks, forgetting those whose time is up
+    pub fn load() -> Snoozed {
+        let mut snoozed: Snoozed = storage::load_or_set_aside(SNOOZE_FILE);
+        let now = Utc::now();
+        snoozed.until.retain(|_, until| *until > now);
+        snoozed
+    }
+
+    pub fn is_snoozed(&self, track: &FullTrack) -> bool {
+        track
+            .id
+            .as_ref()
+            .is_some_and(|id| self.until.contains_key(id.id()))
+    }
+
+    pub fn snooze(&mut self, track: &FullTrack, days: u32) {
+        let Some(id) = &track.id else {
+            return;
+        };
+        log::info!("Snoozing track {} for {} days", id.id(), days);
+        self.until.insert(
+            id.id().to_string(),
+            Utc::now() + TimeDelta::days(i64::from(days)),
+        );
+        if let Err(e) = storage::save(SNOOZE_FILE, self) {
+            log::error!("Failed to save snoozed tracks: {}", e);
+        }
+    }
+}
@@ -1,25 +1,53 @@
-use std::iter;
+use std::{collections::HashMap, iter};
 
+use chrono::{DateTime, Utc};
 use rspotify::{
-    model::{FullTrack, PlayableItem, PlaylistId, SimplifiedPlaylist, TrackId},
+    http::Query,
+    model::{
+        AlbumId, ArtistId, FullArtist, FullTrack, Market, PlayableItem, PlaylistId,
+        PlaylistTracksRef, SearchResult, SearchType, SimplifiedAlbum, SimplifiedPlaylist, TrackId,
+    },
     prelude::*,
-    scopes, AuthCodePkceSpotify, Config, Credentials, OAuth,
+    scopes, AuthCodePkceSpotify, ClientResult, Config, Credentials, OAuth,
 };
+use serde::Deserialize;
 
-static APP_ID: &'static str = "9c7a1f7848ba4f5b839b4e199e2ed1a9";
-static REDIRECT_URI: &'static str = "http://localhost:8888/callback";
-static SCOPES: [&'static str; 4] = [
+static APP_ID: &str = "9c7a1f7848ba4f5b839b4e199e2ed1a9";
+static REDIRECT_URI: &str = "http://localhost:8888/callback";
+static SCOPES: [&str; 6] = [
     "playlist-read-private",
     "playlist-read-collaborative",
+    "playlist-modify-public",
+    "playlist-modify-private",
     "user-library-read",
     "user-library-modify",
 ];
 
+#[derive(Clone)]
 pub enum SpotifyPlaylistsError {
     Add(Vec<String>),
     Remove(Vec<String>),
 }
 
+impl SpotifyPlaylistsError {
+    pub fn message(&self) -> String {
+        match self {
+            SpotifyPlaylistsError::Add(names) => format!("Failed to add to {}", names.join(", ")),
+            SpotifyPlaylistsError::Remove(names) => {
+                format!("Failed to remove from {}", names.join(", "))
+            }
+        }
+    }
+}
+
+/// A track along with when and by whom it was added to a playlist
+pub struct PlaylistTrack {
+    pub track: FullTrack,
+    pub added_at: Option<DateTime<Utc>>,
+    /// User ID of whoever added the track
+    pub added_by: Option<String>,
+}
+
 pub fn authenticate() -> AuthCodePkceSpotify {
     let creds = Credentials::new_pkce(APP_ID);
     let oauth = OAuth {
@@ -42,6 +70,16 @@ pub fn authenticate() -> AuthCodePkceSpotify {
     spotify
 }
 
+/// The ID in a track's URI (spotify:track:ID) or link (https://open.spotify.com/track/ID?si=...)
+pub fn track_id(reference: &str) -> &str {
+    let reference = reference.trim();
+    let id = reference
+        .strip_prefix("spotify:track:")
+        .or_else(|| reference.split("/track/").nth(1))
+        .unwrap_or(reference);
+    id.split(['?', '/']).next().unwrap_or(id)
+}
+
 pub fn user_name(spotify: &AuthCodePkceSpotify) -> Option<String> {
     spotify.current_user().unwrap().display_name
 }
@@ -55,27 +93,342 @@ pub fn my_playlists(spotify: &AuthCodePkceSpotify) -> Vec<SimplifiedPlaylist> {
         .collect()
 }
 
-pub fn tracks_in_playlist(
+/// A playlist as listed by the API, for the description rspotify leaves out
+#[derive(Deserialize)]
+struct DescribedPlaylist {
+    id: String,
+    description: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct DescribedPage {
+    items: Vec<Option<DescribedPlaylist>>,
+    next: Option<String>,
+}
+
+/// Descriptions of the user's playlists by ID, for those that have one
+pub fn playlist_descriptions(spotify: &AuthCodePkceSpotify) -> HashMap<String, String> {
+    let mut descriptions = HashMap::new();
+    let mut offset = 0;
+
+    loop {
+        let offset_text = offset.to_string();
+        let query = Query::from([("limit", "50"), ("offset", offset_text.as_str())]);
+        let page: DescribedPage = match spotify
+            .api_get("me/playlists", &query)
+            .map_err(|e| e.to_string())
+            .and_then(|body| serde_json::from_str(&body).map_err(|e| e.to_string()))
+        {
+            Ok(page) => page,
+            Err(e) => {
+                log::warn!("Failed to get playlist descriptions: {}", e);
+                break;
+            }
+        };
+
+        offset += page.items.len();
+        for playlist in page.items.into_iter().flatten() {
+            // descriptions come HTML-escaped
+            if let Some(description) = playlist.description.filter(|d| !d.is_empty()) {
+                let description = description
+                    .replace("&#x27;", "'")
+                    .replace("&quot;", "\"")
+                    .replace("&lt;", "<")
+                    .replace("&gt;", ">")
+                    .replace("&amp;", "&");
+                descriptions.insert(playlist.id, description);
+            }
+        }
+        if page.next.is_none() {
+            break;
+        }
+    }
+
+    descriptions
+}
+
+pub fn items_in_playlist(
     spotify: &AuthCodePkceSpotify,
     playlist_id: PlaylistId<'static>,
-) -> Vec<FullTrack> {
+) -> Vec<PlaylistTrack> {
     spotify
         .playlist_items(playlist_id.clone(), None, None)
         .filter_map(|result| {
-            result
+            let playlist_item = result
                 .inspect_err(|e| {
                     log::warn!("Error getting item from playlist {}: {}", playlist_id, e)
                 })
+                .ok()?;
+
+            match playlist_item.track? {
+                PlayableItem::Track(full_track) => Some(PlaylistTrack {
+                    track: full_track,
+                    added_at: playlist_item.added_at,
+                    added_by: playlist_item.added_by.map(|user| user.id.id().to_string()),
+                }),
+                _ => None,
+            }
+        })
+        .collect()
+}
+
+pub fn tracks_in_playlist(
+    spotify: &AuthCodePkceSpotify,
+    playlist_id: PlaylistId<'static>,
+) -> Vec<FullTrack> {
+    items_in_playlist(spotify, playlist_id)
+        .into_iter()
+        .map(|item| item.track)
+        .collect()
+}
+
+/// Genres of each artist, keyed by artist ID
+pub fn artist_genres(
+    spotify: &AuthCodePkceSpotify,
+    artist_ids: &[ArtistId<'static>],
+) -> HashMap<String, Vec<String>> {
+    // the API accepts at most 50 artists per request
+    artist_ids
+        .chunks(50)
+        .filter_map(|chunk| {
+            spotify
+                .artists(chunk.iter().cloned())
+                .inspect_err(|e| log::warn!("Failed to get artists: {}", e))
+                .ok()
+        })
+        .flatten()
+        .map(|artist| (artist.id.id().to_string(), artist.genres))
+        .collect()
+}
+
+/// Up to `limit` results of one type for a search query, which can use field filters such as
+/// isrc: or artist:
+fn search(
+    spotify: &AuthCodePkceSpotify,
+    query: &str,
+    search_type: SearchType,
+    limit: u32,
+) -> Option<SearchResult> {
+    spotify
+        .search(query, search_type, None, None, Some(limit), None)
+        .inspect_err(|e| log::warn!("Failed to search for {}: {}", query, e))
+        .ok()
+}
+
+pub fn search_tracks(spotify: &AuthCodePkceSpotify, query: &str, limit: u32) -> Vec<FullTrack> {
+    match search(spotify, query, SearchType::Track, limit) {
+        Some(SearchResult::Tracks(page)) => page.items,
+        _ => Vec::new(),
+    }
+}
+
+pub fn search_albums(
+    spotify: &AuthCodePkceSpotify,
+    query: &str,
+    limit: u32,
+) -> Vec<SimplifiedAlbum> {
+    match search(spotify, query, SearchType::Album, limit) {
+        Some(SearchResult::Albums(page)) => page.items,
+        _ => Vec::new(),
+    }
+}
+
+pub fn search_artists(spotify: &AuthCodePkceSpotify, query: &str, limit: u32) -> Vec<FullArtist> {
+    match search(spotify, query, SearchType::Artist, limit) {
+        Some(SearchResult::Artists(page)) => page.items,
+        _ => Vec::new(),
+    }
+}
+
+/// An album's tracks, in order
+pub fn album_tracks(
+    spotify: &AuthCodePkceSpotify,
+    album_id: AlbumId<'static>,
+) -> Vec<TrackId<'static>> {
+    spotify
+        .album_track(album_id, None)
+        .filter_map(|result| {
+            result
+                .inspect_err(|e| log::warn!("Error getting album track: {}", e))
+                .ok()
+        })
+        .filter_map(|track| track.id)
+        .collect()
+}
+
+/// The artist's most popular tracks, in the user's country
+pub fn artist_top_tracks(
+    spotify: &AuthCodePkceSpotify,
+    artist_id: ArtistId<'static>,
+) -> Vec<TrackId<'static>> {
+    spotify
+        .artist_top_tracks(artist_id.clone(), Some(Market::FromToken))
+        .inspect_err(|e| log::warn!("Failed to get top tracks of artist {}: {}", artist_id, e))
+        .unwrap_or_default()
+        .into_iter()
+        .filter_map(|track| track.id)
+        .collect()
+}
+
+pub fn track(spotify: &AuthCodePkceSpotify, track_id: &TrackId<'static>) -> Option<FullTrack> {
+    spotify
+        .track(track_id.clone(), None)
+        .inspect_err(|e| log::warn!("Failed to get track {}: {}", track_id, e))
+        .ok()
+}
+
+pub fn liked_tracks(spotify: &AuthCodePkceSpotify) -> Vec<FullTrack> {
+    spotify
+        .current_user_saved_tracks(None)
+        .filter_map(|result| {
+            result
+                .inspect_err(|e| log::warn!("Error getting liked track: {}", e))
                 .ok()
-                .and_then(|playlist_item| playlist_item.track)
-                .and_then(|playable_track| match playable_track {
-                    PlayableItem::Track(full_track) => Some(full_track),
-                    _ => None,
-                })
         })
+        .map(|saved| saved.track)
         .collect()
 }
 
+pub fn like_tracks(
+    spotify: &AuthCodePkceSpotify,
+    track_ids: &[TrackId<'static>],
+) -> ClientResult<()> {
+    // the API accepts at most 50 tracks per request
+    for chunk in track_ids.chunks(50) {
+        spotify.current_user_saved_tracks_add(chunk.iter().cloned())?;
+    }
+    Ok(())
+}
+
+pub fn create_playlist(
+    spotify: &AuthCodePkceSpotify,
+    name: &str,
+    public: Option<bool>,
+    description: Option<&str>,
+) -> ClientResult<SimplifiedPlaylist> {
+    let user_id = spotify.current_user()?.id;
+    log::info!("Creating playlist {}", name);
+    let playlist = spotify.user_playlist_create(user_id, name, public, None, description)?;
+    Ok(SimplifiedPlaylist {
+        collaborative: playlist.collaborative,
+        external_urls: playlist.external_urls,
+        href: playlist.href,
+        id: playlist.id,
+        images: playlist.images,
+        name: playlist.name,
+        owner: playlist.owner,
+        public: playlist.public,
+        snapshot_id: playlist.snapshot_id,
+        tracks: PlaylistTracksRef {
+            href: playlist.tracks.href,
+            total: playlist.tracks.total,
+        },
+    })
+}
+
+pub fn rename_playlist(
+    spotify: &AuthCodePkceSpotify,
+    playlist_id: &PlaylistId<'static>,
+    name: &str,
+) -> ClientResult<()> {
+    log::info!("Renaming playlist {} to {}", playlist_id, name);
+    spotify
+        .playlist_change_detail(playlist_id.clone_static(), Some(name), None, None, None)
+        .map(|_| ())
+}
+
+/// Spotify has no deleting, unfollowing your own playlist is what its apps do instead
+pub fn delete_playlist(
+    spotify: &AuthCodePkceSpotify,
+    playlist_id: &PlaylistId<'static>,
+) -> ClientResult<()> {
+    log::info!("Deleting playlist {}", playlist_id);
+    spotify.playlist_unfollow(playlist_id.clone_static())
+}
+
+/// What's at each position of a playlist, None for local files, episodes and tracks no longer
+/// available, which can't be added back but still take up a position
+pub fn playlist_positions(
+    spotify: &AuthCodePkceSpotify,
+    playlist_id: &PlaylistId<'static>,
+) -> ClientResult<Vec<Option<TrackId<'static>>>> {
+    spotify
+        .playlist_items(playlist_id.clone_static(), None, None)
+        .map(|result| {
+            result.map(|item| match item.track {
+                Some(PlayableItem::Track(track)) => track.id,
+                _ => None,
+            })
+        })
+        .collect()
+}
+
+/// Adds tracks to a playlist in order, the first of them at the given position
+pub fn insert_tracks(
+    spotify: &AuthCodePkceSpotify,
+    playlist_id: &PlaylistId<'static>,
+    track_ids: &[TrackId<'static>],
+    position: usize,
+) -> ClientResult<()> {
+    log::info!(
+        "Inserting {} tracks into playlist {} at position {}",
+        track_ids.len(),
+        playlist_id,
+        position
+    );
+
+    // the API accepts at most 100 tracks per request
+    for (index, chunk) in track_ids.chunks(100).enumerate() {
+        spotify.playlist_add_items(
+            playlist_id.clone_static(),
+            chunk.iter().map(|id| PlayableId::Track(id.clone())),
+            Some((position + index * 100) as u32),
+        )?;
+    }
+    Ok(())
+}
+
+/// Moves the item at one position of a playlist to another, which unlike removing and adding
+/// it again keeps when it was added and by whom
+pub fn move_item(
+    spotify: &AuthCodePkceSpotify,
+    playlist_id: &PlaylistId<'static>,
+    from: usize,
+    to: usize,
+) -> ClientResult<()> {
+    spotify.playlist_reorder_items(
+        playlist_id.clone_static(),
+        Some(from as i32),
+        Some(to as i32),
+        Some(1),
+        None,
+    )?;
+    Ok(())
+}
+
+/// Adds tracks to the end of a playlist, in order
+pub fn add_tracks(
+    spotify: &AuthCodePkceSpotify,
+    playlist_id: &PlaylistId<'static>,
+    track_ids: &[TrackId<'static>],
+) -> ClientResult<()> {
+    log::info!(
+        "Adding {} tracks to playlist {}",
+        track_ids.len(),
+        playlist_id
+    );
+
+    // the API accepts at most 100 tracks per request
+    for chunk in track_ids.chunks(100) {
+        spotify.playlist_add_items(
+            playlist_id.clone_static(),
+            chunk.iter().map(|id| PlayableId::Track(id.clone())),
+            None,
+        )?;
+    }
+    Ok(())
+}
+
 pub fn remove_from_playlist(
     spotify: &AuthCodePkceSpotify,
     track_id: &TrackId,
@@ -193,7 +546,93 @@ pub fn add_to_playlists(
     }
 }
 
-fn is_track_in_playlist(
+/// Sorts several tracks at once, with one request per playlist rather than per track, then
+/// removes them from the source. Each playlist comes with the tracks it doesn't have yet.
+pub fn sort_tracks(
+    spotify: &AuthCodePkceSpotify,
+    track_ids: &[TrackId<'static>],
+    additions: &[(PlaylistId<'static>, Vec<TrackId<'static>>)],
+    source_playlist_id: &PlaylistId<'static>,
+) -> Result<(), SpotifyPlaylistsError> {
+    let mut errors: Vec<String> = Vec::new();
+
+    for (playlist_id, new_ids) in additions {
+        if let Err(e) = add_tracks(spotify, playlist_id, new_ids) {
+            log::error!("Failed to add tracks to playlist {}: {}", playlist_id, e);
+            errors.push(playlist_name(spotify, playlist_id));
+        }
+    }
+
+    // only those not liked yet, so liking doesn't move the others to the top of liked songs
+    let mut unliked = Vec::new();
+    for chunk in track_ids.chunks(50) {
+        match spotify.current_user_saved_tracks_contains(chunk.iter().cloned()) {
+            Ok(liked) => unliked.extend(
+                chunk
+                    .iter()
+                    .zip(liked)
+                    .filter(|(_, liked)| !liked)
+                    .map(|(id, _)| id.clone()),
+            ),
+            Err(e) => {
+                log::error!("Failed to check if tracks are in user's liked songs: {}", e);
+                unliked.extend(chunk.iter().cloned());
+            }
+        }
+    }
+    if let Err(e) = like_tracks(spotify, &unliked) {
+        log::error!("Failed to add tracks to user's liked songs: {}", e);
+        errors.push(String::from("Liked Songs"));
+    }
+
+    if !errors.is_empty() {
+        return Err(SpotifyPlaylistsError::Add(errors));
+    }
+    // as with a single track, it stays in the source if adding it anywhere failed
+    remove_tracks(spotify, track_ids, source_playlist_id)
+}
+
+pub fn remove_tracks(
+    spotify: &AuthCodePkceSpotify,
+    track_ids: &[TrackId<'static>],
+    playlist_id: &PlaylistId<'static>,
+) -> Result<(), SpotifyPlaylistsError> {
+    log::info!(
+        "Removing {} tracks from playlist {}",
+        track_ids.len(),
+        playlist_id
+    );
+
+    // the API accepts at most 100 tracks per request
+    for chunk in track_ids.chunks(100) {
+        if let Err(e) = spotify.playlist_remove_all_occurrences_of_items(
+            playlist_id.clone_static(),
+            chunk.iter().map(|id| PlayableId::Track(id.clone())),
+            None,
+        ) {
+            log::error!(
+                "Failed to remove tracks from playlist {}: {}",
+                playlist_id,
+                e
+            );
+            return Err(SpotifyPlaylistsError::Remove(vec![playlist_name(
+                spotify,
+                playlist_id,
+            )]));
+        }
+    }
+    Ok(())
+}
+
+/// For error messages, falling back to the ID if even the name can't be fetched
+fn playlist_name(spotify: &AuthCodePkceSpotify, playlist_id: &PlaylistId<'static>) -> String {
+    spotify
+        .playlist(playlist_id.clone_static(), Some("name"), None)
+        .map(|playlist| playlist.name)
+        .unwrap_or(format!("Playlist with ID {}", playlist_id))
+}
+
+pub fn is_track_in_playlist(
     spotify: &AuthCodePkceSpotify,
     playlist_id: &PlaylistId<'static>,
     track_id: &TrackId,
@@ -208,5 +647,5 @@ fn is_track_in_playlist(
             }
         }
     }
-    return false;
+    false
 }
@@ -0,0 +1,82 @@
+use std::{
+    error::Error,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::ui;
+
+// lives next to the token cache and logs, in the directory sortify is run from
+static DATA_DIR: &str = ".sortify";
+
+pub fn path(file_name: &str) -> PathBuf {
+    PathBuf::from(DATA_DIR).join(file_name)
+}
+
+/// Reads a JSON file from the data directory, or the default value if there is none yet
+pub fn load<T: DeserializeOwned + Default>(file_name: &str) -> Result<T, String> {
+    let contents = match fs::read_to_string(path(file_name)) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(T::default()),
+        Err(e) => return Err(format!("Failed to read {}: {}", file_name, e)),
+    };
+
+    serde_json::from_str(&contents).map_err(|e| format!("Failed to parse {}: {}", file_name, e))
+}
+
+/// Like `load`, for files the application saves itself. One that can't be read is moved aside
+/// before starting over from the default, so saving doesn't overwrite what was in it.
+pub fn load_or_set_aside<T: DeserializeOwned + Default>(file_name: &str) -> T {
+    load(file_name).unwrap_or_else(|e| {
+        log::error!("{}", e);
+        let aside = path(&format!("{}.broken", file_name));
+        match fs::rename(path(file_name), &aside) {
+            Ok(()) => ui::data_file_warning(&e, Some(&aside)),
+            Err(rename_error) => {
+                log::error!("Failed to move {} aside: {}", file_name, rename_error);
+                ui::data_file_warning(&e, None);
+            }
+        }
+        T::default()
+    })
+}
+
+pub fn save<T: Serialize>(file_name: &str, value: &T) -> Result<(), Box<dyn Error>> {
+    fs::create_dir_all(DATA_DIR)?;
+    write_whole(&path(file_name), &serde_json::to_string(value)?)?;
+    Ok(())
+}
+
+/// Writes the file under another name first and then puts it in place, so the application
+/// exiting or crashing part way through never leaves it half written
+fn write_whole(path: &Path, contents: &str) -> io::Result<()> {
+    let mut temporary = path.as_os_str().to_owned();
+    temporary.push(".tmp");
+    fs::write(&temporary, contents)?;
+    fs::rename(&temporary, path)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use super::*;
+
+    #[test]
+    fn writes_replace_the_whole_file() {
+        let directory = env::temp_dir().join(format!("sortify-storage-{}", std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        let path = directory.join("data.json");
+
+        write_whole(&path, "a longer first version").unwrap();
+        write_whole(&path, "second").unwrap();
+        let contents = fs::read_to_string(&path).unwrap();
+        let files = fs::read_dir(&directory).unwrap().count();
+        _ = fs::remove_dir_all(&directory);
+
+        assert_eq!(contents, "second");
+        assert_eq!(files, 1);
+    }
+}
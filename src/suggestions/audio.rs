@@ -0,0 +1,234 @@
+use std::thread::{self, JoinHandle};
+
+use rspotify::{
+    model::{FullTrack, SimplifiedPlaylist},
+    prelude::Id,
+};
+
+use crate::{
+    analysis::{self, AnalysisCache, FEATURE_LEN},
+    audio,
+    library::MembershipIndex,
+};
+
+// a playlist's profile is made from its most recently added tracks
+const PROFILE_TRACKS: usize = 20;
+const MIN_PROFILE_TRACKS: usize = 3;
+// how often the background analysis writes its progress to disk
+const SAVE_EVERY: usize = 25;
+
+fn profile_tracks<'a>(
+    playlist: &SimplifiedPlaylist,
+    membership: &'a MembershipIndex,
+) -> impl Iterator<Item = &'a FullTrack> {
+    membership
+        .tracks(&playlist.id)
+        .iter()
+        .rev()
+        .take(PROFILE_TRACKS)
+}
+
+/// Analyses the tracks that make up each playlist's profile in a separate thread, so sorting can
+/// start right away and suggestions get better as it progresses
+pub fn build_profiles(
+    playlists: &[SimplifiedPlaylist],
+    membership: &MembershipIndex,
+    cache: AnalysisCache,
+) -> JoinHandle<()> {
+    let tracks: Vec<FullTrack> = playlists
+        .iter()
+        .flat_map(|playlist| profile_tracks(playlist, membership).cloned())
+        .filter(|track| analysis::cached(track, &cache).is_none())
+        .collect();
+    log::info!(
+        "Analysing {} playlist tracks in the background",
+        tracks.len()
+    );
+
+    thread::spawn(move || {
+        for (i, track) in tracks.iter().enumerate() {
+            // the same track may be in several playlists
+            if analysis::cached(track, &cache).is_some() {
+                continue;
+            }
+
+            let preview = audio::download_preview(track);
+            analysis::analyse(track, preview.as_deref(), &cache);
+
+            if (i + 1) % SAVE_EVERY == 0 {
+                analysis::save_cache(&cache);
+            }
+        }
+
+        analysis::save_cache(&cache);
+        log::info!("Finished analysing playlist tracks");
+    })
+}
+
+/// Playlists ranked by how close a track's features are to their profile, as pairs of index into
+/// `playlists` and a similarity between 0 and 1
+pub fn rank(
+    features: &[f32],
+    playlists: &[SimplifiedPlaylist],
+    membership: &MembershipIndex,
+    cache: &AnalysisCache,
+) -> Vec<(usize, f32)> {
+    if features.len() != FEATURE_LEN {
+        return Vec::new();
+    }
+    let Ok(cache) = cache.lock() else {
+        return Vec::new();
+    };
+
+    let profiles: Vec<Vec<&Vec<f32>>> = playlists
+        .iter()
+        .map(|playlist| {
+            profile_tracks(playlist, membership)
+                .filter_map(|track| cache.get(track.id.as_ref()?.id()))
+                .map(|analysis| &analysis.features)
+                .filter(|features| features.len() == FEATURE_LEN)
+                .collect()
+        })
+        .collect();
+
+    // scale each feature by its spread over all profiled tracks, so none of them dominates
+    let all: Vec<&Vec<f32>> = profiles.iter().flatten().copied().collect();
+    if all.len() < 2 {
+        return Vec::new();
+    }
+    let mean: Vec<f32> = (0..FEATURE_LEN)
+        .map(|d| all.iter().map(|features| features[d]).sum::<f32>() / all.len() as f32)
+        .collect();
+    let deviation: Vec<f32> = (0..FEATURE_LEN)
+        .map(|d| {
+            let variance = all
+                .iter()
+                .map(|features| (features[d] - mean[d]).powi(2))
+                .sum::<f32>()
+                / all.len() as f32;
+            variance.sqrt().max(f32::EPSILON)
+        })
+        .collect();
+    let standardise = |features: &[f32]| -> Vec<f32> {
+        (0..FEATURE_LEN)
+            .map(|d| (features[d] - mean[d]) / deviation[d])
+            .collect()
+    };
+
+    let target = standardise(features);
+    let mut ranked: Vec<(usize, f32)> = profiles
+        .iter()
+        .enumerate()
+        .filter(|(_, members)| members.len() >= MIN_PROFILE_TRACKS)
+        .map(|(index, members)| {
+            let mut centroid = [0.0; FEATURE_LEN];
+            for member in members {
+                for (d, value) in standardise(member).iter().enumerate() {
+                    centroid[d] += value / members.len() as f32;
+                }
+            }
+
+            let distance = target
+                .iter()
+                .zip(centroid.iter())
+                .map(|(a, b)| (a - b).powi(2))
+                .sum::<f32>()
+                .sqrt()
+                / (FEATURE_LEN as f32).sqrt();
+
+            (index, 1.0 / (1.0 + distance))
+        })
+        .collect();
+
+    ranked.sort_by(|a, b| b.1.total_cmp(&a.1));
+    ranked
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::HashMap,
+        sync::{Arc, Mutex},
+    };
+
+    use super::*;
+    use crate::{analysis::TrackAnalysis, testing};
+
+    /// Playlists whose tracks all have features close to a value of their own, and the cache
+    /// that knows those features. Calm's tracks are around 0, Loud's around 10, and Short has too
+    /// few tracks for a profile.
+    fn library() -> (Vec<SimplifiedPlaylist>, MembershipIndex, AnalysisCache) {
+        let playlists = vec![
+            testing::playlist("0000000000000000000001", "Calm"),
+            testing::playlist("0000000000000000000002", "Loud"),
+            testing::playlist("0000000000000000000003", "Short"),
+        ];
+        let mut membership = MembershipIndex::default();
+        let mut cache = HashMap::new();
+        let mut id = 0;
+
+        for (playlist, centre, count) in [(0, 0.0, 4), (1, 10.0, 4), (2, 0.0, 2)] {
+            for i in 0..count {
+                id += 1;
+                let track_id = format!("{:022}", id);
+                membership.add(
+                    &playlists[playlist].id,
+                    &testing::track(&track_id, "Track", "Artist", 1),
+                );
+                let spread = i as f32 * 0.5;
+                cache.insert(
+                    track_id,
+                    TrackAnalysis {
+                        bpm: None,
+                        key: None,
+                        features: vec![centre + spread; FEATURE_LEN],
+                    },
+                );
+            }
+        }
+
+        (playlists, membership, Arc::new(Mutex::new(cache)))
+    }
+
+    #[test]
+    fn closest_profile_ranks_first() {
+        let (playlists, membership, cache) = library();
+
+        let ranked = rank(&[9.0; FEATURE_LEN], &playlists, &membership, &cache);
+        let order: Vec<usize> = ranked.iter().map(|(index, _)| *index).collect();
+        assert_eq!(order, vec![1, 0]);
+        assert!(ranked[0].1 > ranked[1].1);
+        assert!(ranked
+            .iter()
+            .all(|(_, similarity)| (0.0..=1.0).contains(similarity)));
+
+        let ranked = rank(&[1.0; FEATURE_LEN], &playlists, &membership, &cache);
+        assert_eq!(ranked[0].0, 0);
+    }
+
+    #[test]
+    fn playlists_with_too_few_analysed_tracks_are_left_out() {
+        let (playlists, membership, cache) = library();
+        // half of Calm's tracks haven't been analysed yet
+        {
+            let mut cache = cache.lock().unwrap();
+            cache.remove(&format!("{:022}", 1));
+            cache.remove(&format!("{:022}", 2));
+        }
+
+        let ranked = rank(&[0.0; FEATURE_LEN], &playlists, &membership, &cache);
+        assert_eq!(
+            ranked.iter().map(|(index, _)| *index).collect::<Vec<_>>(),
+            vec![1]
+        );
+    }
+
+    #[test]
+    fn nothing_ranked_without_features() {
+        let (playlists, membership, cache) = library();
+
+        assert!(rank(&[], &playlists, &membership, &cache).is_empty());
+        let empty: AnalysisCache = Arc::default();
+        assert!(rank(&[0.0; FEATURE_LEN], &playlists, &membership, &empty).is_empty());
+    }
+}
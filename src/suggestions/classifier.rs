@@ -0,0 +1,379 @@
+use std::{
+    collections::{HashMap, HashSet},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use rspotify::{
+    model::{FullTrack, PlaylistId, SimplifiedPlaylist},
+    prelude::Id,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    analysis::{TrackAnalysis, CENTROID},
+    library::{release_year, Genres},
+    storage,
+};
+
+static HISTORY_FILE: &str = "history.json";
+static MODEL_FILE: &str = "model.json";
+
+// too few decisions make for wild guesses
+const MIN_EXAMPLES: u32 = 10;
+const MIN_LABEL_EXAMPLES: u32 = 2;
+// how likely a playlist has to be for it to start out checked
+const MIN_CONFIDENCE: f32 = 0.5;
+
+/// A track and the playlists it was sorted into, empty if it was removed without sorting
+#[derive(Serialize, Deserialize)]
+pub struct Decision {
+    pub track_id: String,
+    pub tokens: Vec<String>,
+    pub playlists: Vec<String>,
+    /// Seconds since the Unix epoch
+    pub decided_at: u64,
+}
+
+/// Every decision ever made, kept in order so the model knows which ones it has learned from
+#[derive(Default)]
+pub struct History {
+    decisions: Vec<Decision>,
+}
+
+impl History {
+    pub fn load() -> History {
+        History {
+            decisions: storage::load_or_set_aside(HISTORY_FILE),
+        }
+    }
+
+    pub fn record(&mut self, track: &FullTrack, tokens: Vec<String>, playlists: &[PlaylistId]) {
+        let Some(track_id) = &track.id else {
+            return;
+        };
+        let mut playlist_ids: Vec<String> = Vec::new();
+        for playlist_id in playlists {
+            let playlist_id = playlist_id.id().to_string();
+            if !playlist_ids.contains(&playlist_id) {
+                playlist_ids.push(playlist_id);
+            }
+        }
+
+        self.decisions.push(Decision {
+            track_id: track_id.id().to_string(),
+            tokens,
+            playlists: playlist_ids,
+            decided_at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|duration| duration.as_secs())
+                .unwrap_or_default(),
+        });
+
+        // saved right away so decisions survive the application crashing
+        if let Err(e) = storage::save(HISTORY_FILE, &self.decisions) {
+            log::error!("Failed to save decision history: {}", e);
+        }
+    }
+}
+
+/// Discrete features of a track that the model learns from
+pub fn tokens(track: &FullTrack, genres: &Genres, analysis: Option<&TrackAnalysis>) -> Vec<String> {
+    let mut tokens: Vec<String> = Vec::new();
+
+    for artist in &track.artists {
+        if let Some(id) = &artist.id {
+            tokens.push(format!("artist:{}", id.id()));
+        }
+    }
+    for genre in genres.of_track(track) {
+        tokens.push(format!("genre:{}", genre));
+    }
+    if let Some(year) = release_year(track) {
+        tokens.push(format!("era:{}", year / 5 * 5));
+    }
+
+    if let Some(analysis) = analysis {
+        if let Some(bpm) = analysis.bpm {
+            tokens.push(format!("tempo:{}", (bpm / 10.0) as u32 * 10));
+        }
+        if let Some(key) = analysis.key {
+            tokens.push(format!("key:{}", key));
+        }
+        if let Some(centroid) = analysis.features.get(CENTROID) {
+            tokens.push(format!("brightness:{}", (centroid * 2.0) as u32));
+        }
+    }
+
+    tokens
+}
+
+#[derive(Serialize, Deserialize, Default)]
+struct LabelCounts {
+    examples: u32,
+    tokens: HashMap<String, u32>,
+}
+
+/// Multi-label naive Bayes: one yes/no classifier per playlist, all sharing the same counts of
+/// how often each token appears. Counts only ever grow, so learning is incremental.
+#[derive(Serialize, Deserialize, Default)]
+pub struct Classifier {
+    examples: u32,
+    tokens: HashMap<String, u32>,
+    labels: HashMap<String, LabelCounts>,
+    /// Track ID and time of the last decision learned from, to tell whether the history is still
+    /// the one the counts came from
+    #[serde(default)]
+    last_learned: Option<(String, u64)>,
+}
+
+impl Classifier {
+    /// Loads the model, first learning from any decisions it missed (e.g. after a crash)
+    pub fn load(history: &History) -> Classifier {
+        let mut classifier: Classifier = storage::load_or_set_aside(MODEL_FILE);
+        classifier.catch_up(history);
+        classifier
+    }
+
+    /// Whether the decisions learned from are still the start of the history, which stops being
+    /// true if the history file was reset or replaced on its own
+    fn is_in_step(&self, history: &History) -> bool {
+        let learned = self.examples as usize;
+        if learned == 0 {
+            return true;
+        }
+        let last = history.decisions.get(learned - 1);
+        last.zip(self.last_learned.as_ref())
+            .is_some_and(|(decision, (track_id, decided_at))| {
+                decision.track_id == *track_id && decision.decided_at == *decided_at
+            })
+    }
+
+    /// Learns from the decisions made since the last time, and saves the result
+    pub fn catch_up(&mut self, history: &History) {
+        if self.learn(history) {
+            if let Err(e) = storage::save(MODEL_FILE, self) {
+                log::error!("Failed to save model: {}", e);
+            }
+        }
+    }
+
+    /// Learns from the decisions made since the last time, returning whether anything changed
+    fn learn(&mut self, history: &History) -> bool {
+        let mut retrained = false;
+        if !self.is_in_step(history) {
+            log::warn!("Model is out of step with the decision history, retraining it");
+            *self = Classifier::default();
+            retrained = true;
+        }
+
+        let new = history.decisions.iter().skip(self.examples as usize);
+        let mut learned = 0;
+
+        for decision in new {
+            let tokens: HashSet<&String> = decision.tokens.iter().collect();
+
+            self.examples += 1;
+            for token in &tokens {
+                *self.tokens.entry(token.to_string()).or_default() += 1;
+            }
+
+            // older histories may name a playlist twice in one decision
+            let playlists: HashSet<&String> = decision.playlists.iter().collect();
+            for playlist in playlists {
+                let label = self.labels.entry(playlist.clone()).or_default();
+                label.examples += 1;
+                for token in &tokens {
+                    *label.tokens.entry(token.to_string()).or_default() += 1;
+                }
+            }
+            self.last_learned = Some((decision.track_id.clone(), decision.decided_at));
+            learned += 1;
+        }
+
+        if learned > 0 || retrained {
+            log::info!("Learned from {} new decisions", learned);
+        }
+        learned > 0 || retrained
+    }
+
+    fn probability(&self, label: &LabelCounts, tokens: &HashSet<&String>) -> f32 {
+        let with = label.examples as f32;
+        let without = self.examples.saturating_sub(label.examples) as f32;
+
+        // log odds of the track belonging, with add-one smoothing throughout
+        let mut log_odds = ((with + 1.0) / (without + 1.0)).ln();
+        for token in tokens {
+            let seen = *self.tokens.get(*token).unwrap_or(&0) as f32;
+            let seen_with = *label.tokens.get(*token).unwrap_or(&0) as f32;
+
+            let likelihood_with = (seen_with + 1.0) / (with + 2.0);
+            let likelihood_without = ((seen - seen_with).max(0.0) + 1.0) / (without + 2.0);
+            log_odds += (likelihood_with / likelihood_without).ln();
+        }
+
+        1.0 / (1.0 + (-log_odds).exp())
+    }
+
+    /// Playlists the track likely belongs in, as pairs of index into `playlists` and confidence,
+    /// most likely first
+    pub fn predict(
+        &self,
+        tokens: &[String],
+        playlists: &[SimplifiedPlaylist],
+    ) -> Vec<(usize, f32)> {
+        if self.examples < MIN_EXAMPLES {
+            return Vec::new();
+        }
+        let tokens: HashSet<&String> = tokens.iter().collect();
+
+        let mut predicted: Vec<(usize, f32)> = playlists
+            .iter()
+            .enumerate()
+            .filter_map(|(index, playlist)| {
+                let label = self.labels.get(playlist.id.id())?;
+                if label.examples < MIN_LABEL_EXAMPLES {
+                    return None;
+                }
+                Some((index, self.probability(label, &tokens)))
+            })
+            .filter(|(_, confidence)| *confidence >= MIN_CONFIDENCE)
+            .collect();
+
+        predicted.sort_by(|a, b| b.1.total_cmp(&a.1));
+        predicted
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    const JAZZ: &str = "0000000000000000000001";
+    const TECHNO: &str = "0000000000000000000002";
+
+    fn decision(n: u64, tokens: &[&str], playlists: &[&str]) -> Decision {
+        Decision {
+            track_id: format!("{:022}", n),
+            tokens: tokens.iter().map(|token| token.to_string()).collect(),
+            playlists: playlists.iter().map(|id| id.to_string()).collect(),
+            decided_at: n,
+        }
+    }
+
+    /// Jazz tracks by one artist going into Jazz and techno by another going into Techno, taking
+    /// turns, numbered from `first`
+    fn decisions(first: u64, count: u64) -> Vec<Decision> {
+        (first..first + count)
+            .map(|n| match n % 2 {
+                0 => decision(n, &["artist:miles", "genre:jazz"], &[JAZZ]),
+                _ => decision(n, &["artist:jeff", "genre:techno"], &[TECHNO]),
+            })
+            .collect()
+    }
+
+    fn playlists() -> Vec<SimplifiedPlaylist> {
+        vec![
+            testing::playlist(JAZZ, "Jazz"),
+            testing::playlist(TECHNO, "Techno"),
+        ]
+    }
+
+    fn tokens(tokens: &[&str]) -> Vec<String> {
+        tokens.iter().map(|token| token.to_string()).collect()
+    }
+
+    fn trained(decisions: Vec<Decision>) -> Classifier {
+        let mut classifier = Classifier::default();
+        classifier.learn(&History { decisions });
+        classifier
+    }
+
+    #[test]
+    fn predicts_where_similar_tracks_went() {
+        let classifier = trained(decisions(0, 12));
+
+        let predicted = classifier.predict(&tokens(&["artist:miles"]), &playlists());
+        assert_eq!(predicted.len(), 1);
+        assert_eq!(predicted[0].0, 0);
+        assert!(predicted[0].1 > 0.8);
+
+        let predicted = classifier.predict(&tokens(&["genre:techno"]), &playlists());
+        assert_eq!(
+            predicted.iter().map(|(i, _)| *i).collect::<Vec<_>>(),
+            vec![1]
+        );
+    }
+
+    #[test]
+    fn too_few_decisions_predict_nothing() {
+        let classifier = trained(decisions(0, MIN_EXAMPLES as u64 - 1));
+
+        assert!(classifier
+            .predict(&tokens(&["artist:miles"]), &playlists())
+            .is_empty());
+    }
+
+    #[test]
+    fn playlist_named_twice_counts_once() {
+        let mut history = decisions(0, 10);
+        history.push(decision(10, &["artist:miles"], &[JAZZ, JAZZ]));
+        let classifier = trained(history);
+
+        assert_eq!(classifier.labels[JAZZ].examples, 6);
+        let probability = classifier.probability(
+            &classifier.labels[JAZZ],
+            &tokens(&["artist:miles"]).iter().collect(),
+        );
+        assert!((0.0..=1.0).contains(&probability));
+    }
+
+    #[test]
+    fn overcounted_model_still_predicts() {
+        // as saved by versions that counted a playlist named twice in a decision twice
+        let mut classifier = trained(decisions(0, 10));
+        classifier.labels.get_mut(JAZZ).unwrap().examples = 11;
+        classifier
+            .labels
+            .get_mut(JAZZ)
+            .unwrap()
+            .tokens
+            .insert(String::from("artist:miles"), 11);
+
+        let probability = classifier.probability(
+            &classifier.labels[JAZZ],
+            &tokens(&["artist:miles"]).iter().collect(),
+        );
+        assert!((0.0..=1.0).contains(&probability));
+    }
+
+    #[test]
+    fn catching_up_learns_each_decision_once() {
+        let mut history = History {
+            decisions: decisions(0, 4),
+        };
+        let mut classifier = Classifier::default();
+
+        assert!(classifier.learn(&history));
+        assert!(!classifier.learn(&history));
+        assert_eq!(classifier.examples, 4);
+
+        history.decisions.extend(decisions(4, 3));
+        assert!(classifier.learn(&history));
+        assert_eq!(classifier.examples, 7);
+        assert_eq!(classifier.tokens["artist:miles"], 4);
+        assert_eq!(classifier.last_learned, Some((format!("{:022}", 6), 6)));
+    }
+
+    #[test]
+    fn replaced_history_is_learned_from_scratch() {
+        let mut classifier = trained(decisions(0, 6));
+
+        let replaced = History {
+            decisions: decisions(100, 4),
+        };
+        assert!(classifier.learn(&replaced));
+        assert_eq!(classifier.examples, 4);
+        assert_eq!(classifier.labels[JAZZ].examples, 2);
+    }
+}
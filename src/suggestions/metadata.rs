@@ -0,0 +1,186 @@
+use std::collections::HashMap;
+
+use rspotify::model::{FullTrack, SimplifiedPlaylist};
+
+use crate::library::{release_year, Genres, MembershipIndex};
+
+// releases this many years apart are considered to be from the same era
+const NEARBY_YEARS: i32 = 2;
+// playlists scoring below this are not worth suggesting
+const MIN_SCORE: f32 = 0.2;
+
+// how much each kind of overlap counts towards the score, adding up to 1
+const ARTIST_WEIGHT: f32 = 0.4;
+const ALBUM_WEIGHT: f32 = 0.15;
+const YEAR_WEIGHT: f32 = 0.15;
+const GENRE_WEIGHT: f32 = 0.3;
+
+// this many tracks sharing an artist or album is as strong a signal as it gets
+const ARTIST_SATURATION: usize = 3;
+const ALBUM_SATURATION: usize = 2;
+
+fn score(track: &FullTrack, members: &[&FullTrack], genres: &Genres) -> f32 {
+    if members.is_empty() {
+        return 0.0;
+    }
+
+    let shares_artist = |other: &FullTrack| {
+        other.artists.iter().any(|artist| {
+            artist.id.is_some() && track.artists.iter().any(|mine| mine.id == artist.id)
+        })
+    };
+    let artist_matches = members.iter().filter(|other| shares_artist(other)).count();
+
+    let album_matches = members
+        .iter()
+        .filter(|other| track.album.id.is_some() && other.album.id == track.album.id)
+        .count();
+
+    let year_share = match release_year(track) {
+        Some(year) => {
+            members
+                .iter()
+                .filter(|other| {
+                    release_year(other).is_some_and(|other| (other - year).abs() <= NEARBY_YEARS)
+                })
+                .count() as f32
+                / members.len() as f32
+        }
+        None => 0.0,
+    };
+
+    // how much of the playlist's genre makeup is made of the track's genres
+    let track_genres = genres.of_track(track);
+    let mut genre_counts: HashMap<&String, usize> = HashMap::new();
+    for genre in members.iter().flat_map(|other| genres.of_track(other)) {
+        *genre_counts.entry(genre).or_default() += 1;
+    }
+    let total_genres: usize = genre_counts.values().sum();
+    let genre_share = if total_genres > 0 {
+        genre_counts
+            .iter()
+            .filter(|(genre, _)| track_genres.contains(*genre))
+            .map(|(_, count)| count)
+            .sum::<usize>() as f32
+            / total_genres as f32
+    } else {
+        0.0
+    };
+
+    ARTIST_WEIGHT * (artist_matches.min(ARTIST_SATURATION) as f32 / ARTIST_SATURATION as f32)
+        + ALBUM_WEIGHT * (album_matches.min(ALBUM_SATURATION) as f32 / ALBUM_SATURATION as f32)
+        + YEAR_WEIGHT * year_share
+        + GENRE_WEIGHT * genre_share
+}
+
+/// Playlists that share artists, albums, eras or genres with a track, as pairs of index into
+/// `playlists` and a score between 0 and 1, best first
+pub fn rank(
+    track: &FullTrack,
+    playlists: &[SimplifiedPlaylist],
+    membership: &MembershipIndex,
+    genres: &Genres,
+) -> Vec<(usize, f32)> {
+    let mut ranked: Vec<(usize, f32)> = playlists
+        .iter()
+        .enumerate()
+        .map(|(index, playlist)| {
+            // the track itself shouldn't count towards a playlist it's already in
+            let members: Vec<&FullTrack> = membership
+                .tracks(&playlist.id)
+                .iter()
+                .filter(|other| other.id != track.id)
+                .collect();
+            (index, score(track, &members, genres))
+        })
+        .filter(|(_, score)| *score >= MIN_SCORE)
+        .collect();
+
+    ranked.sort_by(|a, b| b.1.total_cmp(&a.1));
+    ranked
+}
+
+#[cfg(test)]
+mod tests {
+    use rspotify::model::{AlbumId, ArtistId};
+
+    use super::*;
+    use crate::testing;
+
+    /// A track by the artist with that number, from the album and year, made up into IDs
+    fn track(id: u32, artist: u32, album: u32, year: i32) -> FullTrack {
+        let mut track = testing::track(&format!("{:022}", id), "Track", "Artist", 1);
+        track.artists[0].id = Some(ArtistId::from_id(format!("{:022}", 1000 + artist)).unwrap());
+        track.album.id = Some(AlbumId::from_id(format!("{:022}", 2000 + album)).unwrap());
+        track.album.release_date = Some(format!("{}-01-01", year));
+        track
+    }
+
+    fn genres() -> Genres {
+        let artist = |n: u32| format!("{:022}", 1000 + n);
+        let (one, two, three) = (artist(1), artist(2), artist(3));
+        Genres::known(&[
+            (&one, &["cool jazz"]),
+            (&two, &["cool jazz", "bebop"]),
+            (&three, &["techno"]),
+        ])
+    }
+
+    /// Playlists of the tracks, and the index of which tracks are in which
+    fn library(contents: &[&[FullTrack]]) -> (Vec<SimplifiedPlaylist>, MembershipIndex) {
+        let mut membership = MembershipIndex::default();
+        let playlists = contents
+            .iter()
+            .enumerate()
+            .map(|(i, tracks)| {
+                let playlist = testing::playlist(&format!("{:022}", i), &format!("Playlist {}", i));
+                for track in tracks.iter() {
+                    membership.add(&playlist.id, track);
+                }
+                playlist
+            })
+            .collect();
+        (playlists, membership)
+    }
+
+    #[test]
+    fn more_overlap_ranks_higher() {
+        let mine = track(1, 1, 1, 1959);
+        let (playlists, membership) = library(&[
+            // same artist and album
+            &[
+                track(10, 1, 1, 1959),
+                track(11, 1, 1, 1959),
+                track(12, 1, 1, 1959),
+            ],
+            // same genre and era only
+            &[track(20, 2, 2, 1958), track(21, 2, 3, 1960)],
+            // nothing in common
+            &[track(30, 3, 4, 1995), track(31, 3, 4, 1996)],
+        ]);
+
+        let ranked = rank(&mine, &playlists, &membership, &genres());
+        let order: Vec<usize> = ranked.iter().map(|(index, _)| *index).collect();
+        assert_eq!(order, vec![0, 1]);
+        assert!((ranked[0].1 - 1.0).abs() < 1e-6);
+        assert!(ranked[1].1 > MIN_SCORE && ranked[1].1 < ranked[0].1);
+    }
+
+    #[test]
+    fn track_itself_doesnt_count() {
+        let mine = track(1, 1, 1, 1959);
+        let (playlists, membership) = library(&[std::slice::from_ref(&mine), &[]]);
+
+        assert!(rank(&mine, &playlists, &membership, &genres()).is_empty());
+    }
+
+    #[test]
+    fn unknown_details_dont_match_each_other() {
+        let mut mine = testing::track(&format!("{:022}", 1), "Track", "Artist", 1);
+        mine.album.release_date = None;
+        let other = testing::track(&format!("{:022}", 2), "Track", "Artist", 1);
+        let (playlists, membership) = library(&[&[other]]);
+
+        assert!(rank(&mine, &playlists, &membership, &genres()).is_empty());
+    }
+}
@@ -0,0 +1,134 @@
+pub mod audio;
+pub mod classifier;
+mod metadata;
+
+use rspotify::model::{FullTrack, PlaylistId, SimplifiedPlaylist};
+
+use crate::{analysis::TrackAnalysis, session::Session};
+
+const TOP_SUGGESTIONS: usize = 3;
+
+pub struct Suggestions {
+    /// Indices of playlists similar to the track, best first
+    pub suggested: Vec<usize>,
+    /// Indices of playlists past decisions say the track belongs in, with the model's confidence
+    pub predicted: Vec<(usize, f32)>,
+    /// Indices of playlists the user's script asked to start out checked
+    pub scripted: Vec<usize>,
+    /// Indices of playlists the user had checked before the screen was rebuilt, which take the
+    /// place of the pre-selection
+    pub checked: Option<Vec<usize>>,
+}
+
+/// The top playlists of both rankings, those of the first one first
+fn best_of(
+    first: Vec<(usize, f32)>,
+    second: Vec<(usize, f32)>,
+    playlists: &[SimplifiedPlaylist],
+    source_playlist_id: &PlaylistId<'static>,
+) -> Vec<usize> {
+    // the track is already in the source, which would always look like a match
+    let top = |ranked: Vec<(usize, f32)>| {
+        ranked
+            .into_iter()
+            .map(|(index, _)| index)
+            .filter(|index| playlists[*index].id != *source_playlist_id)
+            .take(TOP_SUGGESTIONS)
+    };
+
+    let mut suggested: Vec<usize> = Vec::new();
+    for index in top(first).chain(top(second)) {
+        if !suggested.contains(&index) && suggested.len() < TOP_SUGGESTIONS {
+            suggested.push(index);
+        }
+    }
+    suggested
+}
+
+pub fn suggest(
+    track: &FullTrack,
+    analysis: Option<&TrackAnalysis>,
+    tokens: &[String],
+    session: &Session,
+) -> Suggestions {
+    // metadata overlap is the more reliable signal, so its matches come first
+    let by_metadata = metadata::rank(
+        track,
+        &session.playlists,
+        &session.membership,
+        &session.genres,
+    );
+    let by_audio = analysis
+        .map(|analysis| {
+            audio::rank(
+                &analysis.features,
+                &session.playlists,
+                &session.membership,
+                &session.analysis_cache,
+            )
+        })
+        .unwrap_or_default();
+
+    let suggested = best_of(
+        by_metadata,
+        by_audio,
+        &session.playlists,
+        &session.source_playlist_id,
+    );
+    let predicted = session.classifier.predict(tokens, &session.playlists);
+
+    log::info!(
+        "Suggesting playlists {:?} and predicting {:?} for track {}",
+        suggested,
+        predicted,
+        track.name
+    );
+    Suggestions {
+        suggested,
+        predicted,
+        scripted: Vec::new(),
+        checked: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    fn playlists() -> Vec<SimplifiedPlaylist> {
+        (0..5)
+            .map(|i| testing::playlist(&format!("{:022}", i), &format!("Playlist {}", i)))
+            .collect()
+    }
+
+    fn ranked(indices: &[usize]) -> Vec<(usize, f32)> {
+        indices.iter().map(|index| (*index, 0.5)).collect()
+    }
+
+    #[test]
+    fn source_is_never_suggested() {
+        let playlists = playlists();
+        let source = playlists[0].id.clone_static();
+
+        assert_eq!(
+            best_of(ranked(&[0, 1, 2, 3]), Vec::new(), &playlists, &source),
+            vec![1, 2, 3]
+        );
+        assert_eq!(
+            best_of(ranked(&[0]), ranked(&[0, 4]), &playlists, &source),
+            vec![4]
+        );
+    }
+
+    #[test]
+    fn first_ranking_goes_first_without_repeats() {
+        let playlists = playlists();
+        let source = playlists[0].id.clone_static();
+
+        assert_eq!(
+            best_of(ranked(&[3]), ranked(&[3, 1, 2, 4]), &playlists, &source),
+            vec![3, 1, 2]
+        );
+    }
+}
@@ -0,0 +1,242 @@
+use std::{error::Error, fs, path::Path, time::Duration};
+
+use chrono::{DateTime, Utc};
+use rspotify::model::{FullTrack, PlaylistId, SimplifiedPlaylist};
+use serde::Serialize;
+
+use crate::{services::TrackAction, spotify::SpotifyPlaylistsError, ui};
+
+#[derive(Serialize)]
+pub struct PlaylistCount {
+    pub name: String,
+    pub tracks: usize,
+}
+
+/// A decision that couldn't be carried out, kept to try again
+#[derive(Serialize)]
+pub struct Failure {
+    /// Name and artists
+    pub track: String,
+    pub message: String,
+    #[serde(skip)]
+    pub full_track: FullTrack,
+    #[serde(skip)]
+    pub action: TrackAction,
+}
+
+impl Failure {
+    /// What's left of the action, leaving out playlists that already have the track so trying
+    /// again doesn't add it twice. With none left, removing it from the source may still be.
+    pub fn remaining(&self, has_track: impl Fn(&PlaylistId<'static>) -> bool) -> TrackAction {
+        match &self.action {
+            TrackAction::Add(playlist_ids) => TrackAction::Add(
+                playlist_ids
+                    .iter()
+                    .filter(|playlist_id| !has_track(playlist_id))
+                    .cloned()
+                    .collect(),
+            ),
+            action => action.clone(),
+        }
+    }
+}
+
+/// What happened during a session, shown at the end of it
+#[derive(Serialize)]
+pub struct Summary {
+    pub source_playlist: String,
+    pub started_at: DateTime<Utc>,
+    pub sorted: usize,
+    pub removed: usize,
+    pub skipped: usize,
+    /// Tracks added to each playlist, in the order they were first added to
+    pub playlists: Vec<PlaylistCount>,
+    /// Over the tracks decided on the track screen, rules and scripts being instant
+    pub average_decision_seconds: Option<f64>,
+    /// Number of tracks in the source playlist when the session started and ended
+    pub source_before: usize,
+    pub source_after: usize,
+    pub failures: Vec<Failure>,
+    #[serde(skip)]
+    decision_time: Duration,
+    #[serde(skip)]
+    timed_tracks: usize,
+}
+
+impl Summary {
+    pub fn new(source_playlist: String, source_before: usize) -> Summary {
+        Summary {
+            source_playlist,
+            started_at: Utc::now(),
+            sorted: 0,
+            removed: 0,
+            skipped: 0,
+            playlists: Vec::new(),
+            average_decision_seconds: None,
+            source_before,
+            source_after: source_before,
+            failures: Vec::new(),
+            decision_time: Duration::ZERO,
+            timed_tracks: 0,
+        }
+    }
+
+    /// Whether anything was decided on, or tried to be
+    pub fn is_empty(&self) -> bool {
+        self.sorted + self.removed + self.skipped + self.failures.len() == 0
+    }
+
+    /// Counts the outcome of acting on a track, keeping the action to retry if it failed
+    pub fn record(
+        &mut self,
+        track: &FullTrack,
+        action: &TrackAction,
+        result: &Result<TrackAction, SpotifyPlaylistsError>,
+        playlists: &[SimplifiedPlaylist],
+    ) {
+        match result {
+            Ok(TrackAction::Add(playlist_ids)) => {
+                self.sorted += 1;
+                for id in playlist_ids {
+                    let Some(playlist) = playlists.iter().find(|playlist| &playlist.id == id)
+                    else {
+                        continue;
+                    };
+                    match self
+                        .playlists
+                        .iter_mut()
+                        .find(|count| count.name == playlist.name)
+                    {
+                        Some(count) => count.tracks += 1,
+                        None => self.playlists.push(PlaylistCount {
+                            name: playlist.name.clone(),
+                            tracks: 1,
+                        }),
+                    }
+                }
+            }
+            Ok(TrackAction::Remove(_)) => self.removed += 1,
+            Ok(TrackAction::Skip) => self.skipped += 1,
+            Err(e) => self.failures.push(Failure {
+                track: ui::track::summary(track),
+                message: e.message(),
+                full_track: track.clone(),
+                action: action.clone(),
+            }),
+        }
+    }
+
+    /// Adds the time it took to decide on some tracks together, usually just the one
+    pub fn time_decision(&mut self, time: Duration, tracks: usize) {
+        self.decision_time += time;
+        self.timed_tracks += tracks;
+        self.average_decision_seconds =
+            Some(self.decision_time.as_secs_f64() / self.timed_tracks as f64);
+    }
+
+    pub fn finish(&mut self, source_after: usize) {
+        self.source_after = source_after;
+    }
+
+    fn to_markdown(&self) -> String {
+        let mut text = format!(
+            "# Sorting {}\n\n{}\n\n",
+            self.source_playlist,
+            self.started_at.format("%Y-%m-%d %H:%M UTC")
+        );
+        text += &format!(
+            "- Sorted: {}\n- Removed: {}\n- Skipped: {}\n",
+            self.sorted, self.removed, self.skipped
+        );
+        if let Some(seconds) = self.average_decision_seconds {
+            text += &format!("- Average decision time: {:.1}s\n", seconds);
+        }
+        text += &format!(
+            "- Source playlist: {} tracks, down from {}\n",
+            self.source_after, self.source_before
+        );
+
+        if !self.playlists.is_empty() {
+            text += "\n## Playlists\n\n| Playlist | Tracks added |\n| --- | --- |\n";
+            for count in &self.playlists {
+                text += &format!(
+                    "| {} | {} |\n",
+                    count.name.replace('|', "\\|"),
+                    count.tracks
+                );
+            }
+        }
+        if !self.failures.is_empty() {
+            text += "\n## Failures\n\n";
+            for failure in &self.failures {
+                text += &format!("- {}: {}\n", failure.track, failure.message);
+            }
+        }
+        text
+    }
+
+    /// Writes the summary as JSON if the file's extension says so, or as Markdown
+    pub fn write(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        let is_json = path
+            .extension()
+            .is_some_and(|extension| extension.eq_ignore_ascii_case("json"));
+        let contents = if is_json {
+            serde_json::to_string_pretty(self)?
+        } else {
+            self.to_markdown()
+        };
+        fs::write(path, contents)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::track;
+
+    fn playlist_id(n: u8) -> PlaylistId<'static> {
+        PlaylistId::from_id(format!("{:022}", n)).unwrap()
+    }
+
+    fn failure(action: TrackAction) -> Failure {
+        Failure {
+            track: String::from("Artist - Track"),
+            message: String::from("Failed to add to B"),
+            full_track: track("0000000000000000000001", "Track", "Artist", 1),
+            action,
+        }
+    }
+
+    #[test]
+    fn retrying_a_partly_failed_add_leaves_out_playlists_that_worked() {
+        let failure = failure(TrackAction::Add(vec![
+            playlist_id(1),
+            playlist_id(2),
+            playlist_id(3),
+        ]));
+        let added = [playlist_id(1), playlist_id(3)];
+
+        assert_eq!(
+            failure.remaining(|id| added.contains(id)),
+            TrackAction::Add(vec![playlist_id(2)])
+        );
+    }
+
+    #[test]
+    fn retrying_after_only_the_removal_failed_adds_nowhere() {
+        let failure = failure(TrackAction::Add(vec![playlist_id(1), playlist_id(2)]));
+
+        assert_eq!(failure.remaining(|_| true), TrackAction::Add(Vec::new()));
+    }
+
+    #[test]
+    fn retrying_a_removal_removes_again() {
+        let failure = failure(TrackAction::Remove(playlist_id(9)));
+
+        assert_eq!(
+            failure.remaining(|_| true),
+            TrackAction::Remove(playlist_id(9))
+        );
+    }
+}
@@ -0,0 +1,48 @@
+use rspotify::model::{FullTrack, SimplifiedPlaylist};
+use serde_json::json;
+
+/// A track as the API would send it, with just what tests look at filled in
+pub fn track(id: &str, name: &str, artist: &str, duration_ms: u64) -> FullTrack {
+    serde_json::from_value(json!({
+        "album": {
+            "album_type": "album",
+            "artists": [],
+            "external_urls": {},
+            "href": null,
+            "id": null,
+            "images": [],
+            "name": "Album",
+        },
+        "artists": [{ "external_urls": {}, "href": null, "id": null, "name": artist }],
+        "disc_number": 1,
+        "duration_ms": duration_ms,
+        "explicit": false,
+        "external_ids": {},
+        "external_urls": {},
+        "href": null,
+        "id": id,
+        "is_local": false,
+        "name": name,
+        "popularity": 0,
+        "preview_url": null,
+        "track_number": 1,
+    }))
+    .unwrap()
+}
+
+/// A playlist as the API would send it, with just what tests look at filled in
+pub fn playlist(id: &str, name: &str) -> SimplifiedPlaylist {
+    serde_json::from_value(json!({
+        "collaborative": false,
+        "external_urls": {},
+        "href": "",
+        "id": id,
+        "images": [],
+        "name": name,
+        "owner": { "external_urls": {}, "href": "", "id": "user" },
+        "public": true,
+        "snapshot_id": "",
+        "tracks": { "href": "", "total": 0 },
+    }))
+    .unwrap()
+}
@@ -0,0 +1,284 @@
+use std::collections::{HashMap, HashSet};
+
+use rspotify::{model::SimplifiedPlaylist, prelude::Id};
+
+use super::RESERVED_KEYS;
+use crate::{
+    config::{GroupConfig, PlaylistConfig},
+    fuzzy::{self, Candidate, Hit},
+    presets::Presets,
+};
+
+/// How the user arranged their playlists, along with which groups are collapsed right now
+pub struct Layout {
+    playlists: HashMap<String, PlaylistConfig>,
+    /// Groups from the config's list first, in order, then any others by name
+    groups: Vec<String>,
+    collapsed: HashSet<String>,
+}
+
+impl Layout {
+    /// Takes the arrangement from the config, leaving out hotkeys that can't be used and
+    /// returning why
+    pub fn new(
+        mut playlists: HashMap<String, PlaylistConfig>,
+        groups: Vec<GroupConfig>,
+    ) -> (Layout, Vec<String>) {
+        let mut problems = Vec::new();
+        let mut taken: HashMap<char, String> = HashMap::new();
+
+        // in order, so the same playlist keeps a contested hotkey every time
+        let mut ids: Vec<String> = playlists.keys().cloned().collect();
+        ids.sort();
+        for id in ids {
+            let settings = playlists.get_mut(&id).unwrap();
+            let Some(key) = settings.hotkey else {
+                continue;
+            };
+
+            let problem = if !key.is_alphabetic() {
+                Some(format!("hotkey {} of playlist {} isn't a letter", key, id))
+            } else if RESERVED_KEYS.contains(&key.to_string().as_str()) {
+                Some(format!(
+                    "hotkey {} of playlist {} is already used on the track screen",
+                    key, id
+                ))
+            } else if let Some(other) = taken.get(&key) {
+                Some(format!(
+                    "hotkey {} is given to both playlist {} and {}",
+                    key, other, id
+                ))
+            } else {
+                taken.insert(key, id.clone());
+                None
+            };
+            if let Some(problem) = problem {
+                settings.hotkey = None;
+                problems.push(problem);
+            }
+        }
+
+        let mut order: Vec<String> = Vec::new();
+        let mut collapsed = HashSet::new();
+        for group in groups {
+            if group.collapsed {
+                collapsed.insert(group.name.clone());
+            }
+            if !order.contains(&group.name) {
+                order.push(group.name);
+            }
+        }
+        let mut others: Vec<&String> = playlists
+            .values()
+            .filter_map(|settings| settings.group.as_ref())
+            .filter(|group| !order.contains(group))
+            .collect();
+        others.sort();
+        others.dedup();
+        order.extend(others.into_iter().cloned());
+
+        (
+            Layout {
+                playlists,
+                groups: order,
+                collapsed,
+            },
+            problems,
+        )
+    }
+
+    /// The hotkeys given to playlists, leaving out those that couldn't be used
+    pub fn hotkeys(&self) -> impl Iterator<Item = char> + '_ {
+        self.playlists
+            .values()
+            .filter_map(|settings| settings.hotkey)
+    }
+
+    pub fn toggle(&mut self, group: &str) {
+        if !self.collapsed.remove(group) {
+            self.collapsed.insert(group.to_string());
+        }
+    }
+
+    pub fn arrange(
+        &self,
+        playlists: &[SimplifiedPlaylist],
+        descriptions: &HashMap<String, String>,
+    ) -> Chooser {
+        let settings = |playlist: &SimplifiedPlaylist| self.playlists.get(playlist.id.id());
+        let is_favourite =
+            |playlist: &SimplifiedPlaylist| settings(playlist).is_some_and(|s| s.favourite);
+        let group =
+            |playlist: &SimplifiedPlaylist| settings(playlist).and_then(|s| s.group.as_ref());
+        let entries = |keep: &dyn Fn(&SimplifiedPlaylist) -> bool| -> Vec<Entry> {
+            playlists
+                .iter()
+                .enumerate()
+                .filter(|(_, playlist)| keep(playlist))
+                .map(|(index, playlist)| Entry {
+                    index,
+                    name: playlist.name.clone(),
+                    description: descriptions.get(playlist.id.id()).cloned(),
+                    hotkey: settings(playlist).and_then(|s| s.hotkey),
+                })
+                .collect()
+        };
+
+        let mut sections = Vec::new();
+        let favourites = entries(&is_favourite);
+        if !favourites.is_empty() {
+            sections.push(Section {
+                title: Some(String::from("Favourites")),
+                collapsed: false,
+                entries: favourites,
+            });
+        }
+        for name in &self.groups {
+            let members = entries(&|playlist| {
+                !is_favourite(playlist) && group(playlist).is_some_and(|group| group == name)
+            });
+            if !members.is_empty() {
+                sections.push(Section {
+                    title: Some(name.clone()),
+                    collapsed: self.collapsed.contains(name),
+                    entries: members,
+                });
+            }
+        }
+        let rest = entries(&|playlist| !is_favourite(playlist) && group(playlist).is_none());
+        if !rest.is_empty() {
+            // without favourites or groups, the list looks as it always has
+            let title = (!sections.is_empty()).then(|| String::from("Other playlists"));
+            sections.push(Section {
+                title,
+                collapsed: false,
+                entries: rest,
+            });
+        }
+
+        Chooser {
+            sections,
+            names: playlists
+                .iter()
+                .map(|playlist| playlist.name.clone())
+                .collect(),
+            groups: self.groups.clone(),
+            presets: Vec::new(),
+            previous: Vec::new(),
+        }
+    }
+}
+
+pub struct PresetEntry {
+    pub key: char,
+    pub name: String,
+    /// Where the preset's playlists are in the session's list, leaving out deleted ones
+    pub indices: Vec<usize>,
+}
+
+pub struct Entry {
+    /// Where the playlist is in the session's list
+    pub index: usize,
+    pub name: String,
+    pub description: Option<String>,
+    pub hotkey: Option<char>,
+}
+
+pub struct Section {
+    pub title: Option<String>,
+    pub collapsed: bool,
+    pub entries: Vec<Entry>,
+}
+
+/// The playlists as arranged on the track screen, numbered in the order they're shown
+pub struct Chooser {
+    pub sections: Vec<Section>,
+    /// By index in the session's list
+    names: Vec<String>,
+    groups: Vec<String>,
+    pub presets: Vec<PresetEntry>,
+    /// Where the playlists the last track went into are in the session's list
+    pub previous: Vec<usize>,
+}
+
+impl Chooser {
+    pub fn with_presets(mut self, presets: &Presets, playlists: &[SimplifiedPlaylist]) -> Chooser {
+        let indices = |ids: &[String]| -> Vec<usize> {
+            playlists
+                .iter()
+                .enumerate()
+                .filter(|(_, playlist)| ids.iter().any(|id| id == playlist.id.id()))
+                .map(|(index, _)| index)
+                .collect()
+        };
+        self.presets = presets
+            .presets
+            .iter()
+            .map(|preset| PresetEntry {
+                key: preset.key,
+                name: preset.name.clone(),
+                indices: indices(&preset.playlists),
+            })
+            .collect();
+        self.previous = indices(&presets.previous);
+        self
+    }
+
+    pub fn preset(&self, key: &str) -> Option<&PresetEntry> {
+        self.presets
+            .iter()
+            .find(|preset| preset.key.to_string() == key)
+    }
+
+    pub fn count(&self) -> usize {
+        self.names.len()
+    }
+
+    pub fn name(&self, index: usize) -> &str {
+        &self.names[index]
+    }
+
+    fn entries(&self) -> impl Iterator<Item = &Entry> {
+        self.sections.iter().flat_map(|section| &section.entries)
+    }
+
+    /// Index of the playlist shown with this number, collapsed groups included
+    pub fn index_of(&self, number: usize) -> Option<usize> {
+        self.entries()
+            .nth(number.checked_sub(1)?)
+            .map(|entry| entry.index)
+    }
+
+    pub fn index_of_hotkey(&self, key: &str) -> Option<usize> {
+        self.entries()
+            .find(|entry| entry.hotkey.is_some_and(|hotkey| hotkey.to_string() == key))
+            .map(|entry| entry.index)
+    }
+
+    /// The playlists matching the query, best first, with their numbers
+    pub fn filter(&self, query: &str) -> Vec<(usize, &Entry, Hit)> {
+        let entries: Vec<&Entry> = self.entries().collect();
+        let candidates: Vec<Candidate> = entries
+            .iter()
+            .map(|entry| Candidate {
+                name: &entry.name,
+                description: entry.description.as_deref(),
+            })
+            .collect();
+        fuzzy::filter(query, &candidates)
+            .into_iter()
+            .map(|hit| (hit.index + 1, entries[hit.index], hit))
+            .collect()
+    }
+
+    /// The group's name as configured, ignoring case
+    pub fn group(&self, name: &str) -> Option<&String> {
+        self.groups
+            .iter()
+            .find(|group| group.eq_ignore_ascii_case(name))
+    }
+
+    pub fn has_groups(&self) -> bool {
+        !self.groups.is_empty()
+    }
+}
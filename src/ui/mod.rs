@@ -1,13 +1,35 @@
-use std::collections::HashMap;
+use std::{
+    collections::HashMap,
+    io::Write,
+    path::{Path, PathBuf},
+};
 
+use chooser::Chooser;
 use clearscreen::clear as clearscreen;
-use rspotify::model::{FullTrack, SimplifiedPlaylist};
+use rspotify::model::{FullTrack, SimplifiedArtist, SimplifiedPlaylist};
+use rspotify::prelude::Id;
 use text_io::read;
 use utils::{string_to_half_screen, wrap_text_to_screen};
 use yansi::Paint;
 
-use crate::{services, spotify::SpotifyPlaylistsError};
+use crate::{
+    audio::{Samples, Visualiser},
+    fuzzy::Candidate,
+    import::LocalTrack,
+    plan,
+    queue::{Group, Queue, Status},
+    rules::Rule,
+    scripting::Binding,
+    search::Hit,
+    services,
+    snapshot::{Diff, PlaylistChange, Restore, SnapshotTrack},
+    spotify::{PlaylistTrack, SpotifyPlaylistsError},
+    suggestions::Suggestions,
+    summary::Summary,
+};
 
+pub(crate) mod chooser;
+pub(crate) mod selection;
 pub(crate) mod track;
 pub(crate) mod utils;
 
@@ -15,10 +37,72 @@ pub enum TrackAction {
     Add(Vec<usize>),
     Remove,
     Skip,
+    /// Decides on all of a group's tracks at these positions in the queue, adding them to the
+    /// checked playlists or, if there are none, removing them
+    Batch {
+        positions: Vec<usize>,
+        checked: Vec<usize>,
+    },
+    /// Skips the track for this many days
+    Snooze(u32),
+    /// Puts the track off until the end of the session
+    Defer,
     ChangeVolume(bool),
+    CycleVisualiser,
+    /// Index of one of the script's key bindings, and the playlists checked at the time
+    Custom {
+        binding: usize,
+        checked: Vec<usize>,
+    },
+    Previous,
+    /// Number of the track to go to, as shown in the list
+    GoTo(usize),
+    ListTracks,
+    /// Along with the indices of the playlists checked at the time, to keep them checked
+    EditPlaylists {
+        edit: PlaylistEdit,
+        checked: Vec<usize>,
+    },
+    ToggleGroup {
+        group: String,
+        checked: Vec<usize>,
+    },
+    /// Saves what's checked as a preset, and keeps it checked
+    SavePreset {
+        name: String,
+        key: char,
+        checked: Vec<usize>,
+    },
     Quit,
 }
 
+pub enum PlaylistEdit {
+    Create {
+        name: String,
+        description: String,
+        public: bool,
+    },
+    Rename {
+        index: usize,
+        name: String,
+    },
+    Delete(usize),
+}
+
+/// Keys of the track screen, which scripts can't bind
+pub const RESERVED_KEYS: [&str; 20] = [
+    "y", "a", "s", "z", "w", "r", "m", "p", ".", "n", "e", "x", "c", "b", "g", "l", "u", "d", "v",
+    "q",
+];
+
+/// How far into the source playlist the track on screen is
+pub struct Progress {
+    /// Counting from 1
+    pub number: usize,
+    pub total: usize,
+    pub has_previous: bool,
+}
+
 pub fn welcome() {
     println!("♪♫♪ {}", "Welcome to Sortify!".bold().italic());
 }
@@ -61,19 +145,29 @@ pub fn confirm_account(user_name: Option<String>) -> bool {
     true
 }
 
-pub fn choose_source(playlists: &Vec<SimplifiedPlaylist>) -> usize {
+pub fn loading(what: &str) {
+    println!("{}", format!("{}...", what).italic().dim());
+}
+
+pub fn choose_source(
+    playlists: &[SimplifiedPlaylist],
+    descriptions: &HashMap<String, String>,
+) -> usize {
     println!("Choose source playlist");
     println!(
         "{}",
-        wrap_text_to_screen(&"You'll have the option to 'sort' each track in this playlist. When sorted, tracks are added to other playlists, removed from the source, and added to your liked songs.".to_string()).italic().dim()
+        wrap_text_to_screen("You'll have the option to 'sort' each track in this playlist. When sorted, tracks are added to other playlists, removed from the source, and added to your liked songs.").italic().dim()
     );
     println!();
 
     let source_index = utils::choose_one(
         &playlists
             .iter()
-            .map(|playlist| playlist.name.clone())
-            .collect(),
+            .map(|playlist| Candidate {
+                name: &playlist.name,
+                description: descriptions.get(playlist.id.id()).map(String::as_str),
+            })
+            .collect::<Vec<Candidate>>(),
     );
 
     _ = clearscreen();
@@ -87,95 +181,430 @@ pub fn choose_source(playlists: &Vec<SimplifiedPlaylist>) -> usize {
     source_index
 }
 
+/// Shows the track, as rendered by `track::display`, and asks what to do with it
 pub fn handle_track(
-    track: &FullTrack,
-    playlists: &Vec<SimplifiedPlaylist>,
-    image_cache: &mut HashMap<String, String>,
-    volume: Option<f32>,
+    track: &track::Shown,
+    progress: &Progress,
+    group: Option<&Group>,
+    chooser: &Chooser,
+    audio: Option<(f32, Visualiser, Samples)>,
+    suggestions: &Suggestions,
+    bindings: &[Binding],
 ) -> TrackAction {
-    let mut selected: Vec<bool> = vec![false; playlists.len()];
-    let playlist_names: Vec<&String> = playlists.iter().map(|playlist| &playlist.name).collect();
+    let volume = audio.as_ref().map(|(volume, _, _)| *volume);
+    let visualiser = audio.map(|(_, mode, samples)| (mode, samples));
+    let suggested = &suggestions.suggested;
+    // start out with what past decisions say is likely, and what the script asked for, unless
+    // the screen is being rebuilt with playlists already checked
+    let mut selected: Vec<bool> = vec![false; chooser.count()];
+    match &suggestions.checked {
+        Some(checked) => {
+            for i in checked {
+                selected[*i] = true;
+            }
+        }
+        None => {
+            for i in suggestions
+                .predicted
+                .iter()
+                .map(|(i, _)| i)
+                .chain(&suggestions.scripted)
+            {
+                selected[*i] = true;
+            }
+        }
+    }
+    // a query narrowing down the playlists shown
+    let mut filter: Option<String> = None;
+    // what couldn't be made sense of in the last line entered
+    let mut invalid: Option<Vec<String>> = None;
+    // what's needed before the last key entered can do anything
+    let mut hint: Option<&str> = None;
+    let checked = |selected: &[bool]| -> Vec<usize> {
+        selected
+            .iter()
+            .enumerate()
+            .filter(|(_, is_selected)| **is_selected)
+            .map(|(i, _)| i)
+            .collect()
+    };
 
     loop {
         println!(
-            "{}\n\n{}",
-            track::display(track, image_cache),
-            "Choose playlists to add track to"
+            "{}",
+            utils::center_string(&format!("{}/{}", progress.number, progress.total)).dim()
         );
-        println!();
+        println!("{}", track.title);
+        if let Some((mode, samples)) = &visualiser {
+            if let Some(row) = track::visualiser::render(*mode, samples) {
+                println!("{}", row);
+            }
+        }
+        println!("{}", track.details);
 
-        for i in 0..playlist_names.len() {
-            if selected[i] {
-                let line = format!("[✓] {} - {}", i + 1, playlist_names[i]);
-                print!("{} ", string_to_half_screen(&line).green());
-            } else {
-                let line = format!("{} - {}", i + 1, playlist_names[i]);
-                print!("{} ", string_to_half_screen(&line));
-            };
+        // everything below the track is gathered first, so we know how far up the visualiser row is
+        let mut menu = String::from("\nChoose playlists to add track to\n\n");
 
-            if i % 2 != 0 || i == playlist_names.len() - 1 {
-                println!();
+        let label = |number: usize, entry: &chooser::Entry| match entry.hotkey {
+            Some(hotkey) => format!("{}/{}", number, hotkey),
+            None => number.to_string(),
+        };
+        let hits = filter
+            .as_ref()
+            .map(|query| chooser.filter(query))
+            .unwrap_or_default();
+        if let Some(query) = &filter {
+            if hits.is_empty() {
+                menu += &format!("Nothing matches {}\n", query);
+            }
+            for (number, entry, hit) in &hits {
+                let candidate = Candidate {
+                    name: &entry.name,
+                    description: entry.description.as_deref(),
+                };
+                let mark = if selected[entry.index] {
+                    format!("{} ", "[✓]".green())
+                } else if suggested.contains(&entry.index) {
+                    format!("{} ", "★".yellow())
+                } else {
+                    String::new()
+                };
+                menu += &format!(
+                    "{}{} - {}\n",
+                    mark,
+                    label(*number, entry),
+                    utils::filtered_name(&candidate, hit)
+                );
             }
+            menu += &format!(
+                "\n{}\n",
+                "Enter or Space toggles these, / on its own shows all playlists"
+                    .italic()
+                    .dim()
+            );
         }
 
-        println!();
-        println!("a - Confirm and add to playlists");
-        println!("s - Skip track");
-        println!("r - Remove from source without adding");
+        let mut number = 0;
+        for section in chooser.sections.iter().filter(|_| filter.is_none()) {
+            if let Some(title) = &section.title {
+                if section.collapsed {
+                    let checked = section
+                        .entries
+                        .iter()
+                        .filter(|entry| selected[entry.index])
+                        .count();
+                    menu += &format!(
+                        "{} {}\n",
+                        format!("▸ {}", title).bold(),
+                        format!("({} playlists, {} checked)", section.entries.len(), checked).dim()
+                    );
+                    // numbers stay the same whether a group is collapsed or not
+                    number += section.entries.len();
+                    continue;
+                }
+                menu += &format!("{}\n", format!("▾ {}", title).bold());
+            }
+
+            for (i, entry) in section.entries.iter().enumerate() {
+                number += 1;
+                let label = label(number, entry);
+                if selected[entry.index] {
+                    let line = format!("[✓] {} - {}", label, entry.name);
+                    menu += &format!("{} ", string_to_half_screen(&line).green());
+                } else if suggested.contains(&entry.index) {
+                    let line = format!("★ {} - {}", label, entry.name);
+                    menu += &format!("{} ", string_to_half_screen(&line).yellow());
+                } else {
+                    let line = format!("{} - {}", label, entry.name);
+                    menu += &format!("{} ", string_to_half_screen(&line));
+                };
+
+                if i % 2 != 0 || i == section.entries.len() - 1 {
+                    menu += "\n";
+                }
+            }
+            if section.title.is_some() {
+                menu += "\n";
+            }
+        }
+
+        menu += "\n";
+        if !suggestions.predicted.is_empty() {
+            let predictions: Vec<String> = suggestions
+                .predicted
+                .iter()
+                .map(|(i, confidence)| format!("{} ({:.0}%)", chooser.name(*i), confidence * 100.0))
+                .collect();
+            menu += &format!(
+                "{}\n\n",
+                wrap_text_to_screen(&format!(
+                    "Pre-selected from your past decisions: {}",
+                    predictions.join(", ")
+                ))
+                .italic()
+                .dim()
+            );
+        }
+        if !suggestions.scripted.is_empty() {
+            let scripted: Vec<&str> = suggestions
+                .scripted
+                .iter()
+                .map(|i| chooser.name(*i))
+                .collect();
+            menu += &format!(
+                "{}\n\n",
+                wrap_text_to_screen(&format!(
+                    "Pre-selected by your script: {}",
+                    scripted.join(", ")
+                ))
+                .italic()
+                .dim()
+            );
+        }
+        if !suggested.is_empty() {
+            menu += "y - Select suggested playlists (★)\n";
+        }
+        let names = |indices: &[usize]| -> String {
+            indices
+                .iter()
+                .map(|i| chooser.name(*i))
+                .collect::<Vec<&str>>()
+                .join(" + ")
+        };
+        for preset in &chooser.presets {
+            menu += &format!(
+                "{} - {} {}\n",
+                preset.key,
+                preset.name,
+                format!("({})", names(&preset.indices)).dim()
+            );
+        }
+        if !chooser.previous.is_empty() {
+            menu += &format!(
+                ". - Same as previous {}\n",
+                format!("({})", names(&chooser.previous)).dim()
+            );
+        }
+        menu += "p - Save checked playlists as a preset\n";
+        menu += "a - Confirm and add to playlists\n";
+        if let Some(group) = group {
+            menu += &format!(
+                "m - Decide for all {} tracks {}\n",
+                group.tracks.len(),
+                group.shared
+            );
+        }
+        menu += &format!(
+            "{}\n",
+            "3-7 +4 -5 @name clear invert - Toggle, check or uncheck; end with a to confirm".dim()
+        );
+        menu += "s - Skip track | z - Skip for a number of days\n";
+        menu += "w - Decide later, at the end of the session\n";
+        menu += "r - Remove from source without adding\n";
+        if progress.has_previous {
+            menu += "b - Back to previous track\n";
+        }
+        menu += "g - Go to track | l - List all tracks\n";
+        menu += "/text - Filter playlists by name or description\n";
+        menu += "n - New playlist | e - Rename a playlist | x - Delete a playlist\n";
+        if chooser.has_groups() {
+            menu += "c - Collapse or expand a group\n";
+        }
+        for binding in bindings {
+            menu += &format!("{} - {}\n", binding.key, binding.description);
+        }
         if let Some(vol) = volume {
-            println!();
-            println!(
-                "Current volume: {:.1}\nu - Volume up | d - Volume down",
+            menu += &format!(
+                "\nCurrent volume: {:.1}\nu - Volume up | d - Volume down\n",
                 vol
             );
-            println!();
         }
-        println!("q - Quit");
-        println!();
+        if let Some((mode, _)) = &visualiser {
+            menu += &format!("v - Visualiser ({})\n", mode.name());
+        }
+        if volume.is_some() || visualiser.is_some() {
+            menu += "\n";
+        }
+        menu += "q - Quit\n\n";
+        if let Some(tokens) = invalid.take() {
+            menu += &format!(
+                "{}\n",
+                format!("Not understood, nothing changed: {}", tokens.join(" ")).red()
+            );
+        }
+        if let Some(hint) = hint.take() {
+            menu += &format!("{}\n", hint.yellow());
+        }
 
-        print!("Choice: ");
-        let user_input: String = read!();
+        print!("{}Choice: ", menu);
+        _ = std::io::stdout().flush();
+
+        // redraw the visualiser while waiting for input, unless it has scrolled out of view. Long
+        // lines wrap, so what's under the row is measured in rows of the terminal, not lines.
+        let rows_up = utils::screen_columns().map(|columns| {
+            utils::screen_rows(&format!("{}\n{}Choice: ", track.details, menu), columns)
+        });
+        let animation = match (&visualiser, rows_up) {
+            (Some((mode, samples)), Some(rows_up))
+                if *mode != Visualiser::Off
+                    && utils::screen_height().is_some_and(|height| rows_up < height) =>
+            {
+                Some(track::visualiser::Animation::start(
+                    *mode,
+                    samples.clone(),
+                    rows_up,
+                ))
+            }
+            _ => None,
+        };
+
+        let user_input: String = read!("{}\n");
+        drop(animation);
         println!();
 
+        if let Some(query) = user_input.trim().strip_prefix('/') {
+            let query = query.trim();
+            filter = (!query.is_empty()).then(|| query.to_string());
+            _ = clearscreen();
+            continue;
+        }
+        if user_input.trim().is_empty() && filter.is_some() {
+            for (_, entry, _) in &hits {
+                selected[entry.index] = !selected[entry.index];
+            }
+            filter = None;
+            _ = clearscreen();
+            continue;
+        }
+
         match user_input.trim() {
             "r" => break TrackAction::Remove,
             "s" => break TrackAction::Skip,
+            "z" => {
+                let days = read_line("Days to skip it for (leave empty to cancel): ");
+                match days.parse::<u32>() {
+                    Ok(days) if days > 0 => break TrackAction::Snooze(days),
+                    _ if !days.is_empty() => invalid = Some(vec![days]),
+                    _ => (),
+                }
+                _ = clearscreen();
+                continue;
+            }
+            "w" => {
+                _ = clearscreen();
+                break TrackAction::Defer;
+            }
+            "m" if group.is_some() => {
+                let checked = checked(&selected);
+                let names: Vec<&str> = checked.iter().map(|i| chooser.name(*i)).collect();
+                if let Some(positions) = choose_grouped(group.unwrap(), &names) {
+                    _ = clearscreen();
+                    break TrackAction::Batch { positions, checked };
+                }
+                _ = clearscreen();
+                continue;
+            }
+            "b" if progress.has_previous => {
+                _ = clearscreen();
+                break TrackAction::Previous;
+            }
+            "g" => {
+                let number = read_line(&format!(
+                    "Track to go to (1-{}, leave empty to cancel): ",
+                    progress.total
+                ));
+                match number.parse::<usize>() {
+                    Ok(number) => {
+                        _ = clearscreen();
+                        break TrackAction::GoTo(number);
+                    }
+                    Err(_) if !number.is_empty() => invalid = Some(vec![number]),
+                    Err(_) => (),
+                }
+                _ = clearscreen();
+                continue;
+            }
+            "l" => {
+                _ = clearscreen();
+                break TrackAction::ListTracks;
+            }
             "q" => break TrackAction::Quit,
-            "u" => {
-                if let Some(_) = volume {
-                    // causes loop without feedback, so screen needs clearing
+            "u" if volume.is_some() => {
+                // causes loop without feedback, so screen needs clearing
+                _ = clearscreen();
+                break TrackAction::ChangeVolume(true);
+            }
+            "d" if volume.is_some() => {
+                // causes loop without feedback, so screen needs clearing
+                _ = clearscreen();
+                break TrackAction::ChangeVolume(false);
+            }
+            "v" if visualiser.is_some() => {
+                // causes loop without feedback, so screen needs clearing
+                _ = clearscreen();
+                break TrackAction::CycleVisualiser;
+            }
+            "p" => {
+                if checked(&selected).is_empty() {
+                    hint = Some("Check the playlists for the preset first");
+                } else if let Some((name, key)) = new_preset(chooser, bindings) {
+                    _ = clearscreen();
+                    break TrackAction::SavePreset {
+                        name,
+                        key,
+                        checked: checked(&selected),
+                    };
+                }
+                _ = clearscreen();
+                continue;
+            }
+            "n" | "e" | "x" => {
+                let edit = match user_input.trim() {
+                    "n" => new_playlist(),
+                    "e" => rename_playlist(chooser),
+                    _ => delete_playlist(chooser),
+                };
+                if let Some(edit) = edit {
                     _ = clearscreen();
-                    break TrackAction::ChangeVolume(true);
+                    break TrackAction::EditPlaylists {
+                        edit,
+                        checked: checked(&selected),
+                    };
                 }
-                ()
+                _ = clearscreen();
+                continue;
             }
-            "d" => {
-                if let Some(_) = volume {
+            "c" if chooser.has_groups() => {
+                if let Some(group) = choose_group(chooser) {
                     // causes loop without feedback, so screen needs clearing
                     _ = clearscreen();
-                    break TrackAction::ChangeVolume(false);
+                    break TrackAction::ToggleGroup {
+                        group,
+                        checked: checked(&selected),
+                    };
                 }
-                ()
-            }
-            "a" => {
-                break {
-                    let mut indexes: Vec<usize> = Vec::new();
-                    for i in 0..selected.len() {
-                        if selected[i] {
-                            indexes.push(i)
-                        }
-                    }
-                    TrackAction::Add(indexes)
+                _ = clearscreen();
+                continue;
+            }
+            key => {
+                if let Some(binding) = bindings.iter().position(|binding| binding.key == key) {
+                    // the script's decision is shown on the next screen, so this one is done
+                    _ = clearscreen();
+                    break TrackAction::Custom {
+                        binding,
+                        checked: checked(&selected),
+                    };
                 }
             }
-            _ => (),
         }
 
-        for maybe_number in user_input.split_ascii_whitespace() {
-            if let Ok(number) = maybe_number.parse::<usize>() {
-                if number >= 1 && number <= playlists.len() {
-                    selected[number - 1] = !selected[number - 1];
-                }
+        // anything else picks playlists, possibly confirming them in the same line
+        match selection::apply(&user_input, &mut selected, chooser, suggested) {
+            Ok(true) => break TrackAction::Add(checked(&selected)),
+            Ok(false) => (),
+            Err(tokens) => {
+                log::warn!("Ignoring selection with invalid tokens: {:?}", tokens);
+                invalid = Some(tokens);
             }
         }
 
@@ -184,15 +613,308 @@ pub fn handle_track(
     }
 }
 
+/// Lists a group's tracks to leave some of them out, returning the positions of the rest
+fn choose_grouped(group: &Group, playlists: &[&str]) -> Option<Vec<usize>> {
+    println!();
+    if playlists.is_empty() {
+        println!("Removing all tracks {} from the source:", group.shared);
+    } else {
+        println!(
+            "Adding all tracks {} to {}:",
+            group.shared,
+            playlists.join(", ")
+        );
+    }
+    for (number, (_, track)) in group.tracks.iter().enumerate() {
+        println!("{:>4}. {}", number + 1, track::summary(track));
+    }
+    println!();
+
+    loop {
+        let input = read_line("Numbers of tracks to leave out (Enter for none, q to cancel): ");
+        if input == "q" {
+            return None;
+        }
+        let left_out: Result<Vec<usize>, _> = input
+            .split_whitespace()
+            .map(|number| match number.parse::<usize>() {
+                // the track on screen is what the decision was made for
+                Ok(n) if (2..=group.tracks.len()).contains(&n) => Ok(n - 1),
+                _ => Err(number),
+            })
+            .collect();
+        match left_out {
+            Ok(left_out) => {
+                return Some(
+                    group
+                        .tracks
+                        .iter()
+                        .enumerate()
+                        .filter(|(number, _)| !left_out.contains(number))
+                        .map(|(_, (position, _))| *position)
+                        .collect(),
+                )
+            }
+            Err(number) => println!("Option {} is invalid, please try again", number),
+        }
+    }
+}
+
+/// For a file sortify saves that couldn't be read, and where it was moved to keep it
+pub fn data_file_warning(message: &str, aside: Option<&Path>) {
+    match aside {
+        Some(aside) => println!(
+            "{} {}",
+            message.yellow(),
+            format!("Starting over, the old file is kept as {}", aside.display()).dim()
+        ),
+        None => println!("{}", message.yellow()),
+    }
+}
+
+pub fn config_load_warning(message: &str) {
+    println!(
+        "{} {}",
+        "Ignoring your config, using the default settings:".yellow(),
+        message
+    );
+}
+
+fn read_line(prompt: &str) -> String {
+    print!("{}", prompt);
+    let line: String = read!("{}\n");
+    line.trim().to_string()
+}
+
+fn new_playlist() -> Option<PlaylistEdit> {
+    let name = read_line("Name of the new playlist (leave empty to cancel): ");
+    if name.is_empty() {
+        return None;
+    }
+    let description = read_line("Description (optional): ");
+
+    println!();
+    println!("y - Public");
+    println!("n - Private");
+    let public = loop {
+        println!();
+        print!("Choice: ");
+        let user_input: char = read!();
+        match user_input {
+            'y' => break true,
+            'n' => break false,
+            _ => println!("option {} is invalid, please try again", user_input),
+        }
+    };
+
+    Some(PlaylistEdit::Create {
+        name,
+        description,
+        public,
+    })
+}
+
+/// Index of a playlist by its number or hotkey on the track screen
+fn choose_listed_playlist(chooser: &Chooser, verb: &str) -> Option<usize> {
+    loop {
+        let input = read_line(&format!(
+            "Number of the playlist to {} (leave empty to cancel): ",
+            verb
+        ));
+        if input.is_empty() {
+            return None;
+        }
+        let index = match input.parse::<usize>() {
+            Ok(number) => chooser.index_of(number),
+            Err(_) => chooser.index_of_hotkey(&input),
+        };
+        match index {
+            Some(index) => return Some(index),
+            None => println!("Option {} is invalid, please try again", input),
+        }
+    }
+}
+
+fn rename_playlist(chooser: &Chooser) -> Option<PlaylistEdit> {
+    let index = choose_listed_playlist(chooser, "rename")?;
+    let name = read_line(&format!(
+        "New name for {} (leave empty to cancel): ",
+        chooser.name(index)
+    ));
+    (!name.is_empty()).then_some(PlaylistEdit::Rename { index, name })
+}
+
+fn delete_playlist(chooser: &Chooser) -> Option<PlaylistEdit> {
+    let index = choose_listed_playlist(chooser, "delete")?;
+    println!();
+    utils::confirmation(format!(
+        "Do you wish to delete {}? It can only be brought back from a snapshot.",
+        chooser.name(index).bold()
+    ))
+    .then_some(PlaylistEdit::Delete(index))
+}
+
+/// Name and key of a preset for the checked playlists
+fn new_preset(chooser: &Chooser, bindings: &[Binding]) -> Option<(String, char)> {
+    let name = read_line("Name of the preset (leave empty to cancel): ");
+    if name.is_empty() {
+        return None;
+    }
+    loop {
+        let key = read_line("Key for the preset (leave empty to cancel): ");
+        let mut chars = key.chars();
+        let (Some(c), None) = (chars.next(), chars.next()) else {
+            if key.is_empty() {
+                return None;
+            }
+            println!("A preset's key is a single letter, please try again");
+            continue;
+        };
+
+        let is_taken = RESERVED_KEYS.contains(&key.as_str())
+            || bindings.iter().any(|binding| binding.key == key)
+            || chooser.index_of_hotkey(&key).is_some();
+        if !c.is_alphabetic() {
+            println!("A preset's key is a single letter, please try again");
+        } else if is_taken {
+            println!("{} is already used on this screen, please try again", key);
+        } else {
+            if let Some(preset) = chooser.preset(&key) {
+                println!("This replaces preset {}", preset.name);
+            }
+            return Some((name, c));
+        }
+    }
+}
+
+fn choose_group(chooser: &Chooser) -> Option<String> {
+    loop {
+        let input = read_line("Name of the group to collapse or expand (leave empty to cancel): ");
+        if input.is_empty() {
+            return None;
+        }
+        match chooser.group(&input) {
+            Some(group) => return Some(group.clone()),
+            None => println!("There's no group called {}, please try again", input),
+        }
+    }
+}
+
+pub fn config_warning(message: &str) {
+    println!("{} {}", "Ignoring part of your config:".yellow(), message);
+}
+
+pub fn playlist_edit_error(message: &str) {
+    println!("{} {}", "Failed to change playlists:".red(), message);
+    println!();
+}
+
 pub fn track_action_feedback(
     track: &FullTrack,
     result: Result<services::TrackAction, SpotifyPlaylistsError>,
 ) {
     _ = clearscreen();
 
+    println!("{}", feedback_message(track, result));
+    println!();
+}
+
+/// Lists the tracks of the source playlist with what happened to each, returning the position of
+/// the one to go to, if any
+pub fn choose_queued(queue: &Queue) -> Option<usize> {
+    println!("{}", "Tracks of the source playlist".bold());
+    println!();
+    for (i, (track, status)) in queue.tracks().enumerate() {
+        let marker = if i == queue.position() { "▸" } else { " " };
+        let status = match status {
+            Status::Pending => status.name().dim(),
+            Status::Sorted => status.name().green(),
+            Status::Removed => status.name().red(),
+            Status::Skipped => status.name().yellow(),
+            Status::Deferred => status.name().blue(),
+        };
+        println!(
+            "{} {} {} [{}]",
+            marker,
+            format!("{:>4}.", i + 1).dim(),
+            track::summary(track),
+            status
+        );
+    }
+    println!();
+
+    loop {
+        let number = read_line("Track to go to (leave empty to go back): ");
+        if number.is_empty() {
+            _ = clearscreen();
+            return None;
+        }
+        match number.parse::<usize>() {
+            Ok(n) if queue.is_open(n.wrapping_sub(1)) => {
+                _ = clearscreen();
+                return Some(n - 1);
+            }
+            Ok(n) => unavailable_track(n, queue.status(n.wrapping_sub(1))),
+            Err(_) => println!("Option {} is invalid, please try again", number),
+        }
+    }
+}
+
+pub fn filtered_tracks(count: usize) {
+    println!(
+        "{}",
+        format!(
+            "Leaving out {} tracks that don't match the queue's filters",
+            count
+        )
+        .italic()
+        .dim()
+    );
+}
+
+pub fn snoozed_tracks(count: usize) {
+    println!(
+        "{}",
+        format!("Leaving out {} tracks skipped for now", count)
+            .italic()
+            .dim()
+    );
+    println!();
+}
+
+/// For a track that can't be gone back to, because it's no longer in the source playlist
+pub fn unavailable_track(number: usize, status: Option<Status>) {
+    match status {
+        Some(status) => println!(
+            "{} {} {}",
+            "Track".red(),
+            number,
+            format!("has already been {}", status.name()).red()
+        ),
+        None => println!("{} {}", "There is no track".red(), number),
+    }
+}
+
+/// Reports a decision made by a rule, one line each so they can be followed as they happen
+pub fn rule_feedback(
+    rule_name: &str,
+    track: &FullTrack,
+    result: Result<services::TrackAction, SpotifyPlaylistsError>,
+) {
+    println!(
+        "{} {}",
+        format!("[{}]", rule_name).dim(),
+        feedback_message(track, result)
+    );
+}
+
+fn feedback_message(
+    track: &FullTrack,
+    result: Result<services::TrackAction, SpotifyPlaylistsError>,
+) -> String {
     let track_summary = track::summary(track);
 
-    let msg = match result {
+    match result {
         Ok(action) => match action {
             services::TrackAction::Add(_) => format!("Sucessfully sorted {}", track_summary),
             services::TrackAction::Remove(_) => format!(
@@ -213,12 +935,431 @@ pub fn track_action_feedback(
                 playlists.join(", ")
             ),
         },
+    }
+}
+
+pub fn unknown_playlist(name: &str) {
+    println!("{} {}", "You have no playlist called".red(), name);
+}
+
+pub fn exported(written: &[(PathBuf, usize)]) {
+    for (path, tracks) in written {
+        println!("Wrote {} tracks to {}", tracks, path.display());
+    }
+}
+
+pub fn snapshot_saved(path: &Path) {
+    println!("Saved snapshot to {}", path.display());
+}
+
+pub fn snapshot_error(message: &str) {
+    println!("{} {}", "Snapshot failed:".red(), message);
+}
+
+/// Lists what restoring would change and asks for the go ahead
+pub fn confirm_restore(restore: &Restore) -> bool {
+    println!();
+    if restore.playlists.is_empty() && restore.liked.is_empty() {
+        println!("Your library is already as it was in the snapshot");
+        return false;
+    }
+
+    for playlist in &restore.playlists {
+        let change = if playlist.id.is_none() {
+            format!("recreate with {} tracks", playlist.missing)
+        } else if playlist.missing > 0 {
+            format!(
+                "add back {} tracks and restore their order",
+                playlist.missing
+            )
+        } else {
+            String::from("restore track order")
+        };
+        println!("{} {}", playlist.name.bold(), format!("- {}", change).dim());
+    }
+    if !restore.liked.is_empty() {
+        println!(
+            "{} {}",
+            "Liked Songs".bold(),
+            format!("- like {} tracks again", restore.liked.len()).dim()
+        );
+    }
+    println!();
+
+    utils::confirmation("Restore these playlists?".to_string())
+}
+
+pub fn restored(errors: &[String]) {
+    if errors.is_empty() {
+        println!("Restored your library");
+    } else {
+        println!("{} {}", "Failed to restore".red(), errors.join(", "));
+    }
+}
+
+pub fn diff_report(diff: &Diff) {
+    if diff.is_empty() {
+        println!("No changes");
+        return;
+    }
+
+    let print_tracks = |sign: &str, tracks: &[SnapshotTrack]| {
+        for snapshot_track in tracks {
+            let line = format!(
+                "  {} {} - {}",
+                sign,
+                snapshot_track.name,
+                snapshot_track.artists.join(", ")
+            );
+            if sign == "+" {
+                println!("{}", line.green());
+            } else {
+                println!("{}", line.red());
+            }
+        }
     };
 
-    println!("{}", msg);
+    for playlist in &diff.playlists {
+        let heading = match (&playlist.change, &playlist.renamed_from) {
+            (PlaylistChange::Created, _) => format!("{} (created)", playlist.name),
+            (PlaylistChange::Deleted, _) => format!("{} (deleted)", playlist.name),
+            (PlaylistChange::Changed, Some(old)) => {
+                format!("{} (renamed from {})", playlist.name, old)
+            }
+            (PlaylistChange::Changed, None) => playlist.name.clone(),
+        };
+        println!("{}", heading.bold());
+        print_tracks("+", &playlist.added);
+        print_tracks("-", &playlist.removed);
+        if playlist.reordered {
+            println!("{}", "  ~ tracks reordered".yellow());
+        }
+        println!();
+    }
+
+    if !diff.liked.is_empty() || !diff.unliked.is_empty() {
+        println!("{}", "Liked Songs".bold());
+        print_tracks("+", &diff.liked);
+        print_tracks("-", &diff.unliked);
+    }
+}
+
+pub fn diff_json(diff: &Diff) {
+    match serde_json::to_string_pretty(diff) {
+        Ok(json) => println!("{}", json),
+        Err(e) => log::error!("Failed to serialize diff: {}", e),
+    }
+}
+
+fn length(milliseconds: i64) -> String {
+    format!("{}:{:02}", milliseconds / 60000, milliseconds / 1000 % 60)
+}
+
+/// Which of the tracks the file is, if any
+pub fn choose_match(local: &LocalTrack, candidates: &[FullTrack]) -> Option<usize> {
+    println!();
+    println!(
+        "Which of these is {}{}?",
+        local.summary().bold(),
+        local
+            .duration_ms
+            .map(|duration_ms| format!(" ({})", length(duration_ms)))
+            .unwrap_or_default()
+    );
+    for (i, candidate) in candidates.iter().enumerate() {
+        println!(
+            "{} - {} {}",
+            i + 1,
+            track::summary(candidate),
+            format!(
+                "({}, {})",
+                candidate.album.name,
+                length(candidate.duration.num_milliseconds())
+            )
+            .dim()
+        );
+    }
+    println!("0 - None of these");
+
+    loop {
+        println!();
+        print!("Choice: ");
+        let user_input: usize = read!();
+
+        match user_input {
+            0 => break None,
+            n if n <= candidates.len() => break Some(n - 1),
+            _ => println!("Option {} is invalid, please try again", user_input),
+        }
+    }
+}
+
+pub fn not_found(missing: &[&LocalTrack]) {
+    println!("Couldn't find {} tracks on Spotify:", missing.len());
+    for local in missing {
+        println!("  {}", local.summary().dim());
+    }
+    println!();
+}
+
+pub fn confirm_import(found: usize, missing: &[&LocalTrack], destination: &str) -> bool {
+    println!();
+    if !missing.is_empty() {
+        not_found(missing);
+    }
+    if found == 0 {
+        println!("There's nothing to import");
+        return false;
+    }
+
+    utils::confirmation(format!("Add {} tracks to {}?", found, destination.bold()))
+}
+
+pub fn added_tracks(count: usize, playlist: &str) {
+    println!("Added {} tracks to {}", count, playlist);
     println!();
 }
 
+pub fn search_query() -> String {
+    print!("Search for a track, album or artist (leave empty to stop): ");
+    let query: String = read!("{}\n");
+    query.trim().to_string()
+}
+
+fn describe_hit(hit: &Hit) -> String {
+    let names = |artists: &[SimplifiedArtist]| {
+        artists
+            .iter()
+            .map(|artist| artist.name.clone())
+            .collect::<Vec<String>>()
+            .join(", ")
+    };
+    match hit {
+        Hit::Track(track) => format!(
+            "{} {}",
+            track::summary(track),
+            format!("({})", track.album.name).dim()
+        ),
+        Hit::Album(album) => format!(
+            "{} {} - {}",
+            "Album".italic(),
+            album.name,
+            names(&album.artists)
+        ),
+        Hit::Artist(artist) => format!(
+            "{} {} {}",
+            "Artist".italic(),
+            artist.name,
+            "(top tracks)".dim()
+        ),
+    }
+}
+
+/// Which of the search results to add
+pub fn choose_hits(hits: &[Hit]) -> Vec<usize> {
+    println!();
+    if hits.is_empty() {
+        println!("Nothing found");
+        println!();
+        return Vec::new();
+    }
+    for (i, hit) in hits.iter().enumerate() {
+        println!("{} - {}", i + 1, describe_hit(hit));
+    }
+
+    loop {
+        println!();
+        print!("Numbers of what to add, separated by spaces (leave empty for none): ");
+        let user_input: String = read!("{}\n");
+        println!();
+
+        let chosen: Result<Vec<usize>, _> = user_input
+            .split_whitespace()
+            .map(|number| match number.parse::<usize>() {
+                Ok(n) if (1..=hits.len()).contains(&n) => Ok(n - 1),
+                _ => Err(number),
+            })
+            .collect();
+        match chosen {
+            Ok(chosen) => break chosen,
+            Err(number) => println!("Option {} is invalid, please try again", number),
+        }
+    }
+}
+
+pub fn no_buffer() {
+    println!(
+        "{} set \"buffer\" in .sortify/config.json to the name of a playlist, or pass --source",
+        "No buffer playlist:".red()
+    );
+}
+
+pub fn buffer_error(message: &str) {
+    println!("{} {}", "Failed to add to the buffer:".red(), message);
+}
+
+pub fn import_error(message: &str) {
+    println!("{} {}", "Failed to import:".red(), message);
+}
+
+pub fn export_error(message: &str) {
+    println!("{} {}", "Failed to export:".red(), message);
+}
+
+pub fn editor_error(message: &str) {
+    println!("{} {}", "Couldn't edit the tracks:".red(), message);
+    println!();
+}
+
+pub fn plan_errors(errors: &[String]) {
+    println!("{}", "There are problems with the decisions:".red());
+    for error in errors {
+        println!("    {}", error);
+    }
+    println!();
+}
+
+/// Lists the decisions made in one go and asks for the go ahead
+pub fn confirm_plan(
+    plan: &plan::Plan,
+    items: &[PlaylistTrack],
+    playlists: &[SimplifiedPlaylist],
+) -> bool {
+    println!();
+    for (index, action) in plan {
+        println!(
+            "{} {}",
+            track::summary(&items[*index].track),
+            format!("→ {}", describe_action(action, playlists)).dim()
+        );
+    }
+
+    let discarded = plan
+        .iter()
+        .filter(|(_, action)| matches!(action, services::TrackAction::Remove(_)))
+        .count();
+    let sorted = plan.len() - discarded;
+
+    println!();
+    println!(
+        "{} to sort, {} to remove without sorting, {} to skip",
+        sorted.to_string().green(),
+        discarded.to_string().red(),
+        items.len() - plan.len()
+    );
+    println!();
+    utils::confirmation("Apply these changes?".to_string())
+}
+
+pub fn script_error(message: &str) {
+    println!();
+    println!("{} {}", "Your script failed:".red(), message);
+}
+
+pub fn script_warning(message: &str) {
+    println!("{} {}", "Ignoring part of your script:".yellow(), message);
+}
+
+pub fn rules_error(message: &str) {
+    println!();
+    println!("{} {}", "Couldn't read your rules:".red(), message);
+    println!("Fix or remove the rules file and restart the program.");
+}
+
+fn describe_action(action: &services::TrackAction, playlists: &[SimplifiedPlaylist]) -> String {
+    match action {
+        services::TrackAction::Add(playlist_ids) => format!(
+            "add to {}",
+            playlist_ids
+                .iter()
+                .map(|id| playlists
+                    .iter()
+                    .find(|playlist| &playlist.id == id)
+                    .map_or(id.to_string(), |playlist| playlist.name.clone()))
+                .collect::<Vec<String>>()
+                .join(", ")
+        ),
+        services::TrackAction::Remove(_) => "remove from source without sorting".to_string(),
+        services::TrackAction::Skip => "skip".to_string(),
+    }
+}
+
+/// Lists what each rule would do to the tracks in the source playlist
+pub fn rules_report(
+    rules: &[Rule],
+    plan: &[Vec<usize>],
+    items: &[PlaylistTrack],
+    playlists: &[SimplifiedPlaylist],
+) {
+    println!();
+
+    for (rule, indices) in rules.iter().zip(plan) {
+        println!(
+            "{} {} {}",
+            rule.name.bold(),
+            format!("({})", describe_action(&rule.action, playlists)).italic(),
+            format!("- {} tracks", indices.len()).dim()
+        );
+        for index in indices {
+            println!("    {}", track::summary(&items[*index].track));
+        }
+    }
+
+    let decided: usize = plan.iter().map(|indices| indices.len()).sum();
+    println!();
+    println!(
+        "{}",
+        format!("{} tracks left to sort by hand", items.len() - decided).italic()
+    );
+    println!();
+}
+
+/// Shows what the rules will do and asks for the go ahead
+pub fn confirm_rules(
+    rules: &[Rule],
+    plan: &[Vec<usize>],
+    items: &[PlaylistTrack],
+    playlists: &[SimplifiedPlaylist],
+) -> bool {
+    rules_report(rules, plan, items, playlists);
+    utils::confirmation("Apply these rules?".to_string())
+}
+
+pub fn summary(summary: &Summary) {
+    println!();
+    println!("{}", "Session summary".bold());
+    println!();
+    println!("Sorted   {}", summary.sorted.green());
+    println!("Removed  {}", summary.removed.red());
+    println!("Skipped  {}", summary.skipped.yellow());
+    for count in &summary.playlists {
+        println!("  {} {}", count.tracks, count.name.dim());
+    }
+    if let Some(seconds) = summary.average_decision_seconds {
+        println!("{:.1}s per track on average", seconds);
+    }
+    println!(
+        "{} went from {} to {} tracks",
+        summary.source_playlist, summary.source_before, summary.source_after
+    );
+    if !summary.failures.is_empty() {
+        println!();
+        println!("{}", format!("Failed ({})", summary.failures.len()).red());
+        for failure in &summary.failures {
+            println!("  {} {}", failure.track, failure.message.dim());
+        }
+    }
+    println!();
+}
+
+pub fn summary_written(path: &Path) {
+    println!("Wrote the summary to {}", path.display());
+}
+
+pub fn summary_error(message: &str) {
+    println!("{} {}", "Failed to write the summary:".red(), message);
+}
+
 pub fn goodbye(source_playlist_name: Option<&String>) {
     let bye = "See you next time ♪♫♪";
 
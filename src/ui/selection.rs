@@ -0,0 +1,228 @@
+use super::chooser::Chooser;
+
+enum Change {
+    Toggle,
+    Set,
+    Unset,
+}
+
+/// Playlists a token stands for: a number, a range of numbers, @ and part of a name, or a hotkey
+fn playlists(token: &str, chooser: &Chooser) -> Option<Vec<usize>> {
+    if let Some(name) = token.strip_prefix('@') {
+        let (_, entry, _) = chooser.filter(name).into_iter().next()?;
+        return Some(vec![entry.index]);
+    }
+    if let Some((from, to)) = token.split_once('-') {
+        let (from, to) = (from.parse::<usize>().ok()?, to.parse::<usize>().ok()?);
+        return (from.min(to)..=from.max(to))
+            .map(|number| chooser.index_of(number))
+            .collect();
+    }
+    match token.parse::<usize>() {
+        Ok(number) => chooser.index_of(number).map(|index| vec![index]),
+        Err(_) => chooser.index_of_hotkey(token).map(|index| vec![index]),
+    }
+}
+
+/// Words that change every playlist at once
+pub const WORDS: [&str; 2] = ["clear", "invert"];
+
+/// Applies a line such as "2 5-7 +@chill -3 a" to what's checked. Numbers, ranges, names and
+/// hotkeys toggle playlists, or check them with + and uncheck them with -. "clear" and "invert"
+/// change all of them, "y" checks the suggested ones, "." the previous track's and a preset's
+/// key its playlists. Returns whether the line ended in "a" to confirm, or the tokens that made
+/// no sense, in which case nothing changes.
+pub fn apply(
+    input: &str,
+    selected: &mut [bool],
+    chooser: &Chooser,
+    suggested: &[usize],
+) -> Result<bool, Vec<String>> {
+    let mut changed = selected.to_vec();
+    let mut invalid = Vec::new();
+    let tokens: Vec<&str> = input.split_whitespace().collect();
+    let confirm = tokens.last() == Some(&"a");
+    let tokens = &tokens[..tokens.len() - usize::from(confirm)];
+
+    for token in tokens {
+        let check = |changed: &mut Vec<bool>, indices: &[usize]| {
+            for i in indices {
+                changed[*i] = true;
+            }
+        };
+        match *token {
+            "clear" => changed.fill(false),
+            "invert" => changed.iter_mut().for_each(|checked| *checked = !*checked),
+            "y" => check(&mut changed, suggested),
+            "." => check(&mut changed, &chooser.previous),
+            key if chooser.preset(key).is_some() => {
+                check(&mut changed, &chooser.preset(key).unwrap().indices)
+            }
+            _ => {
+                // "-3" unchecks, but "3-7" is a range
+                let (change, rest) = if let Some(rest) = token.strip_prefix('+') {
+                    (Change::Set, rest)
+                } else if let Some(rest) = token.strip_prefix('-') {
+                    (Change::Unset, rest)
+                } else {
+                    (Change::Toggle, *token)
+                };
+                let Some(indices) = playlists(rest, chooser) else {
+                    invalid.push(token.to_string());
+                    continue;
+                };
+                for i in indices {
+                    changed[i] = match change {
+                        Change::Toggle => !changed[i],
+                        Change::Set => true,
+                        Change::Unset => false,
+                    };
+                }
+            }
+        }
+    }
+
+    if !invalid.is_empty() {
+        return Err(invalid);
+    }
+    selected.copy_from_slice(&changed);
+    Ok(confirm)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::{
+        config::PlaylistConfig,
+        testing::playlist,
+        ui::chooser::{Layout, PresetEntry},
+    };
+
+    /// Chill (hotkey j), Deep House, Jazz classics and Workout, numbered 1 to 4, with a preset
+    /// on k for the first two and the last track having gone into Workout
+    fn chooser() -> Chooser {
+        let playlists = [
+            playlist("0000000000000000000001", "Chill"),
+            playlist("0000000000000000000002", "Deep House"),
+            playlist("0000000000000000000003", "Jazz classics"),
+            playlist("0000000000000000000004", "Workout"),
+        ];
+        let config = HashMap::from([(
+            String::from("0000000000000000000001"),
+            PlaylistConfig {
+                hotkey: Some('j'),
+                ..Default::default()
+            },
+        )]);
+        let (layout, _) = Layout::new(config, Vec::new());
+        let mut chooser = layout.arrange(&playlists, &HashMap::new());
+        chooser.presets = vec![PresetEntry {
+            key: 'k',
+            name: String::from("Evening"),
+            indices: vec![0, 1],
+        }];
+        chooser.previous = vec![3];
+        chooser
+    }
+
+    fn applied(input: &str, selected: [bool; 4]) -> Result<(bool, [bool; 4]), Vec<String>> {
+        let mut selected = selected;
+        let confirm = apply(input, &mut selected, &chooser(), &[2])?;
+        Ok((confirm, selected))
+    }
+
+    const NONE: [bool; 4] = [false; 4];
+
+    #[test]
+    fn numbers_toggle() {
+        assert_eq!(
+            applied("1 3", NONE),
+            Ok((false, [true, false, true, false]))
+        );
+        assert_eq!(
+            applied("1 3", [true, true, false, false]),
+            Ok((false, [false, true, true, false]))
+        );
+    }
+
+    #[test]
+    fn ranges_go_either_way() {
+        assert_eq!(applied("2-4", NONE), Ok((false, [false, true, true, true])));
+        assert_eq!(applied("4-2", NONE), Ok((false, [false, true, true, true])));
+    }
+
+    #[test]
+    fn plus_and_minus_check_and_uncheck() {
+        let selected = [true, true, false, false];
+        assert_eq!(
+            applied("+1 -2 +3 -4", selected),
+            Ok((false, [true, false, true, false]))
+        );
+        assert_eq!(applied("-1-2", selected), Ok((false, NONE)));
+    }
+
+    #[test]
+    fn names_and_hotkeys() {
+        assert_eq!(
+            applied("@jazz", NONE),
+            Ok((false, [false, false, true, false]))
+        );
+        assert_eq!(
+            applied("+@deep", NONE),
+            Ok((false, [false, true, false, false]))
+        );
+        assert_eq!(applied("j", NONE), Ok((false, [true, false, false, false])));
+    }
+
+    #[test]
+    fn names_end_at_spaces() {
+        assert_eq!(
+            applied("@deep house", NONE),
+            Err(vec![String::from("house")])
+        );
+    }
+
+    #[test]
+    fn words_for_all_playlists() {
+        let selected = [true, false, true, false];
+        assert_eq!(applied("clear", selected), Ok((false, NONE)));
+        assert_eq!(
+            applied("invert", selected),
+            Ok((false, [false, true, false, true]))
+        );
+        assert_eq!(applied("y", NONE), Ok((false, [false, false, true, false])));
+        assert_eq!(applied(".", NONE), Ok((false, [false, false, false, true])));
+        assert_eq!(applied("k", NONE), Ok((false, [true, true, false, false])));
+        assert_eq!(
+            applied("clear 4", selected),
+            Ok((false, [false, false, false, true]))
+        );
+    }
+
+    #[test]
+    fn a_at_the_end_confirms() {
+        assert_eq!(
+            applied("2 a", NONE),
+            Ok((true, [false, true, false, false]))
+        );
+        assert_eq!(applied("a", NONE), Ok((true, NONE)));
+        assert_eq!(applied("a 2", NONE), Err(vec![String::from("a")]));
+    }
+
+    #[test]
+    fn nonsense_changes_nothing() {
+        let mut selected = [true, false, false, false];
+        let result = apply("2 9 3-9 @zzz", &mut selected, &chooser(), &[]);
+        assert_eq!(
+            result,
+            Err(vec![
+                String::from("9"),
+                String::from("3-9"),
+                String::from("@zzz")
+            ])
+        );
+        assert_eq!(selected, [true, false, false, false]);
+    }
+}
@@ -1,11 +1,15 @@
 mod placeholder;
+pub(crate) mod visualiser;
 
 use rascii_art::{charsets, render_image_to, RenderOptions};
 use rspotify::model::FullTrack;
 use std::{collections::HashMap, error::Error};
 use yansi::Paint;
 
-use crate::ui::utils::{center_string, screen_width};
+use crate::{
+    analysis::TrackAnalysis,
+    ui::utils::{center_string, screen_width},
+};
 
 fn image(url: &String, cache: &mut HashMap<String, String>) -> Result<String, Box<dyn Error>> {
     // Check if the image is already cached
@@ -53,7 +57,35 @@ pub fn summary(track: &FullTrack) -> String {
     format!("{} - {}", track.name, artists(track))
 }
 
-pub fn display(track: &FullTrack, cache: &mut HashMap<String, String>) -> String {
+fn tempo_and_key(analysis: &TrackAnalysis) -> Option<String> {
+    let parts: Vec<String> = [
+        analysis.bpm.map(|bpm| format!("{:.0} BPM", bpm)),
+        analysis.key.map(|key| key.to_string()),
+    ]
+    .into_iter()
+    .flatten()
+    .collect();
+
+    if parts.is_empty() {
+        None
+    } else {
+        Some(parts.join(" · "))
+    }
+}
+
+/// The track as shown, in two parts so the visualiser row can go right under the title
+pub struct Shown {
+    /// Album art and the track's name
+    pub title: String,
+    /// Artists, and tempo and key if they're known
+    pub details: String,
+}
+
+pub fn display(
+    track: &FullTrack,
+    cache: &mut HashMap<String, String>,
+    analysis: Option<&TrackAnalysis>,
+) -> Shown {
     let image =
         image(&track.album.images[0].url, cache).unwrap_or(String::from(if screen_width() >= 48 {
             placeholder::IMAGE_48
@@ -63,5 +95,11 @@ pub fn display(track: &FullTrack, cache: &mut HashMap<String, String>) -> String
     let name = format!("{}", center_string(&track.name).bold());
     let artists = format!("{}", center_string(&artists(track)).italic());
 
-    format!("{}\n{}\n{}", image, name, artists)
+    Shown {
+        title: format!("{}\n{}", image, name),
+        details: match analysis.and_then(tempo_and_key) {
+            Some(details) => format!("{}\n{}", artists, center_string(&details).dim()),
+            None => artists,
+        },
+    }
 }
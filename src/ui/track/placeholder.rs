@@ -1,4 +1,4 @@
-pub static IMAGE_48: &'static str = r"
+pub static IMAGE_48: &str = r"
     ████████████████████████████████████████████████
     ████████████████████████████████████████████████
     ████████████████████████████████████████████████
@@ -24,7 +24,7 @@ pub static IMAGE_48: &'static str = r"
     ████████████████████████████████████████████████
     ████████████████████████████████████████████████";
 
-pub static IMAGE_32: &'static str = r"
+pub static IMAGE_32: &str = r"
     ██████████████▓▓▓▓▓█████████████
     ██████████████▓▓▓▓▓▓▓███████████
     ██████████████▓▓▓▓▓▓▓▓▓█████████
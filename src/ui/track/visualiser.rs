@@ -0,0 +1,135 @@
+use std::{
+    io::Write,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use yansi::Paint;
+
+use crate::{
+    audio::{Samples, Visualiser},
+    dsp,
+    ui::utils::screen_width,
+};
+
+static LEVELS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+const SPECTRUM_FRAME_LEN: usize = 2048;
+const WAVEFORM_SAMPLES_PER_COLUMN: usize = 64;
+const FRAME_INTERVAL: Duration = Duration::from_millis(80);
+
+fn level(value: f32) -> char {
+    let index = (value.clamp(0.0, 1.0) * (LEVELS.len() - 1) as f32).round() as usize;
+    LEVELS[index]
+}
+
+fn waveform(samples: &[f32], width: usize) -> String {
+    let needed = width * WAVEFORM_SAMPLES_PER_COLUMN;
+    let recent = &samples[samples.len().saturating_sub(needed)..];
+
+    recent
+        .chunks(WAVEFORM_SAMPLES_PER_COLUMN)
+        .map(|column| {
+            // root mean square gives a steadier picture of loudness than the peak
+            let rms = (column.iter().map(|s| s * s).sum::<f32>() / column.len() as f32).sqrt();
+            level(rms * 2.5)
+        })
+        .collect()
+}
+
+fn spectrum(samples: &[f32], sample_rate: u32, width: usize) -> String {
+    if samples.len() < SPECTRUM_FRAME_LEN || sample_rate == 0 {
+        return String::new();
+    }
+    let magnitudes = dsp::magnitude_spectrum(&samples[samples.len() - SPECTRUM_FRAME_LEN..]);
+
+    // bars are spaced logarithmically between 40Hz and 16kHz, like the ear hears them
+    let (low, high) = (40.0_f32, 16_000.0_f32.min(sample_rate as f32 / 2.0));
+    let band_edge = |bar: usize| low * (high / low).powf(bar as f32 / width as f32);
+
+    (0..width)
+        .map(|bar| {
+            let energy = magnitudes
+                .iter()
+                .enumerate()
+                .filter(|(bin, _)| {
+                    let freq = dsp::bin_frequency(*bin, SPECTRUM_FRAME_LEN, sample_rate);
+                    freq >= band_edge(bar) && freq < band_edge(bar + 1)
+                })
+                .map(|(_, magnitude)| *magnitude)
+                .fold(0.0, f32::max);
+
+            // map -60dB..0dB relative to a full scale sine onto the bar height
+            let db = 20.0
+                * (energy / (SPECTRUM_FRAME_LEN as f32 / 4.0))
+                    .max(1e-6)
+                    .log10();
+            level((db + 60.0) / 60.0)
+        })
+        .collect()
+}
+
+/// Renders the latest samples as a single screen-wide row, or None if the visualiser is off
+pub fn render(mode: Visualiser, samples: &Samples) -> Option<String> {
+    let (recent, sample_rate): (Vec<f32>, u32) = {
+        let window = samples.lock().ok()?;
+        (window.samples.iter().copied().collect(), window.sample_rate)
+    };
+    let width = screen_width();
+
+    let row = match mode {
+        Visualiser::Off => return None,
+        Visualiser::Waveform => waveform(&recent, width),
+        Visualiser::Spectrum => spectrum(&recent, sample_rate, width),
+    };
+
+    // keep the row's width constant while there is not enough audio yet
+    let padding = " ".repeat(width.saturating_sub(row.chars().count()));
+    Some(format!("{}", format!("{}{}", row, padding).green()))
+}
+
+/// Keeps redrawing the visualiser row in place until dropped
+pub struct Animation {
+    running: Arc<AtomicBool>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl Animation {
+    /// Starts redrawing the row `rows_up` rows of the terminal above the cursor, which must stay
+    /// where it is (i.e. waiting for user input) while the animation runs
+    pub fn start(mode: Visualiser, samples: Samples, rows_up: usize) -> Animation {
+        let running = Arc::new(AtomicBool::new(true));
+
+        let handle = {
+            let running = running.clone();
+            thread::spawn(move || {
+                while running.load(Ordering::Relaxed) {
+                    if let Some(row) = render(mode, &samples) {
+                        // save cursor, move up to the row, redraw it, restore cursor
+                        let mut stdout = std::io::stdout().lock();
+                        _ = write!(stdout, "\x1b7\x1b[{}A\r{}\x1b8", rows_up, row);
+                        _ = stdout.flush();
+                    }
+                    thread::sleep(FRAME_INTERVAL);
+                }
+            })
+        };
+
+        Animation {
+            running,
+            handle: Some(handle),
+        }
+    }
+}
+
+impl Drop for Animation {
+    fn drop(&mut self) {
+        self.running.store(false, Ordering::Relaxed);
+        if let Some(handle) = self.handle.take() {
+            _ = handle.join();
+        }
+    }
+}
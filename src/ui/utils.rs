@@ -1,7 +1,10 @@
-use std::cmp::min;
+use std::{cmp::min, fs, path::Path, process::Command};
 
 use terminal_size::{terminal_size, Height, Width};
 use text_io::read;
+use yansi::Paint;
+
+use crate::fuzzy::{self, Candidate, Hit};
 
 pub fn confirmation(prompt: String) -> bool {
     loop {
@@ -25,23 +28,89 @@ pub fn confirmation(prompt: String) -> bool {
     }
 }
 
-pub fn choose_one(options: &Vec<String>) -> usize {
-    for i in 0..options.len() {
-        println!("{} - {}", i + 1, options[i]);
+/// Opens a file with the given contents in the user's editor, and reads it back once it closes
+pub fn edit_in_editor(path: &Path, text: &str) -> Result<String, String> {
+    let editor = std::env::var("VISUAL")
+        .or_else(|_| std::env::var("EDITOR"))
+        .unwrap_or_else(|_| String::from(if cfg!(windows) { "notepad" } else { "vi" }));
+
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+    }
+    fs::write(path, text).map_err(|e| e.to_string())?;
+
+    // editors are often configured with arguments, e.g. "code --wait"
+    let mut words = editor.split_whitespace();
+    let program = words.next().ok_or("$EDITOR is empty")?;
+    let status = Command::new(program)
+        .args(words)
+        .arg(path)
+        .status()
+        .map_err(|e| format!("Couldn't open {}: {}", editor, e))?;
+    if !status.success() {
+        return Err(format!("{} exited with {}", editor, status));
+    }
+
+    fs::read_to_string(path).map_err(|e| e.to_string())
+}
+
+/// A name as filtered, with the matched characters highlighted, and the description if that's
+/// what matched
+pub fn filtered_name(candidate: &Candidate, hit: &Hit) -> String {
+    let mark = |run: &str| run.bold().underline().to_string();
+    let mut line = fuzzy::highlight(candidate.name, &hit.name, mark);
+    if let (Some(description), Some(positions)) = (candidate.description, &hit.description) {
+        line += &format!(" — {}", fuzzy::highlight(description, positions, mark));
+    }
+    line
+}
+
+/// Picks an option by its number, or by typing part of its name or description and then Enter
+/// for the best match
+pub fn choose_one(options: &[Candidate]) -> usize {
+    for (i, option) in options.iter().enumerate() {
+        println!("{} - {}", i + 1, option.name);
     }
 
-    let mut user_input: usize;
+    let mut best: Option<usize> = None;
     loop {
         println!();
-        print!("Choice: ");
-        user_input = read!();
+        print!("Choice (a number, or text to search for): ");
+        let user_input: String = read!("{}\n");
+        let user_input = user_input.trim();
 
-        if user_input >= 1 && user_input <= options.len() {
-            break;
+        if let Ok(number) = user_input.parse::<usize>() {
+            if number >= 1 && number <= options.len() {
+                break number - 1;
+            }
+            println!("Option {} is invalid, please try again", number);
+            continue;
+        }
+        if user_input.is_empty() {
+            match best {
+                Some(best) => break best,
+                None => continue,
+            }
+        }
+
+        let hits = fuzzy::filter(user_input, options);
+        println!();
+        if hits.is_empty() {
+            println!("Nothing matches {}", user_input);
+        }
+        for hit in &hits {
+            println!(
+                "{} - {}",
+                hit.index + 1,
+                filtered_name(&options[hit.index], hit)
+            );
+        }
+        best = hits.first().map(|hit| hit.index);
+        if best.is_some() {
+            println!();
+            println!("{}", "Press Enter to pick the first one".italic().dim());
         }
-        println!("Option {} is invalid, please try again", user_input);
     }
-    user_input - 1
 }
 
 pub fn screen_width() -> usize {
@@ -52,25 +121,56 @@ pub fn screen_width() -> usize {
     }
 }
 
-pub fn center_string(str: &String) -> String {
+pub fn screen_height() -> Option<usize> {
+    terminal_size().map(|(Width(_), Height(h))| h.into())
+}
+
+pub fn screen_columns() -> Option<usize> {
+    terminal_size().map(|(Width(w), Height(_))| w.into())
+}
+
+/// How many characters of the line show on screen, leaving out escape sequences for colours and
+/// styles
+fn visible_len(line: &str) -> usize {
+    let mut len = 0;
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        if c == '\x1b' {
+            // a control sequence ends with its first letter, e.g. "\x1b[1;31m"
+            chars.find(|c| c.is_ascii_alphabetic());
+        } else {
+            len += 1;
+        }
+    }
+    len
+}
+
+/// How many rows the text takes up on a terminal that many columns wide, with long lines wrapping
+pub fn screen_rows(text: &str, columns: usize) -> usize {
+    text.split('\n')
+        .map(|line| visible_len(line).div_ceil(columns.max(1)).max(1))
+        .sum()
+}
+
+pub fn center_string(str: &str) -> String {
     let pad_size = screen_width().saturating_sub(str.chars().count()) / 2;
     let pad = " ".repeat(pad_size);
 
     format!("{}{}{}", pad, str, pad)
 }
 
-fn pad_string_right(str: &String, n: usize) -> String {
+fn pad_string_right(str: &str, n: usize) -> String {
     let pad_size = n.saturating_sub(str.chars().count());
     let pad = " ".repeat(pad_size);
 
     format!("{}{}", str, pad)
 }
 
-fn clip_string(str: &String, n: usize) -> String {
+fn clip_string(str: &str, n: usize) -> String {
     format!("{}...", str.chars().take(n - 3).collect::<String>())
 }
 
-pub fn string_to_half_screen(str: &String) -> String {
+pub fn string_to_half_screen(str: &str) -> String {
     let half_screen_width = screen_width() / 2;
     if str.chars().count() > half_screen_width {
         clip_string(str, half_screen_width)
@@ -79,7 +179,7 @@ pub fn string_to_half_screen(str: &String) -> String {
     }
 }
 
-pub fn wrap_text_to_screen(text: &String) -> String {
+pub fn wrap_text_to_screen(text: &str) -> String {
     let max_len = screen_width();
     let mut result = String::new();
     let mut line_len = 0;
@@ -105,3 +205,26 @@ pub fn wrap_text_to_screen(text: &String) -> String {
 
     result
 }
+
+#[cfg(test)]
+mod tests {
+    use yansi::Paint;
+
+    use super::*;
+
+    #[test]
+    fn long_lines_take_up_more_rows() {
+        assert_eq!(screen_rows("one\ntwo", 10), 2);
+        assert_eq!(screen_rows(&"x".repeat(10), 10), 1);
+        assert_eq!(screen_rows(&"x".repeat(25), 10), 3);
+        assert_eq!(screen_rows("\n\nChoice: ", 10), 3);
+    }
+
+    #[test]
+    fn colours_take_up_no_room() {
+        let styled = format!("{}", "x".repeat(10).red().bold());
+
+        assert!(styled.len() > 10);
+        assert_eq!(screen_rows(&styled, 10), 1);
+    }
+}